@@ -0,0 +1,90 @@
+//! ## 近似成员查询结构的性能对比
+//!
+//! - 无需 nightly：`cargo bench --bench bloomfilter`
+//! - 相同目标误判率下，对比插入/查询耗时、内存占用与实测误判率
+//! - partial 的默认 SHA 摘要模式与 double hashing 模式(SipHash、xxHash64)
+//!
+
+use bc_algo::draft_for_exercise::bloomfilter::{
+    cuckoo::CuckooFilter,
+    fasthash::{SIPHASH, XXHASH64},
+    optimal_params,
+    partial::ParBloomFilter,
+};
+use std::time::{Duration, Instant};
+
+const N: usize = 10_0000;
+
+fn keys(range: std::ops::Range<usize>) -> Vec<[u8; 8]> {
+    range.map(|i| (i as u64).to_le_bytes()).collect()
+}
+
+fn report(name: &str, bytes: usize, insert: Duration, lookup: Duration, fp_cnt: usize) {
+    println!(
+        "{:<12} {:>10} bytes {:>8.2} bits/item  insert {:>8.1} ns/op  lookup {:>8.1} ns/op  fp {:.5}",
+        name,
+        bytes,
+        (bytes * 8) as f64 / N as f64,
+        insert.as_nanos() as f64 / N as f64,
+        lookup.as_nanos() as f64 / (2 * N) as f64,
+        fp_cnt as f64 / N as f64,
+    );
+}
+
+fn cuckoo(fp_rate: f64, members: &[[u8; 8]], others: &[[u8; 8]]) {
+    let mut cf = CuckooFilter::with_capacity(N, fp_rate);
+
+    let now = Instant::now();
+    members.iter().for_each(|k| assert!(cf.insert(k)));
+    let insert = now.elapsed();
+
+    let now = Instant::now();
+    members.iter().for_each(|k| assert!(cf.lookup(k)));
+    let fp_cnt = others.iter().filter(|k| cf.lookup(*k)).count();
+    let lookup = now.elapsed();
+
+    report("cuckoo", cf.size_in_bytes(), insert, lookup, fp_cnt);
+}
+
+fn partial(name: &str, mut bf: ParBloomFilter, members: &[[u8; 8]], others: &[[u8; 8]]) {
+    let now = Instant::now();
+    members.iter().for_each(|k| {
+        bf.set(k);
+    });
+    let insert = now.elapsed();
+
+    let now = Instant::now();
+    members.iter().for_each(|k| assert!(bf.find(k).is_some()));
+    let fp_cnt = others.iter().filter(|k| bf.find(*k).is_some()).count();
+    let lookup = now.elapsed();
+
+    report(name, bf.m() / 8, insert, lookup, fp_cnt);
+}
+
+fn main() {
+    let members = keys(0..N);
+    let others = keys(N..2 * N);
+    for fp_rate in &[0.01, 0.001, 0.0001] {
+        println!("==== n = {}, target fp rate = {}", N, fp_rate);
+        let (m, k) = optimal_params(N, *fp_rate);
+        cuckoo(*fp_rate, &members, &others);
+        partial(
+            "partial",
+            ParBloomFilter::with_params(m, k),
+            &members,
+            &others,
+        );
+        partial(
+            "partial+sip",
+            ParBloomFilter::with_double_hashing(m, k, SIPHASH),
+            &members,
+            &others,
+        );
+        partial(
+            "partial+xxh",
+            ParBloomFilter::with_double_hashing(m, k, XXHASH64),
+            &members,
+            &others,
+        );
+    }
+}
@@ -0,0 +1,336 @@
+//! ## 256 bit 无符号整数与 compact 编码
+//!
+//! #### 算法说明
+//! - 区块哈希按小端解释为 256 bit 无符号整数，与 target 比较大小；
+//! - 区块头中的 nBits 是 target 的 compact 编码，类似于以 256 为底的浮点数：
+//!   最高字节为字节数 size，低 23 bit 为尾数 mantissa，target = mantissa * 256^(size - 3)；
+//! - 第 24 bit 为符号位，置位且尾数非零即为负数；size 过大、超出 256 bit 即为溢出，两者都不是合法的 target；
+//! - 编码时若尾数的最高位为 1，则右移一个字节、size 加 1，以免被解释为负数，因此编码不改变数值，
+//!   但 decode(encode(x)) 只保留 x 最高的 3 个字节。
+//!
+//! #### 实现属性
+//! - <font color=Green>√</font> 多线程安全
+//! - <font color=Green>√</font> 无 unsafe 代码
+//!
+
+use std::ops::{Div, Not, Shl, Shr};
+
+///256 bit 无符号整数，高位在前，因此可以直接按字典序比较大小
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct U256(pub [u64; 4]);
+
+impl U256 {
+    pub const ZERO: U256 = U256([0; 4]);
+    pub const MAX: U256 = U256([u64::MAX; 4]);
+
+    pub fn from_u64(n: u64) -> U256 {
+        U256([0, 0, 0, n])
+    }
+
+    ///#### 按小端解释 32 字节，即区块哈希的内部字节序
+    pub fn from_le_bytes(bytes: &[u8; 32]) -> U256 {
+        let mut res = [0; 4];
+        for (i, limb) in res.iter_mut().enumerate() {
+            let mut buf = [0; 8];
+            buf.copy_from_slice(&bytes[(3 - i) * 8..(4 - i) * 8]);
+            *limb = u64::from_le_bytes(buf);
+        }
+        U256(res)
+    }
+
+    pub fn to_le_bytes(&self) -> [u8; 32] {
+        let mut res = [0; 32];
+        for (i, limb) in self.0.iter().enumerate() {
+            res[(3 - i) * 8..(4 - i) * 8].copy_from_slice(&limb.to_le_bytes());
+        }
+        res
+    }
+
+    #[inline(always)]
+    pub fn is_zero(&self) -> bool {
+        U256::ZERO == *self
+    }
+
+    #[inline(always)]
+    pub fn low_u64(&self) -> u64 {
+        self.0[3]
+    }
+
+    ///- #: 有效 bit 数，0 的有效 bit 数为 0
+    pub fn bits(&self) -> usize {
+        match self.0.iter().position(|x| 0 != *x) {
+            Some(i) => (4 - i) * 64 - self.0[i].leading_zeros() as usize,
+            None => 0,
+        }
+    }
+
+    //- #: 左移 n bit，移出的高位丢弃
+    fn shift_left(&self, n: usize) -> U256 {
+        let mut res = [0; 4];
+        let (limbs, bits) = (n / 64, n % 64);
+        for (i, r) in res
+            .iter_mut()
+            .enumerate()
+            .take(4usize.saturating_sub(limbs))
+        {
+            *r = self.0[i + limbs] << bits;
+            if 0 < bits && i + limbs + 1 < 4 {
+                *r |= self.0[i + limbs + 1] >> (64 - bits);
+            }
+        }
+        U256(res)
+    }
+
+    //- #: 右移 n bit
+    fn shift_right(&self, n: usize) -> U256 {
+        let mut res = [0; 4];
+        let (limbs, bits) = (n / 64, n % 64);
+        for (i, r) in res.iter_mut().enumerate().skip(limbs) {
+            *r = self.0[i - limbs] >> bits;
+            if 0 < bits && limbs < i {
+                *r |= self.0[i - limbs - 1] << (64 - bits);
+            }
+        }
+        U256(res)
+    }
+
+    ///- #: 加法，溢出时返回 None
+    pub fn checked_add(&self, other: &U256) -> Option<U256> {
+        let mut res = [0; 4];
+        let mut carry = false;
+        for i in (0..4).rev() {
+            let (x, c1) = self.0[i].overflowing_add(other.0[i]);
+            let (x, c2) = x.overflowing_add(carry as u64);
+            res[i] = x;
+            carry = c1 || c2;
+        }
+        if carry {
+            None
+        } else {
+            Some(U256(res))
+        }
+    }
+
+    ///- #: 乘以 n，溢出时返回 None
+    pub fn checked_mul_u64(&self, n: u64) -> Option<U256> {
+        let mut res = [0; 4];
+        let mut carry = 0;
+        for i in (0..4).rev() {
+            let x = u128::from(self.0[i]) * u128::from(n) + carry;
+            res[i] = x as u64;
+            carry = x >> 64;
+        }
+        if 0 == carry {
+            Some(U256(res))
+        } else {
+            None
+        }
+    }
+
+    ///- #: 除以 n 的商，n 须大于 0
+    pub fn div_u64(&self, n: u64) -> U256 {
+        assert!(0 < n, "division by zero");
+        let mut res = [0; 4];
+        let mut rem = 0u128;
+        for (r, limb) in res.iter_mut().zip(self.0.iter()) {
+            let x = (rem << 64) | u128::from(*limb);
+            *r = (x / u128::from(n)) as u64;
+            rem = x % u128::from(n);
+        }
+        U256(res)
+    }
+
+    //- #: 除法的商，除数须大于 0，逐 bit 的长除法
+    fn long_div(&self, other: &U256) -> U256 {
+        assert!(!other.is_zero(), "division by zero");
+        let mut res = U256::ZERO;
+        let mut rem = *self;
+        let bits = self.bits();
+        if bits < other.bits() {
+            return res;
+        }
+        let shift = bits - other.bits();
+        let mut d = *other << shift;
+        for i in (0..=shift).rev() {
+            if d <= rem {
+                rem = rem.wrapping_sub(&d);
+                res.0[3 - i / 64] |= 1 << (i % 64);
+            }
+            d = d >> 1;
+        }
+        res
+    }
+
+    //- #: self - other，调用方保证 other <= self
+    fn wrapping_sub(&self, other: &U256) -> U256 {
+        let mut res = [0; 4];
+        let mut borrow = false;
+        for i in (0..4).rev() {
+            let (x, b1) = self.0[i].overflowing_sub(other.0[i]);
+            let (x, b2) = x.overflowing_sub(borrow as u64);
+            res[i] = x;
+            borrow = b1 || b2;
+        }
+        U256(res)
+    }
+
+    ///- #: 近似的浮点值
+    pub fn to_f64(&self) -> f64 {
+        self.0
+            .iter()
+            .fold(0.0, |acc, x| acc * 18_446_744_073_709_551_616.0 + *x as f64)
+    }
+
+    ///#### 由 compact 编码解出 target
+    ///- #: 负数或溢出时返回 None
+    pub fn from_compact(compact: u32) -> Option<U256> {
+        let size = (compact >> 24) as usize;
+        let mut word = compact & 0x007f_ffff;
+        let res = if size <= 3 {
+            word >>= 8 * (3 - size);
+            U256::from_u64(u64::from(word))
+        } else {
+            U256::from_u64(u64::from(word)) << (8 * (size - 3))
+        };
+        let negative = 0 != word && 0 != compact & 0x0080_0000;
+        let overflow =
+            0 != word && (34 < size || (0xff < word && 33 < size) || (0xffff < word && 32 < size));
+        if negative || overflow {
+            None
+        } else {
+            Some(res)
+        }
+    }
+
+    ///#### 编码为 compact 形式，只保留最高的 3 个字节
+    pub fn to_compact(&self) -> u32 {
+        let mut size = self.bits().div_ceil(8);
+        let mut compact = if size <= 3 {
+            (self.low_u64() << (8 * (3 - size))) as u32
+        } else {
+            (*self >> (8 * (size - 3))).low_u64() as u32
+        };
+        if 0 != compact & 0x0080_0000 {
+            compact >>= 8;
+            size += 1;
+        }
+        compact | (size as u32) << 24
+    }
+}
+
+impl Shl<usize> for U256 {
+    type Output = U256;
+
+    fn shl(self, n: usize) -> U256 {
+        self.shift_left(n)
+    }
+}
+
+impl Shr<usize> for U256 {
+    type Output = U256;
+
+    fn shr(self, n: usize) -> U256 {
+        self.shift_right(n)
+    }
+}
+
+impl Div for U256 {
+    type Output = U256;
+
+    fn div(self, other: U256) -> U256 {
+        self.long_div(&other)
+    }
+}
+
+impl Not for U256 {
+    type Output = U256;
+
+    fn not(self) -> U256 {
+        let mut res = self.0;
+        res.iter_mut().for_each(|x| *x = !*x);
+        U256(res)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn arith() {
+        let a = U256([0, 1, 0, u64::MAX]);
+        assert_eq!(
+            U256([0, 1, 1, 0]),
+            a.checked_add(&U256::from_u64(1)).unwrap()
+        );
+        assert_eq!(None, U256::MAX.checked_add(&U256::from_u64(1)));
+        assert_eq!(U256([0, 2, 1, u64::MAX - 1]), a.checked_mul_u64(2).unwrap());
+        assert_eq!(None, U256::MAX.checked_mul_u64(2));
+        assert_eq!(a, a.checked_mul_u64(12345).unwrap().div_u64(12345));
+        assert_eq!(a, a.checked_mul_u64(12345).unwrap() / U256::from_u64(12345));
+        assert_eq!(U256::from_u64(1 << 63), a / U256([0, 0, 2, 0]));
+        assert_eq!(U256::ZERO, a / U256::MAX);
+        assert_eq!(U256::from_u64(1), U256::MAX / U256::MAX);
+        assert_eq!(129, a.bits());
+        assert_eq!(0, U256::ZERO.bits());
+        assert_eq!(U256([0, 0, 1, u64::MAX << 1]), a << 128 >> 127);
+        assert_eq!(a, a << 64 >> 64);
+        assert_eq!(U256([u64::MAX, u64::MAX - 1, u64::MAX, 0]), !a);
+        assert!(U256::from_u64(2) < a && a < U256([1, 0, 0, 0]));
+
+        let mut bytes = [0; 32];
+        bytes[0] = 1;
+        bytes[31] = 0x80;
+        assert_eq!(U256([1 << 63, 0, 0, 1]), U256::from_le_bytes(&bytes));
+        assert_eq!(bytes, U256::from_le_bytes(&bytes).to_le_bytes());
+    }
+
+    //vectors from bitcoin core's arith_uint256 tests
+    #[test]
+    fn compact() {
+        for bits in [
+            0,
+            0x0012_3456,
+            0x0100_3456,
+            0x0200_0056,
+            0x0300_0000,
+            0x0400_0000,
+            0x0092_3456,
+            0x0180_3456,
+            0x0280_0056,
+            0x0380_0000,
+            0x0480_0000,
+        ] {
+            assert_eq!(Some(U256::ZERO), U256::from_compact(bits));
+            assert_eq!(0, U256::from_compact(bits).unwrap().to_compact());
+        }
+
+        let cases = [
+            (0x0112_3456, U256::from_u64(0x12), 0x0112_0000),
+            (0x0212_3456, U256::from_u64(0x1234), 0x0212_3400),
+            (0x0312_3456, U256::from_u64(0x12_3456), 0x0312_3456),
+            (0x0412_3456, U256::from_u64(0x1234_5600), 0x0412_3456),
+            (0x0500_9234, U256::from_u64(0x9234_0000), 0x0500_9234),
+            (
+                0x2012_3456,
+                U256::from_u64(0x12_3456) << (8 * 29),
+                0x2012_3456,
+            ),
+        ];
+        for (bits, target, compact) in cases.iter() {
+            assert_eq!(Some(*target), U256::from_compact(*bits));
+            assert_eq!(*compact, target.to_compact());
+        }
+        assert_eq!(
+            U256([0x0000_0000_ffff_0000, 0, 0, 0]),
+            U256::from_compact(0x1d00_ffff).unwrap()
+        );
+
+        //negative or overflowing
+        assert_eq!(None, U256::from_compact(0x0492_3456));
+        assert_eq!(None, U256::from_compact(0x01fe_dcba));
+        assert_eq!(None, U256::from_compact(0xff12_3456));
+        assert_eq!(None, U256::from_compact(0x2201_0000));
+        assert!(U256::from_compact(0x2200_0001).is_some());
+    }
+}
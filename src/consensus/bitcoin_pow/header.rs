@@ -0,0 +1,168 @@
+//! ## 比特币区块头
+//!
+//! #### 算法说明
+//! - 区块头共 80 字节：version(i32) | prev_hash(32) | merkle_root(32) | time(u32) | nBits(u32) | nonce(u32)，
+//!   整数一律小端，哈希为内部字节序(即直接由 double-SHA256 得到的顺序，显示时按字节逆序)；
+//! - 区块哈希为区块头的 double-SHA256，按小端解释为 256 bit 整数之后须不大于 nBits 所表示的 target；
+//! - target 须为正数，且不大于网络参数中的 pow_limit，否则区块无效。
+//!
+//! #### 实现属性
+//! - <font color=Green>√</font> 多线程安全
+//! - <font color=Green>√</font> 无 unsafe 代码
+//!
+
+use super::arith::U256;
+use super::retarget::Params;
+use ring::digest::{digest, SHA256};
+
+///区块头的字节数
+pub const HEADER_LEN: usize = 80;
+
+///比特币区块头
+///- @version: 版本号
+///- @prev_hash: 前一个区块的哈希
+///- @merkle_root: 交易的 merkle root
+///- @time: 时间戳，单位秒
+///- @bits: target 的 compact 编码
+///- @nonce: 挖矿时遍历的随机数
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BlockHeader {
+    pub version: i32,
+    pub prev_hash: [u8; 32],
+    pub merkle_root: [u8; 32],
+    pub time: u32,
+    pub bits: u32,
+    pub nonce: u32,
+}
+
+impl BlockHeader {
+    ///#### 序列化为 80 字节
+    pub fn to_bytes(&self) -> [u8; HEADER_LEN] {
+        let mut res = [0; HEADER_LEN];
+        res[..4].copy_from_slice(&self.version.to_le_bytes());
+        res[4..36].copy_from_slice(&self.prev_hash);
+        res[36..68].copy_from_slice(&self.merkle_root);
+        res[68..72].copy_from_slice(&self.time.to_le_bytes());
+        res[72..76].copy_from_slice(&self.bits.to_le_bytes());
+        res[76..].copy_from_slice(&self.nonce.to_le_bytes());
+        res
+    }
+
+    ///#### 从 80 字节反序列化
+    ///- #: 长度不是 80 字节时返回 None
+    pub fn from_bytes(bytes: &[u8]) -> Option<BlockHeader> {
+        if HEADER_LEN != bytes.len() {
+            return None;
+        }
+        let u32_at = |pos: usize| {
+            let mut buf = [0; 4];
+            buf.copy_from_slice(&bytes[pos..pos + 4]);
+            u32::from_le_bytes(buf)
+        };
+        let mut res = BlockHeader {
+            version: u32_at(0) as i32,
+            time: u32_at(68),
+            bits: u32_at(72),
+            nonce: u32_at(76),
+            ..Default::default()
+        };
+        res.prev_hash.copy_from_slice(&bytes[4..36]);
+        res.merkle_root.copy_from_slice(&bytes[36..68]);
+        Some(res)
+    }
+
+    ///- #: 区块哈希，内部字节序
+    pub fn hash(&self) -> [u8; 32] {
+        double_sha256(&self.to_bytes())
+    }
+
+    ///#### 验证区块哈希满足区块头自身声明的 nBits
+    pub fn check_pow(&self, params: &Params) -> bool {
+        check_pow(&self.hash(), self.bits, params)
+    }
+}
+
+///- #: SHA256(SHA256(data))
+pub fn double_sha256(data: &[u8]) -> [u8; 32] {
+    let mut res = [0; 32];
+    res.copy_from_slice(digest(&SHA256, digest(&SHA256, data).as_ref()).as_ref());
+    res
+}
+
+///#### 验证哈希不大于 nBits 所表示的 target
+///- #: target 为负数、零、溢出或大于 pow_limit 时同样返回 false
+///- @hash[in]: 区块哈希，内部字节序
+///- @bits[in]: target 的 compact 编码
+///- @params[in]: 网络参数
+pub fn check_pow(hash: &[u8; 32], bits: u32, params: &Params) -> bool {
+    match U256::from_compact(bits) {
+        Some(target) if !target.is_zero() && target <= params.pow_limit => {
+            U256::from_le_bytes(hash) <= target
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testutil::{hash, hex};
+
+    //mainnet blocks 0 and 1
+    const GENESIS: &str = "0100000000000000000000000000000000000000000000000000000000000000000000003ba3edfd7a7b12b27ac72c3e67768f617fc81bc3888a51323a9fb8aa4b1e5e4a29ab5f49ffff001d1dac2b7c";
+    const BLOCK1: &str = "010000006fe28c0ab6f1b372c1a6a246ae63f74f931e8365e15a089c68d6190000000000982051fd1e4ba744bbbe680e1fee14677ba1a3c3540bf7b1cdb606e857233e0e61bc6649ffff001d01e36299";
+
+    #[test]
+    fn header() {
+        let params = Params::MAINNET;
+        let genesis = BlockHeader::from_bytes(&hex(GENESIS)).unwrap();
+        assert_eq!(1, genesis.version);
+        assert_eq!([0; 32], genesis.prev_hash);
+        assert_eq!(
+            hash("4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b"),
+            genesis.merkle_root
+        );
+        assert_eq!(1_231_006_505, genesis.time);
+        assert_eq!(0x1d00_ffff, genesis.bits);
+        assert_eq!(2_083_236_893, genesis.nonce);
+        assert_eq!(hex(GENESIS), genesis.to_bytes().to_vec());
+        assert_eq!(
+            hash("000000000019d6689c085ae165831e934ff763ae46a2a6c172b3f1b60a8ce26f"),
+            genesis.hash()
+        );
+        assert!(genesis.check_pow(&params));
+
+        let block1 = BlockHeader::from_bytes(&hex(BLOCK1)).unwrap();
+        assert_eq!(genesis.hash(), block1.prev_hash);
+        assert_eq!(
+            hash("00000000839a8e6886ab5951d76f411475428afc90947ee320161bbf18eb6048"),
+            block1.hash()
+        );
+        assert!(block1.check_pow(&params));
+
+        //a different nonce almost surely misses the target
+        let mut bad = block1;
+        bad.nonce += 1;
+        assert!(!bad.check_pow(&params));
+        assert!(BlockHeader::from_bytes(&hex(BLOCK1)[..79]).is_none());
+    }
+
+    #[test]
+    fn pow() {
+        let params = Params::MAINNET;
+        let zero = [0; 32];
+        assert!(check_pow(&zero, 0x1d00_ffff, &params));
+        assert!(!check_pow(&zero, 0, &params));
+        assert!(!check_pow(&zero, 0x0492_3456, &params));
+        assert!(!check_pow(&zero, 0xff12_3456, &params));
+        //easier than the pow limit is only valid on regtest
+        assert!(!check_pow(&zero, 0x207f_ffff, &params));
+        assert!(check_pow(&zero, 0x207f_ffff, &Params::REGTEST));
+
+        //the hash equal to the target passes, one above fails
+        let target = U256::from_compact(0x1d00_ffff).unwrap();
+        assert!(check_pow(&target.to_le_bytes(), 0x1d00_ffff, &params));
+        let above = target.checked_add(&U256::from_u64(1)).unwrap();
+        assert!(!check_pow(&above.to_le_bytes(), 0x1d00_ffff, &params));
+    }
+}
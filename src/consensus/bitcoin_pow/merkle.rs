@@ -0,0 +1,192 @@
+//! ## 比特币交易 merkle root
+//!
+//! #### 算法说明
+//! - 叶子为各交易的 txid(内部字节序)，父节点为左右子节点串联之后的 double-SHA256；
+//! - 某一层的节点数为奇数时，复制该层最后一个节点补齐，而不是补空哈希，只有一个节点时即为 root；
+//! - CVE-2012-2459：由于复制补齐，交易列表 [.., x, y] 与 [.., x, y, x, y] 等末尾重复的列表得到相同的 root，
+//!   攻击者可据此构造与合法区块同哈希的无效区块，使节点将合法区块标记为无效；
+//!   计算时若同一层中成对的两个节点相同，即判定交易列表被篡改(mutated)，此类区块应直接拒绝而不是标记为无效；
+//! - merkle branch：从叶子到 root 路径上每一层的兄弟节点，配合叶子的序号即可由 txid 重算 root，
+//!   SPV 节点只需区块头与 branch 就能确认交易被打包进了区块。
+//!
+//! #### 实现属性
+//! - <font color=Green>√</font> 多线程安全
+//! - <font color=Green>√</font> 无 unsafe 代码
+//!
+
+use super::header::double_sha256;
+
+//- #: double-SHA256(left | right)
+fn hash_pair(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    let mut buf = [0; 64];
+    buf[..32].copy_from_slice(left);
+    buf[32..].copy_from_slice(right);
+    double_sha256(&buf)
+}
+
+//- #: 上一层，以及本层是否存在成对的相同节点
+fn next_layer(layer: &[[u8; 32]]) -> (Vec<[u8; 32]>, bool) {
+    let mut mutated = false;
+    let res = layer
+        .chunks(2)
+        .map(|pair| match pair {
+            [left, right] => {
+                mutated |= left == right;
+                hash_pair(left, right)
+            }
+            [last] => hash_pair(last, last),
+            _ => unreachable!(),
+        })
+        .collect();
+    (res, mutated)
+}
+
+///#### 计算 merkle root，并检测 CVE-2012-2459 所述的篡改
+///- #: (root, 是否被篡改)，交易列表为空时 root 为全零
+///- @txids[in]: 按区块中的顺序排列的 txid，内部字节序
+pub fn compute_root(txids: &[[u8; 32]]) -> ([u8; 32], bool) {
+    if txids.is_empty() {
+        return ([0; 32], false);
+    }
+    let mut layer = txids.to_vec();
+    let mut mutated = false;
+    while 1 < layer.len() {
+        let (next, m) = next_layer(&layer);
+        mutated |= m;
+        layer = next;
+    }
+    (layer[0], mutated)
+}
+
+///#### 计算 merkle root，不检测篡改
+pub fn merkle_root(txids: &[[u8; 32]]) -> [u8; 32] {
+    compute_root(txids).0
+}
+
+///#### 生成第 index 个交易的 merkle branch
+///- #: 自底向上每一层的兄弟节点，index 越界时返回 None
+///- @txids[in]: 按区块中的顺序排列的 txid
+///- @index[in]: 交易在区块中的序号
+pub fn branch(txids: &[[u8; 32]], index: usize) -> Option<Vec<[u8; 32]>> {
+    if txids.len() <= index {
+        return None;
+    }
+    let mut res = vec![];
+    let mut layer = txids.to_vec();
+    let mut idx = index;
+    while 1 < layer.len() {
+        //奇数层的最后一个节点以自身为兄弟
+        let sibling = (idx ^ 1).min(layer.len() - 1);
+        res.push(layer[sibling]);
+        layer = next_layer(&layer).0;
+        idx /= 2;
+    }
+    Some(res)
+}
+
+///#### 由 txid、branch 与序号重算 merkle root
+///- @txid[in]: 交易的 txid
+///- @branch[in]: 自底向上每一层的兄弟节点
+///- @index[in]: 交易在区块中的序号
+pub fn root_from_branch(txid: &[u8; 32], branch: &[[u8; 32]], index: usize) -> [u8; 32] {
+    branch
+        .iter()
+        .enumerate()
+        .fold(*txid, |acc, (level, sibling)| {
+            if 0 == index.checked_shr(level as u32).unwrap_or(0) & 1 {
+                hash_pair(&acc, sibling)
+            } else {
+                hash_pair(sibling, &acc)
+            }
+        })
+}
+
+///#### SPV 验证：交易是否被打包进 merkle root 为 root 的区块
+///- @txid[in]: 交易的 txid
+///- @branch[in]: 自底向上每一层的兄弟节点
+///- @index[in]: 交易在区块中的序号
+///- @root[in]: 区块头中的 merkle root
+pub fn verify_branch(txid: &[u8; 32], branch: &[[u8; 32]], index: usize, root: &[u8; 32]) -> bool {
+    //branch 的层数决定了序号的范围
+    0 == index.checked_shr(branch.len() as u32).unwrap_or(0)
+        && root_from_branch(txid, branch, index) == *root
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testutil::hash;
+
+    //显示形式(大端)转为内部字节序
+    #[test]
+    fn mainnet() {
+        //genesis: a single coinbase
+        let coinbase = hash("4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b");
+        assert_eq!((coinbase, false), compute_root(&[coinbase]));
+        assert_eq!(Some(vec![]), branch(&[coinbase], 0));
+
+        //block 170
+        let txids = [
+            hash("b1fea52486ce0c62bb442b530a3f0132b826c74e473d1f2c220bfa78111c5082"),
+            hash("f4184fc596403b9d638783cf57adfe4c75c605f6356fbc91338530e9831e9e16"),
+        ];
+        assert_eq!(
+            hash("7dac2c5666815c17a3b36427de37bb9d2e2c5ccec3f8633eb91a4205cb4c10ff"),
+            merkle_root(&txids)
+        );
+
+        //block 100000
+        let txids = [
+            hash("8c14f0db3df150123e6f3dbbf30f8b955a8249b62ac1d1ff16284aefa3d06d87"),
+            hash("fff2525b8931402dd09222c50775608f75787bd2b87e56995a7bdd30f79702c4"),
+            hash("6359f0868171b1d194cbee1af2f16ea598ae8fad666d9b012c8ed2b79a236ec4"),
+            hash("e9a66845e05d5abc0ad04ec80f774a7e585c6e8db975962d069a522137b80c1d"),
+        ];
+        let root = hash("f3e94742aca4b5ef85488dc37c06c3282295ffec960994b2c0d5ac2a25a95766");
+        assert_eq!((root, false), compute_root(&txids));
+        for (i, txid) in txids.iter().enumerate() {
+            let b = branch(&txids, i).unwrap();
+            assert_eq!(2, b.len());
+            assert!(verify_branch(txid, &b, i, &root));
+            assert!(!verify_branch(txid, &b, i ^ 1, &root));
+            assert!(!verify_branch(txid, &b, i + 4, &root));
+        }
+        assert!(branch(&txids, 4).is_none());
+    }
+
+    #[test]
+    fn odd_and_mutated() {
+        let txids = (0u8..11).map(|i| double_sha256(&[i])).collect::<Vec<_>>();
+        assert_eq!([0; 32], merkle_root(&[]));
+
+        for n in 1..=txids.len() {
+            let (root, mutated) = compute_root(&txids[..n]);
+            assert!(!mutated);
+            for (i, txid) in txids[..n].iter().enumerate() {
+                let b = branch(&txids[..n], i).unwrap();
+                assert!(verify_branch(txid, &b, i, &root));
+                assert!(!verify_branch(&txids[(i + 1) % 11], &b, i, &root));
+            }
+        }
+
+        //the last leaf is duplicated, not padded with an empty hash
+        let (a, b, c) = (txids[0], txids[1], txids[2]);
+        assert_eq!(
+            hash_pair(&hash_pair(&a, &b), &hash_pair(&c, &c)),
+            merkle_root(&[a, b, c])
+        );
+
+        //CVE-2012-2459: duplicating the tail keeps the root but is detected
+        let mut dup = txids[..3].to_vec();
+        dup.push(txids[2]);
+        assert_eq!((merkle_root(&txids[..3]), true), compute_root(&dup));
+
+        let mut dup = txids[..6].to_vec();
+        dup.extend_from_slice(&txids[4..6]);
+        assert_eq!((merkle_root(&txids[..6]), true), compute_root(&dup));
+
+        let mut dup = txids[..10].to_vec();
+        dup.extend_from_slice(&txids[8..10]);
+        assert_eq!((merkle_root(&txids[..10]), true), compute_root(&dup));
+    }
+}
@@ -0,0 +1,348 @@
+//! ## 比特币多线程挖矿
+//!
+//! #### 算法说明
+//! - 对固定的区块头模板遍历 nonce，直到区块哈希不大于 target；
+//! - nonce 只有 32 bit，遍历完之后改变 coinbase 交易中的 extranonce，从而得到新的 merkle root，再重新遍历 nonce；
+//! - 每个 extranonce 的 nonce 空间切分为若干段，由 rayon 线程池并行搜索，任一线程找到解之后其余线程尽快退出；
+//! - 收到新的链头时，调用方置位取消标志，各线程在下一次检查时退出，旧模板上的搜索随即结束；
+//! - 已计算的哈希数可在挖矿过程中从其他线程读取，据此得到实时的哈希率。
+//!
+//! #### 应用场景
+//! - regtest 风格的低难度出块，用于测试链的其余部分。
+//!
+//! #### 实现属性
+//! - <font color=Green>√</font> 多线程安全
+//! - <font color=Green>√</font> 无 unsafe 代码
+//!
+
+use super::arith::U256;
+use super::header::{double_sha256, BlockHeader, HEADER_LEN};
+use super::retarget::Params;
+use rayon::prelude::*;
+use std::ops::Range;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+//每个线程每计算这么多个哈希，检查一次取消标志并更新哈希计数
+const BATCH: u64 = 1024;
+
+//每个线程分到的 nonce 段数，段越多负载越均衡
+const CHUNKS_PER_THREAD: u64 = 4;
+
+///挖矿的解
+///- @header: 满足 target 的区块头
+///- @extranonce: 生成该区块头 merkle root 时所用的 extranonce
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Solution {
+    pub header: BlockHeader,
+    pub extranonce: u64,
+}
+
+///一次挖矿的结果
+///- @solution: 找到的解，被取消或遍历完全部 extranonce 时为 None
+///- @hashes: 本次计算的哈希数
+///- @elapsed: 耗时
+#[derive(Clone, Copy, Debug)]
+pub struct MineResult {
+    pub solution: Option<Solution>,
+    pub hashes: u64,
+    pub elapsed: Duration,
+}
+
+impl MineResult {
+    ///- #: 每秒哈希数
+    pub fn hash_rate(&self) -> f64 {
+        let secs = self.elapsed.as_secs_f64();
+        if 0.0 < secs {
+            self.hashes as f64 / secs
+        } else {
+            0.0
+        }
+    }
+}
+
+//- @pool: 线程池
+//- @threads: 线程数
+//- @max_nonce: 每个 extranonce 遍历的 nonce 上限(含)
+//- @hashes: 累计计算的哈希数
+pub struct Miner {
+    pool: rayon::ThreadPool,
+    threads: usize,
+    max_nonce: u32,
+    hashes: AtomicU64,
+}
+
+impl Miner {
+    ///- @threads[in]: 线程数，须大于 0
+    pub fn new(threads: usize) -> Miner {
+        Miner::with_max_nonce(threads, u32::MAX)
+    }
+
+    ///#### 限定每个 extranonce 遍历的 nonce 上限，主要用于测试 extranonce 的切换
+    ///- @threads[in]: 线程数，须大于 0
+    ///- @max_nonce[in]: nonce 上限(含)
+    pub fn with_max_nonce(threads: usize, max_nonce: u32) -> Miner {
+        assert!(0 < threads, "threads must be positive");
+        Miner {
+            pool: rayon::ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .build()
+                .expect("failed to build thread pool"),
+            threads,
+            max_nonce,
+            hashes: AtomicU64::new(0),
+        }
+    }
+
+    #[inline(always)]
+    pub fn threads(&self) -> usize {
+        self.threads
+    }
+
+    ///- #: 累计计算的哈希数，可在挖矿过程中读取
+    #[inline(always)]
+    pub fn hashes(&self) -> u64 {
+        self.hashes.load(Ordering::Relaxed)
+    }
+
+    ///#### 挖矿
+    ///- @template[in]: 区块头模板，其中的 merkle_root 与 nonce 会被替换
+    ///- @merkle_root[in]: 由 extranonce 生成 merkle root
+    ///- @extranonces[in]: 依次尝试的 extranonce
+    ///- @params[in]: 网络参数，template.bits 不是合法的 target 时不挖矿
+    ///- @cancel[in]: 取消标志，置位后各线程尽快退出
+    pub fn mine<F>(
+        &self,
+        template: &BlockHeader,
+        merkle_root: F,
+        extranonces: Range<u64>,
+        params: &Params,
+        cancel: &AtomicBool,
+    ) -> MineResult
+    where
+        F: Fn(u64) -> [u8; 32],
+    {
+        let start = Instant::now();
+        let before = self.hashes();
+        let target = match U256::from_compact(template.bits) {
+            Some(t) if !t.is_zero() && t <= params.pow_limit => t,
+            _ => U256::ZERO,
+        };
+
+        let mut solution = None;
+        if !target.is_zero() {
+            for extranonce in extranonces {
+                if cancel.load(Ordering::Relaxed) {
+                    break;
+                }
+                let mut header = *template;
+                header.merkle_root = merkle_root(extranonce);
+                solution = self.search(&header, &target, cancel).map(|nonce| {
+                    header.nonce = nonce;
+                    Solution { header, extranonce }
+                });
+                if solution.is_some() {
+                    break;
+                }
+            }
+        }
+
+        MineResult {
+            solution,
+            hashes: self.hashes() - before,
+            elapsed: start.elapsed(),
+        }
+    }
+
+    //- #: 在 [0, max_nonce] 中并行搜索满足 target 的 nonce
+    fn search(&self, header: &BlockHeader, target: &U256, cancel: &AtomicBool) -> Option<u32> {
+        let total = u64::from(self.max_nonce) + 1;
+        let chunks = (self.threads as u64 * CHUNKS_PER_THREAD).min(total);
+        let size = total.div_ceil(chunks);
+        let found = AtomicBool::new(false);
+        let bytes = header.to_bytes();
+
+        self.pool.install(|| {
+            (0..chunks).into_par_iter().find_map_any(|i| {
+                let range = i * size..((i + 1) * size).min(total);
+                self.scan(&bytes, range, target, cancel, &found)
+            })
+        })
+    }
+
+    //在一段 nonce 中顺序搜索，每 BATCH 个哈希更新一次计数，退出前补上尚未计入的部分
+    //- #: 满足 target 的 nonce
+    //- @found[in]: 任一段找到解之后置位，其余段在下一次检查时退出
+    fn scan(
+        &self,
+        bytes: &[u8; HEADER_LEN],
+        range: Range<u64>,
+        target: &U256,
+        cancel: &AtomicBool,
+        found: &AtomicBool,
+    ) -> Option<u32> {
+        let mut buf = *bytes;
+        let (mut cnt, mut reported) = (0u64, 0u64);
+        let mut res = None;
+        for nonce in range {
+            buf[76..].copy_from_slice(&(nonce as u32).to_le_bytes());
+            cnt += 1;
+            if U256::from_le_bytes(&double_sha256(&buf)) <= *target {
+                res = Some(nonce as u32);
+                found.store(true, Ordering::Relaxed);
+                break;
+            }
+            if BATCH == cnt - reported {
+                self.hashes.fetch_add(BATCH, Ordering::Relaxed);
+                reported = cnt;
+                if cancel.load(Ordering::Relaxed) || found.load(Ordering::Relaxed) {
+                    break;
+                }
+            }
+        }
+        self.hashes.fetch_add(cnt - reported, Ordering::Relaxed);
+        res
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::Arc;
+    use std::thread;
+
+    //stands in for the merkle root of a coinbase carrying the extranonce
+    fn root(extranonce: u64) -> [u8; 32] {
+        double_sha256(&extranonce.to_le_bytes())
+    }
+
+    #[test]
+    fn regtest_chain() {
+        let params = Params::REGTEST;
+        let miner = Miner::new(4);
+        let cancel = AtomicBool::new(false);
+        let mut prev = BlockHeader {
+            version: 1,
+            time: 1_296_688_602,
+            bits: 0x207f_ffff,
+            ..Default::default()
+        };
+
+        for (i, bits) in [0x207f_ffff, 0x2000_ffff, 0x1f00_ffff].iter().enumerate() {
+            let template = BlockHeader {
+                prev_hash: prev.hash(),
+                time: prev.time + 600,
+                bits: *bits,
+                ..prev
+            };
+            let res = miner.mine(&template, root, 0..u64::MAX, &params, &cancel);
+            let sol = res.solution.unwrap();
+            assert!(sol.header.check_pow(&params));
+            assert_eq!(root(sol.extranonce), sol.header.merkle_root);
+            assert_eq!(template.prev_hash, sol.header.prev_hash);
+            assert!(0 < res.hashes);
+            if 2 == i {
+                assert!(0.0 < res.hash_rate());
+            }
+            prev = sol.header;
+        }
+        assert!(0 < miner.hashes());
+    }
+
+    #[test]
+    fn extranonce() {
+        //16 nonces per extranonce at a 1/256 success rate
+        let params = Params::REGTEST;
+        let miner = Miner::with_max_nonce(2, 15);
+        let cancel = AtomicBool::new(false);
+        let template = BlockHeader {
+            bits: 0x2000_ffff,
+            ..Default::default()
+        };
+        let res = miner.mine(&template, root, 0..1000, &params, &cancel);
+        let sol = res.solution.unwrap();
+        assert!(0 < sol.extranonce);
+        assert!(sol.header.nonce <= 15);
+        assert!(sol.header.check_pow(&params));
+        assert_eq!(root(sol.extranonce), sol.header.merkle_root);
+        assert!(16 * sol.extranonce <= res.hashes);
+
+        //extranonce 0 has no solution, so the range is exhausted
+        let res = miner.mine(&template, root, 0..1, &params, &cancel);
+        assert!(res.solution.is_none());
+        assert_eq!(16, res.hashes);
+
+        //an invalid target is never mined
+        let bad = BlockHeader {
+            bits: 0x0492_3456,
+            ..template
+        };
+        let res = miner.mine(&bad, root, 0..10, &params, &cancel);
+        assert!(res.solution.is_none());
+        assert_eq!(0, res.hashes);
+    }
+
+    #[test]
+    fn batch_boundary() {
+        //a header whose smallest hash among nonces 0..4096 lies at or beyond nonce 1023
+        let hash = |bytes: &[u8; HEADER_LEN], nonce: u64| {
+            let mut buf = *bytes;
+            buf[76..].copy_from_slice(&(nonce as u32).to_le_bytes());
+            U256::from_le_bytes(&double_sha256(&buf))
+        };
+        let (bytes, best) = (0..)
+            .map(|time| {
+                BlockHeader {
+                    time,
+                    ..Default::default()
+                }
+                .to_bytes()
+            })
+            .map(|bytes| (bytes, (0..4096).min_by_key(|n| hash(&bytes, *n)).unwrap()))
+            .find(|(_, best)| BATCH - 1 <= *best)
+            .unwrap();
+
+        //the solution is the last of exactly one batch, every hash is counted once
+        let miner = Miner::new(1);
+        let (cancel, found) = (AtomicBool::new(false), AtomicBool::new(false));
+        let target = hash(&bytes, best);
+        let range = best + 1 - BATCH..best + 1;
+        let res = miner.scan(&bytes, range, &target, &cancel, &found);
+        assert_eq!(Some(best as u32), res);
+        assert_eq!(BATCH, miner.hashes());
+
+        //a range without a solution is counted in full as well
+        let found = AtomicBool::new(false);
+        let res = miner.scan(&bytes, 0..BATCH + 5, &U256::ZERO, &cancel, &found);
+        assert!(res.is_none());
+        assert_eq!(2 * BATCH + 5, miner.hashes());
+    }
+
+    #[test]
+    fn cancel() {
+        let params = Params::MAINNET;
+        let miner = Arc::new(Miner::new(2));
+        let cancel = Arc::new(AtomicBool::new(false));
+        let template = BlockHeader {
+            bits: 0x1b00_ffff,
+            ..Default::default()
+        };
+
+        let handle = {
+            let (miner, cancel) = (Arc::clone(&miner), Arc::clone(&cancel));
+            thread::spawn(move || miner.mine(&template, root, 0..u64::MAX, &params, &cancel))
+        };
+        while 0 == miner.hashes() {
+            thread::sleep(Duration::from_millis(1));
+        }
+        //a new tip arrives
+        cancel.store(true, Ordering::Relaxed);
+        let res = handle.join().unwrap();
+        assert!(res.solution.is_none());
+        assert!(0 < res.hashes);
+
+        let res = miner.mine(&template, root, 0..u64::MAX, &params, &cancel);
+        assert!(res.solution.is_none());
+        assert_eq!(0, res.hashes);
+    }
+}
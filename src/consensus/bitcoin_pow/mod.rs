@@ -1,5 +1,18 @@
-/// ## pow consensus algo
-/// - 比特币的 POW 共识算法
+//! ## pow consensus algo
+//! - 比特币的 POW 共识算法
+//!
+//! #### 算法说明
+//! - 矿工不断改变区块头中的 nonce，直到区块头的 double-SHA256 不大于区块头中 nBits 所表示的 target；
+//! - 验证只需一次哈希，而找到合法的 nonce 平均需要 2^256 / target 次哈希；
+//! - 每 2016 个区块按实际出块速度调整一次 target，使平均出块间隔保持在 10 分钟附近。
+//!
+//! #### 实现属性
+//! - <font color=Green>√</font> 多线程安全
+//! - <font color=Green>√</font> 无 unsafe 代码
+//!
 
-#[cfg(test)]
-mod tests {}
+pub mod arith;
+pub mod header;
+pub mod merkle;
+pub mod miner;
+pub mod retarget;
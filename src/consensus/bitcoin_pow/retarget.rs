@@ -0,0 +1,156 @@
+//! ## 比特币难度调整
+//!
+//! #### 算法说明
+//! - 每 2016 个区块(interval = target_timespan / target_spacing)调整一次 target，
+//!   期望这 2016 个区块恰好耗时两周，即每个区块 10 分钟；
+//! - 实际耗时取调整周期内第一个区块与最后一个区块的时间戳之差，并限制在 [两周 / 4, 两周 * 4] 之内，
+//!   于是单次调整 target 最多变为原来的 4 倍或 1/4；
+//! - 新 target = 旧 target * 实际耗时 / 期望耗时，且不超过 pow_limit，最后编码为 compact 形式；
+//! - 历史原因：实际耗时只覆盖 2015 个区块间隔(off-by-one)，这里与比特币保持一致；
+//! - 难度 difficulty = 难度 1 的 target(即 nBits = 0x1d00ffff) / 当前 target，只用于显示。
+//!
+//! #### 实现属性
+//! - <font color=Green>√</font> 多线程安全
+//! - <font color=Green>√</font> 无 unsafe 代码
+//!
+
+use super::arith::U256;
+
+///难度 1 对应的 nBits
+pub const DIFF1_BITS: u32 = 0x1d00_ffff;
+
+///网络参数
+///- @pow_limit: 允许的最大 target，即最低难度
+///- @target_timespan: 每个调整周期的期望耗时，单位秒
+///- @target_spacing: 期望的出块间隔，单位秒
+///- @no_retargeting: 不调整难度，用于 regtest
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Params {
+    pub pow_limit: U256,
+    pub target_timespan: u32,
+    pub target_spacing: u32,
+    pub no_retargeting: bool,
+}
+
+impl Params {
+    pub const MAINNET: Params = Params {
+        pow_limit: U256([0x0000_0000_ffff_ffff, u64::MAX, u64::MAX, u64::MAX]),
+        target_timespan: 14 * 24 * 3600,
+        target_spacing: 600,
+        no_retargeting: false,
+    };
+
+    pub const REGTEST: Params = Params {
+        pow_limit: U256([0x7fff_ffff_ffff_ffff, u64::MAX, u64::MAX, u64::MAX]),
+        target_timespan: 14 * 24 * 3600,
+        target_spacing: 600,
+        no_retargeting: true,
+    };
+
+    ///- #: 每个调整周期的区块数
+    #[inline(always)]
+    pub fn interval(&self) -> u32 {
+        self.target_timespan / self.target_spacing
+    }
+}
+
+///#### 下一个区块的 nBits
+///- @params[in]: 网络参数
+///- @last_height[in]: 当前最后一个区块的高度
+///- @last_bits[in]: 当前最后一个区块的 nBits
+///- @last_time[in]: 当前最后一个区块的时间戳
+///- @first_time[in]: 高度为 last_height - (interval - 1) 的区块的时间戳，仅在需要调整时使用
+pub fn next_bits(
+    params: &Params,
+    last_height: u32,
+    last_bits: u32,
+    last_time: u32,
+    first_time: u32,
+) -> u32 {
+    if params.no_retargeting || !(last_height + 1).is_multiple_of(params.interval()) {
+        last_bits
+    } else {
+        retarget(params, last_bits, first_time, last_time)
+    }
+}
+
+///#### 按调整周期的实际耗时计算新的 nBits
+///- @params[in]: 网络参数
+///- @last_bits[in]: 调整周期内最后一个区块的 nBits
+///- @first_time[in]: 调整周期内第一个区块的时间戳
+///- @last_time[in]: 调整周期内最后一个区块的时间戳
+pub fn retarget(params: &Params, last_bits: u32, first_time: u32, last_time: u32) -> u32 {
+    let timespan = u64::from(params.target_timespan);
+    let actual = (i64::from(last_time) - i64::from(first_time))
+        .clamp((timespan / 4) as i64, (timespan * 4) as i64) as u64;
+
+    let old = U256::from_compact(last_bits).unwrap_or(params.pow_limit);
+    let res = match old.checked_mul_u64(actual) {
+        Some(x) => x.div_u64(timespan).min(params.pow_limit),
+        None => params.pow_limit,
+    };
+    res.to_compact()
+}
+
+///#### 由 nBits 计算难度
+///- #: nBits 不是合法的 target 时返回 0
+pub fn difficulty(bits: u32) -> f64 {
+    match U256::from_compact(bits) {
+        Some(target) if !target.is_zero() => {
+            U256::from_compact(DIFF1_BITS).unwrap_or_default().to_f64() / target.to_f64()
+        }
+        _ => 0.0,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    //mainnet vectors from bitcoin core's pow tests
+    #[test]
+    fn retarget_mainnet() {
+        let params = Params::MAINNET;
+        assert_eq!(2016, params.interval());
+
+        //the first retarget, at height 32256
+        assert_eq!(
+            0x1d00_d86a,
+            next_bits(&params, 32255, 0x1d00_ffff, 1_262_152_739, 1_261_130_161)
+        );
+        //not on a retarget boundary
+        assert_eq!(
+            0x1d00_ffff,
+            next_bits(&params, 32254, 0x1d00_ffff, 1_262_152_739, 1_261_130_161)
+        );
+        //slower than expected but already at the pow limit
+        assert_eq!(
+            0x1d00_ffff,
+            next_bits(&params, 2015, 0x1d00_ffff, 1_233_061_996, 1_231_006_505)
+        );
+        //4x faster is clamped
+        assert_eq!(
+            0x1c01_68fd,
+            next_bits(&params, 68543, 0x1c05_a3f4, 1_279_297_671, 1_279_008_237)
+        );
+        //4x slower is clamped
+        assert_eq!(
+            0x1d00_e1fd,
+            next_bits(&params, 46367, 0x1c38_7f6f, 1_269_211_443, 1_263_163_443)
+        );
+
+        //regtest never retargets
+        let regtest = Params::REGTEST;
+        assert_eq!(0x207f_ffff, next_bits(&regtest, 2015, 0x207f_ffff, 1, 0));
+    }
+
+    #[test]
+    fn difficulty_of_bits() {
+        assert_eq!(1.0, difficulty(DIFF1_BITS));
+        let d = difficulty(0x1d00_d86a);
+        assert!((1.182..1.183).contains(&d));
+        assert!((45.38..45.39).contains(&difficulty(0x1c05_a3f4)));
+        assert_eq!(0.0, difficulty(0));
+        assert_eq!(0.0, difficulty(0x0492_3456));
+    }
+}
@@ -0,0 +1,288 @@
+//! ## 以太坊难度调整
+//!
+//! #### 算法说明
+//! - 每个区块都由父区块调整难度，调整单位为 parent_difficulty / 2048，出块间隔 Δ = timestamp - parent_timestamp：
+//!   - Frontier：Δ < 13 时增加一个单位，否则减少一个单位；
+//!   - Homestead(EIP-2)：调整 max(1 - Δ / 10, -99) 个单位，出块越慢降得越多，且不受时间戳操纵的影响；
+//!   - Byzantium(EIP-100)：调整 max(y - Δ / 9, -99) 个单位，父区块含叔块时 y = 2，否则 y = 1，
+//!     即以包含叔块在内的区块数作为出块速度，防止矿工通过不打包叔块来压低难度；
+//! - 难度不低于 131072，再加上难度炸弹 2^(⌊n / 100000⌋ - 2)(⌊n / 100000⌋ < 2 时为 0)，炸弹使难度指数上升，
+//!   促使网络硬分叉；
+//! - Byzantium 之后以 n = max(number - delay, 0) 计算炸弹，推迟炸弹生效：Byzantium 推迟 300 万块(EIP-649)，
+//!   Constantinople 推迟 500 万块(EIP-1234)，此后 Muir Glacier、London、Arrow Glacier、Gray Glacier 又各自推迟；
+//! - 各规则的生效高度与炸弹推迟由分叉计划(Schedule)配置。
+//!
+//! #### 实现属性
+//! - <font color=Green>√</font> 多线程安全
+//! - <font color=Green>√</font> 无 unsafe 代码
+//!
+
+///最低难度
+pub const MINIMUM_DIFFICULTY: u128 = 131_072;
+
+//调整单位 = parent_difficulty / DIFFICULTY_BOUND_DIVISOR
+const DIFFICULTY_BOUND_DIVISOR: u128 = 2048;
+
+//Frontier 的目标出块间隔
+const DURATION_LIMIT: u64 = 13;
+
+//炸弹每这么多个区块翻倍
+const EXP_DIFF_PERIOD: u64 = 100_000;
+
+///难度调整规则
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Rule {
+    Frontier,
+    Homestead,
+    Byzantium,
+}
+
+///分叉计划
+///- @homestead: Homestead 规则的生效高度
+///- @byzantium: Byzantium 规则的生效高度
+///- @bomb_delays: (生效高度, 推迟的区块数)，按生效高度升序排列
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Schedule {
+    pub homestead: u64,
+    pub byzantium: u64,
+    pub bomb_delays: &'static [(u64, u64)],
+}
+
+impl Schedule {
+    pub const MAINNET: Schedule = Schedule {
+        homestead: 1_150_000,
+        byzantium: 4_370_000,
+        bomb_delays: &[
+            (4_370_000, 3_000_000),   //Byzantium
+            (7_280_000, 5_000_000),   //Constantinople
+            (9_200_000, 9_000_000),   //Muir Glacier
+            (12_965_000, 9_700_000),  //London
+            (13_773_000, 10_700_000), //Arrow Glacier
+            (15_050_000, 11_400_000), //Gray Glacier
+        ],
+    };
+
+    ///始终使用 Frontier 规则
+    pub const FRONTIER: Schedule = Schedule {
+        homestead: u64::MAX,
+        byzantium: u64::MAX,
+        bomb_delays: &[],
+    };
+
+    ///- #: 高度为 number 的区块所用的规则
+    pub fn rule(&self, number: u64) -> Rule {
+        if self.byzantium <= number {
+            Rule::Byzantium
+        } else if self.homestead <= number {
+            Rule::Homestead
+        } else {
+            Rule::Frontier
+        }
+    }
+
+    ///- #: 高度为 number 的区块的炸弹推迟区块数
+    pub fn bomb_delay(&self, number: u64) -> u64 {
+        self.bomb_delays
+            .iter()
+            .rev()
+            .find(|(activation, _)| *activation <= number)
+            .map_or(0, |(_, delay)| *delay)
+    }
+}
+
+///计算难度所需的父区块信息
+///- @number: 高度
+///- @timestamp: 时间戳，单位秒
+///- @difficulty: 难度
+///- @has_uncles: 是否包含叔块
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Parent {
+    pub number: u64,
+    pub timestamp: u64,
+    pub difficulty: u128,
+    pub has_uncles: bool,
+}
+
+///#### 难度炸弹
+///- #: 2^(⌊number / 100000⌋ - 2)，溢出时取 u128::MAX
+///- @number[in]: 推迟之后的区块高度
+pub fn bomb(number: u64) -> u128 {
+    match number / EXP_DIFF_PERIOD {
+        0 | 1 => 0,
+        period => 1u128.checked_shl((period - 2) as u32).unwrap_or(u128::MAX),
+    }
+}
+
+///#### 子区块的难度
+///- @schedule[in]: 分叉计划
+///- @parent[in]: 父区块
+///- @timestamp[in]: 子区块的时间戳
+pub fn calc_difficulty(schedule: &Schedule, parent: &Parent, timestamp: u64) -> u128 {
+    let number = parent.number + 1;
+    let delta = timestamp.saturating_sub(parent.timestamp);
+    let sigma = match schedule.rule(number) {
+        Rule::Frontier => {
+            if delta < DURATION_LIMIT {
+                1
+            } else {
+                -1
+            }
+        }
+        Rule::Homestead => (1 - (delta / 10).min(1000) as i64).max(-99),
+        Rule::Byzantium => {
+            let y = if parent.has_uncles { 2 } else { 1 };
+            (y - (delta / 9).min(1000) as i64).max(-99)
+        }
+    };
+
+    let unit = parent.difficulty / DIFFICULTY_BOUND_DIVISOR;
+    let adjust = unit.saturating_mul(sigma.unsigned_abs() as u128);
+    let diff = if 0 <= sigma {
+        parent.difficulty.saturating_add(adjust)
+    } else {
+        parent.difficulty.saturating_sub(adjust)
+    };
+    let fake = number.saturating_sub(schedule.bomb_delay(number));
+    diff.max(MINIMUM_DIFFICULTY).saturating_add(bomb(fake))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    //adjustment unit of 10^10
+    const D: u128 = 2048 * 10_000_000_000;
+    const UNIT: u128 = 10_000_000_000;
+
+    fn parent(number: u64, difficulty: u128, has_uncles: bool) -> Parent {
+        Parent {
+            number,
+            timestamp: 1_000_000,
+            difficulty,
+            has_uncles,
+        }
+    }
+
+    #[test]
+    fn schedule() {
+        let s = Schedule::MAINNET;
+        assert_eq!(Rule::Frontier, s.rule(1_149_999));
+        assert_eq!(Rule::Homestead, s.rule(1_150_000));
+        assert_eq!(Rule::Byzantium, s.rule(4_370_000));
+        assert_eq!(Rule::Frontier, Schedule::FRONTIER.rule(u64::MAX - 1));
+
+        assert_eq!(0, s.bomb_delay(4_369_999));
+        assert_eq!(3_000_000, s.bomb_delay(4_370_000));
+        assert_eq!(3_000_000, s.bomb_delay(7_279_999));
+        assert_eq!(5_000_000, s.bomb_delay(7_280_000));
+        assert_eq!(11_400_000, s.bomb_delay(15_050_000));
+
+        assert_eq!(0, bomb(199_999));
+        assert_eq!(1, bomb(200_000));
+        assert_eq!(1 << 10, bomb(1_200_000));
+        assert_eq!(u128::MAX, bomb(u64::MAX));
+    }
+
+    #[test]
+    fn frontier() {
+        //mainnet blocks 1 and 2
+        let s = Schedule::MAINNET;
+        let genesis = Parent {
+            number: 0,
+            timestamp: 0,
+            difficulty: 17_179_869_184,
+            has_uncles: false,
+        };
+        assert_eq!(17_171_480_576, calc_difficulty(&s, &genesis, 1_438_269_988));
+        let block1 = Parent {
+            number: 1,
+            timestamp: 1_438_269_988,
+            difficulty: 17_171_480_576,
+            has_uncles: false,
+        };
+        assert_eq!(17_163_096_064, calc_difficulty(&s, &block1, 1_438_270_017));
+
+        //the 13 second boundary, with the bomb of block 1_000_000
+        let p = parent(999_999, D, false);
+        assert_eq!(D + UNIT + 256, calc_difficulty(&s, &p, p.timestamp + 12));
+        assert_eq!(D - UNIT + 256, calc_difficulty(&s, &p, p.timestamp + 13));
+    }
+
+    #[test]
+    fn homestead() {
+        let s = Schedule::MAINNET;
+        let p = parent(1_199_999, D, false);
+        let bomb = 1 << 10;
+        assert_eq!(D + UNIT + bomb, calc_difficulty(&s, &p, p.timestamp + 9));
+        assert_eq!(D + bomb, calc_difficulty(&s, &p, p.timestamp + 10));
+        assert_eq!(D - UNIT + bomb, calc_difficulty(&s, &p, p.timestamp + 25));
+        //at most 99 units down
+        assert_eq!(
+            D - 99 * UNIT + bomb,
+            calc_difficulty(&s, &p, p.timestamp + 2000)
+        );
+        //uncles are ignored before byzantium
+        let u = parent(1_199_999, D, true);
+        assert_eq!(D + bomb, calc_difficulty(&s, &u, u.timestamp + 10));
+
+        //the same parent under frontier rules
+        let f = Schedule::FRONTIER;
+        assert_eq!(D - UNIT + bomb, calc_difficulty(&f, &p, p.timestamp + 2000));
+
+        //never below the minimum, the bomb comes on top
+        let low = parent(99, MINIMUM_DIFFICULTY, false);
+        assert_eq!(
+            MINIMUM_DIFFICULTY,
+            calc_difficulty(&f, &low, low.timestamp + 100)
+        );
+        let low = parent(1_199_999, MINIMUM_DIFFICULTY, false);
+        assert_eq!(
+            MINIMUM_DIFFICULTY + bomb,
+            calc_difficulty(&s, &low, low.timestamp + 100)
+        );
+    }
+
+    #[test]
+    fn byzantium() {
+        let s = Schedule::MAINNET;
+        //block 5_000_000 is delayed to 2_000_000
+        let bomb = 1 << 18;
+        let p = parent(4_999_999, D, false);
+        let u = parent(4_999_999, D, true);
+        assert_eq!(D + UNIT + bomb, calc_difficulty(&s, &p, p.timestamp + 8));
+        assert_eq!(D + bomb, calc_difficulty(&s, &p, p.timestamp + 9));
+        assert_eq!(D + UNIT + bomb, calc_difficulty(&s, &u, u.timestamp + 9));
+        assert_eq!(D + UNIT + bomb, calc_difficulty(&s, &u, u.timestamp + 17));
+        assert_eq!(D + bomb, calc_difficulty(&s, &u, u.timestamp + 18));
+        assert_eq!(
+            D + 2 * UNIT + bomb,
+            calc_difficulty(&s, &u, u.timestamp + 8)
+        );
+        assert_eq!(
+            D - 99 * UNIT + bomb,
+            calc_difficulty(&s, &u, u.timestamp + 5000)
+        );
+
+        //constantinople pushes the bomb back by another 2_000_000 blocks
+        let before = parent(7_279_998, D, false);
+        assert_eq!(
+            D + (1 << 40),
+            calc_difficulty(&s, &before, before.timestamp + 9)
+        );
+        let after = parent(7_279_999, D, false);
+        assert_eq!(
+            D + (1 << 20),
+            calc_difficulty(&s, &after, after.timestamp + 9)
+        );
+
+        //without a delay the bomb would dominate
+        let undelayed = Schedule {
+            bomb_delays: &[],
+            ..s
+        };
+        assert_eq!(
+            D + (1 << 70),
+            calc_difficulty(&undelayed, &after, after.timestamp + 9)
+        );
+    }
+}
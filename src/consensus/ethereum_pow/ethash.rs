@@ -0,0 +1,546 @@
+//! ## Ethash
+//!
+//! #### 算法说明
+//! - 每 epoch_length 个区块为一个 epoch，epoch 的种子为对 32 字节全零连续做 epoch 次 keccak-256；
+//! - cache：由种子连续做 keccak-512 得到 n 个 64 字节的节点，再做 3 轮 RandMemoHash，
+//!   每个节点与前一个节点及一个伪随机节点的异或值做 keccak-512；cache 大小随 epoch 线性增长，节点数取素数；
+//! - dataset：第 i 项由 cache 中 256 个伪随机节点经 FNV 混合之后做 keccak-512 得到，每一项都只依赖 cache，
+//!   因此轻节点只保存 cache，按需计算所需的 dataset 项；全节点(矿工)预先生成整个 dataset，
+//!   其大小约为 cache 的 64 倍，是内存困难性的来源；
+//! - hashimoto：s = keccak-512(header_hash | nonce(小端))，以 s 初始化 128 字节的 mix，
+//!   64 轮中每轮由 mix 决定读取 dataset 中相邻的两项并以 FNV 混入 mix，
+//!   最后将 mix 每 4 个字压缩为 1 个字得到 32 字节的 mix digest，result = keccak-256(s | mix digest)；
+//! - 验证：mix digest 须与区块头中的一致，且 result 按大端解释之后不大于 2^256 / difficulty；
+//!   轻节点验证只需 cache，结果与基于完整 dataset 的计算相同。
+//!
+//! #### 应用场景
+//! - 以太坊 1.0 的 PoW 共识，测试中使用更小的 cache 与 dataset 参数以加快速度。
+//!
+//! #### 实现属性
+//! - <font color=Green>√</font> 多线程安全
+//! - <font color=Green>√</font> 无 unsafe 代码
+//!
+
+use super::super::bitcoin_pow::arith::U256;
+use rayon::prelude::*;
+use tiny_keccak::{keccak256, keccak512};
+
+///cache 与 dataset 节点的字节数
+pub const HASH_BYTES: usize = 64;
+
+///mix 的字节数
+pub const MIX_BYTES: usize = 128;
+
+//每个节点的 32 bit 字数
+const NODE_WORDS: usize = HASH_BYTES / 4;
+
+//mix 的 32 bit 字数
+const MIX_WORDS: usize = MIX_BYTES / 4;
+
+//每次读取的 dataset 项数
+const MIX_NODES: usize = MIX_BYTES / HASH_BYTES;
+
+const CACHE_ROUNDS: usize = 3;
+const DATASET_PARENTS: u32 = 256;
+const ACCESSES: u32 = 64;
+const FNV_PRIME: u32 = 0x0100_0193;
+
+///cache 与 dataset 的节点，64 字节，按小端分为 16 个 32 bit 字
+pub type Node = [u32; NODE_WORDS];
+
+///Ethash 参数，单位均为字节
+///- @cache_init: epoch 0 的 cache 大小上限
+///- @cache_growth: 每个 epoch 的 cache 增量
+///- @dataset_init: epoch 0 的 dataset 大小上限
+///- @dataset_growth: 每个 epoch 的 dataset 增量
+///- @epoch_length: 每个 epoch 的区块数
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Params {
+    pub cache_init: u64,
+    pub cache_growth: u64,
+    pub dataset_init: u64,
+    pub dataset_growth: u64,
+    pub epoch_length: u64,
+}
+
+impl Params {
+    pub const MAINNET: Params = Params {
+        cache_init: 1 << 24,
+        cache_growth: 1 << 17,
+        dataset_init: 1 << 30,
+        dataset_growth: 1 << 23,
+        epoch_length: 30000,
+    };
+
+    ///- #: 区块所在的 epoch
+    #[inline(always)]
+    pub fn epoch(&self, block_number: u64) -> u64 {
+        block_number / self.epoch_length
+    }
+
+    ///- #: epoch 的 cache 字节数，节点数为不超过上限的最大素数
+    pub fn cache_size(&self, epoch: u64) -> usize {
+        let mut sz = self.cache_init + self.cache_growth * epoch - HASH_BYTES as u64;
+        while !is_prime(sz / HASH_BYTES as u64) {
+            sz -= 2 * HASH_BYTES as u64;
+        }
+        sz as usize
+    }
+
+    ///- #: epoch 的 dataset 字节数，以 MIX_BYTES 计的项数为不超过上限的最大素数
+    pub fn dataset_size(&self, epoch: u64) -> usize {
+        let mut sz = self.dataset_init + self.dataset_growth * epoch - MIX_BYTES as u64;
+        while !is_prime(sz / MIX_BYTES as u64) {
+            sz -= 2 * MIX_BYTES as u64;
+        }
+        sz as usize
+    }
+}
+
+fn is_prime(n: u64) -> bool {
+    if n < 2 {
+        return false;
+    }
+    let mut i = 2;
+    while i * i <= n {
+        if n.is_multiple_of(i) {
+            return false;
+        }
+        i += 1;
+    }
+    true
+}
+
+#[inline(always)]
+fn fnv(a: u32, b: u32) -> u32 {
+    a.wrapping_mul(FNV_PRIME) ^ b
+}
+
+fn to_bytes(words: &[u32], out: &mut [u8]) {
+    out.chunks_mut(4)
+        .zip(words.iter())
+        .for_each(|(o, w)| o.copy_from_slice(&w.to_le_bytes()));
+}
+
+fn from_bytes(bytes: &[u8], out: &mut [u32]) {
+    let mut buf = [0; 4];
+    bytes.chunks(4).zip(out.iter_mut()).for_each(|(b, o)| {
+        buf.copy_from_slice(b);
+        *o = u32::from_le_bytes(buf);
+    });
+}
+
+//- #: keccak-512(node)
+fn hash_node(node: &Node) -> Node {
+    let mut bytes = [0; HASH_BYTES];
+    to_bytes(node, &mut bytes);
+    let mut res = [0; NODE_WORDS];
+    from_bytes(&keccak512(&bytes), &mut res);
+    res
+}
+
+///- #: epoch 的种子，即对 32 字节全零连续做 epoch 次 keccak-256
+pub fn seed_hash(epoch: u64) -> [u8; 32] {
+    (0..epoch).fold([0; 32], |seed, _| keccak256(&seed))
+}
+
+///#### 生成 cache
+///- @size[in]: cache 字节数，须为 HASH_BYTES 的倍数
+///- @seed[in]: epoch 的种子
+pub fn make_cache(size: usize, seed: &[u8; 32]) -> Vec<Node> {
+    let n = size / HASH_BYTES;
+    assert!(0 < n, "cache is empty");
+    let mut cache = Vec::with_capacity(n);
+    let mut first = [0; NODE_WORDS];
+    from_bytes(&keccak512(seed), &mut first);
+    cache.push(first);
+    for i in 1..n {
+        let next = hash_node(&cache[i - 1]);
+        cache.push(next);
+    }
+
+    //RandMemoHash
+    for _ in 0..CACHE_ROUNDS {
+        for i in 0..n {
+            let v = cache[i][0] as usize % n;
+            let mut x = cache[(i + n - 1) % n];
+            x.iter_mut().zip(cache[v].iter()).for_each(|(a, b)| *a ^= b);
+            cache[i] = hash_node(&x);
+        }
+    }
+    cache
+}
+
+///#### 由 cache 计算 dataset 的第 i 项
+pub fn dataset_item(cache: &[Node], i: u32) -> Node {
+    let n = cache.len();
+    let mut mix = cache[i as usize % n];
+    mix[0] ^= i;
+    mix = hash_node(&mix);
+    for j in 0..DATASET_PARENTS {
+        let parent = fnv(i ^ j, mix[j as usize % NODE_WORDS]) as usize % n;
+        mix.iter_mut()
+            .zip(cache[parent].iter())
+            .for_each(|(a, b)| *a = fnv(*a, *b));
+    }
+    hash_node(&mix)
+}
+
+///#### 生成完整的 dataset，各项并行计算
+///- @cache[in]: epoch 的 cache
+///- @size[in]: dataset 字节数，须为 HASH_BYTES 的倍数
+pub fn make_dataset(cache: &[Node], size: usize) -> Vec<Node> {
+    (0..(size / HASH_BYTES) as u32)
+        .into_par_iter()
+        .map(|i| dataset_item(cache, i))
+        .collect()
+}
+
+///hashimoto 的结果
+///- @mix_digest: 压缩之后的 mix，写入区块头
+///- @result: 与 difficulty 比较的哈希
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PowResult {
+    pub mix_digest: [u8; 32],
+    pub result: [u8; 32],
+}
+
+///#### hashimoto
+///- @header_hash[in]: 不含 nonce 与 mix digest 的区块头的 keccak-256
+///- @nonce[in]: nonce
+///- @dataset_size[in]: dataset 字节数
+///- @lookup[in]: 取 dataset 的第 i 项
+pub fn hashimoto<F>(header_hash: &[u8; 32], nonce: u64, dataset_size: usize, lookup: F) -> PowResult
+where
+    F: Fn(u32) -> Node,
+{
+    let rows = (dataset_size / MIX_BYTES) as u32;
+    let mut seed = [0; 40];
+    seed[..32].copy_from_slice(header_hash);
+    seed[32..].copy_from_slice(&nonce.to_le_bytes());
+    let s = keccak512(&seed);
+    let mut s0 = [0; NODE_WORDS];
+    from_bytes(&s, &mut s0);
+
+    let mut mix = [0; MIX_WORDS];
+    mix.chunks_mut(NODE_WORDS)
+        .for_each(|m| m.copy_from_slice(&s0));
+    for i in 0..ACCESSES {
+        let p = fnv(i ^ s0[0], mix[i as usize % MIX_WORDS]) % rows * MIX_NODES as u32;
+        for j in 0..MIX_NODES {
+            let item = lookup(p + j as u32);
+            mix[j * NODE_WORDS..(j + 1) * NODE_WORDS]
+                .iter_mut()
+                .zip(item.iter())
+                .for_each(|(a, b)| *a = fnv(*a, *b));
+        }
+    }
+
+    let mut cmix = [0; MIX_WORDS / 4];
+    cmix.iter_mut()
+        .zip(mix.chunks(4))
+        .for_each(|(c, m)| *c = fnv(fnv(fnv(m[0], m[1]), m[2]), m[3]));
+    let mut mix_digest = [0; 32];
+    to_bytes(&cmix, &mut mix_digest);
+
+    let mut buf = [0; HASH_BYTES + 32];
+    buf[..HASH_BYTES].copy_from_slice(&s);
+    buf[HASH_BYTES..].copy_from_slice(&mix_digest);
+    PowResult {
+        mix_digest,
+        result: keccak256(&buf),
+    }
+}
+
+///#### 仅凭 cache 计算 hashimoto，即轻节点验证
+pub fn hashimoto_light(
+    cache: &[Node],
+    dataset_size: usize,
+    header_hash: &[u8; 32],
+    nonce: u64,
+) -> PowResult {
+    hashimoto(header_hash, nonce, dataset_size, |i| dataset_item(cache, i))
+}
+
+///#### 基于完整 dataset 计算 hashimoto
+pub fn hashimoto_full(dataset: &[Node], header_hash: &[u8; 32], nonce: u64) -> PowResult {
+    hashimoto(header_hash, nonce, dataset.len() * HASH_BYTES, |i| {
+        dataset[i as usize]
+    })
+}
+
+///#### result 是否满足 difficulty，即按大端解释之后不大于 2^256 / difficulty
+///- difficulty 为 0 的区块头无效(go-ethereum 的 errInvalidDifficulty)，返回 false
+pub fn check_difficulty(result: &[u8; 32], difficulty: u128) -> bool {
+    match difficulty {
+        0 => return false,
+        1 => return true,
+        _ => {}
+    }
+    let d = U256([0, 0, (difficulty >> 64) as u64, difficulty as u64]);
+    let mut boundary = U256::MAX / d;
+    if difficulty.is_power_of_two() {
+        //2^256 恰好整除
+        boundary = match boundary.checked_add(&U256::from_u64(1)) {
+            Some(b) => b,
+            None => return true,
+        };
+    }
+    let mut le = *result;
+    le.reverse();
+    U256::from_le_bytes(&le) <= boundary
+}
+
+///一个 epoch 的 Ethash 上下文
+///- @epoch: epoch
+///- @cache: cache
+///- @dataset_size: dataset 字节数
+///- @dataset: 完整的 dataset，仅在 generate_dataset 之后存在
+#[derive(Clone, Debug)]
+pub struct Ethash {
+    epoch: u64,
+    cache: Vec<Node>,
+    dataset_size: usize,
+    dataset: Option<Vec<Node>>,
+}
+
+impl Ethash {
+    ///#### 生成区块所在 epoch 的 cache
+    ///- @params[in]: Ethash 参数
+    ///- @block_number[in]: 区块高度
+    pub fn new(params: &Params, block_number: u64) -> Ethash {
+        let epoch = params.epoch(block_number);
+        Ethash {
+            epoch,
+            cache: make_cache(params.cache_size(epoch), &seed_hash(epoch)),
+            dataset_size: params.dataset_size(epoch),
+            dataset: None,
+        }
+    }
+
+    #[inline(always)]
+    pub fn epoch(&self) -> u64 {
+        self.epoch
+    }
+
+    #[inline(always)]
+    pub fn cache(&self) -> &[Node] {
+        &self.cache
+    }
+
+    #[inline(always)]
+    pub fn dataset_size(&self) -> usize {
+        self.dataset_size
+    }
+
+    #[inline(always)]
+    pub fn dataset(&self) -> Option<&[Node]> {
+        self.dataset.as_deref()
+    }
+
+    ///#### 生成完整的 dataset，此后 compute 不再按需由 cache 计算 dataset 项
+    pub fn generate_dataset(&mut self) {
+        if self.dataset.is_none() {
+            self.dataset = Some(make_dataset(&self.cache, self.dataset_size));
+        }
+    }
+
+    ///#### 计算 hashimoto，已生成 dataset 时使用 dataset，否则使用 cache
+    pub fn compute(&self, header_hash: &[u8; 32], nonce: u64) -> PowResult {
+        match self.dataset.as_ref() {
+            Some(dataset) => hashimoto_full(dataset, header_hash, nonce),
+            None => hashimoto_light(&self.cache, self.dataset_size, header_hash, nonce),
+        }
+    }
+
+    ///#### 验证区块头中的 nonce 与 mix digest
+    ///- @header_hash[in]: 不含 nonce 与 mix digest 的区块头的 keccak-256
+    ///- @nonce[in]: 区块头中的 nonce
+    ///- @mix_digest[in]: 区块头中的 mix digest
+    ///- @difficulty[in]: 区块头中的 difficulty
+    pub fn verify(
+        &self,
+        header_hash: &[u8; 32],
+        nonce: u64,
+        mix_digest: &[u8; 32],
+        difficulty: u128,
+    ) -> bool {
+        let res = hashimoto_light(&self.cache, self.dataset_size, header_hash, nonce);
+        res.mix_digest == *mix_digest && check_difficulty(&res.result, difficulty)
+    }
+
+    ///#### 从 start 开始依次尝试 nonce
+    ///- #: 满足 difficulty 的 (nonce, 结果)，尝试 tries 次仍未找到时返回 None
+    pub fn mine(
+        &self,
+        header_hash: &[u8; 32],
+        start: u64,
+        tries: u64,
+        difficulty: u128,
+    ) -> Option<(u64, PowResult)> {
+        (0..tries)
+            .map(|i| start.wrapping_add(i))
+            .map(|nonce| (nonce, self.compute(header_hash, nonce)))
+            .find(|(_, res)| check_difficulty(&res.result, difficulty))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testutil::{h256, hex};
+
+    //cache 4 KiB, dataset 128 KiB, growing every 100 blocks
+    const SMALL: Params = Params {
+        cache_init: 1 << 12,
+        cache_growth: 1 << 8,
+        dataset_init: 1 << 17,
+        dataset_growth: 1 << 12,
+        epoch_length: 100,
+    };
+
+    #[test]
+    fn sizes() {
+        let p = Params::MAINNET;
+        assert_eq!(16_776_896, p.cache_size(0));
+        assert_eq!(16_907_456, p.cache_size(1));
+        assert_eq!(17_039_296, p.cache_size(2));
+        assert_eq!(1_073_739_904, p.dataset_size(0));
+        assert_eq!(1_082_130_304, p.dataset_size(1));
+        assert_eq!(1_090_514_816, p.dataset_size(2));
+        assert_eq!(0, p.epoch(29_999));
+        assert_eq!(1, p.epoch(30_000));
+
+        assert_eq!([0; 32], seed_hash(0));
+        assert_eq!(
+            h256("290decd9548b62a8d60345a988386fc84ba6bc95484008f6362f93160ef3e563"),
+            seed_hash(1)
+        );
+        assert_eq!(keccak256(&seed_hash(1)), seed_hash(2));
+
+        for epoch in 0..4 {
+            let c = SMALL.cache_size(epoch);
+            let d = SMALL.dataset_size(epoch);
+            assert!(is_prime((c / HASH_BYTES) as u64) && is_prime((d / MIX_BYTES) as u64));
+            assert!(c < SMALL.cache_size(epoch + 1) && d < SMALL.dataset_size(epoch + 1));
+        }
+    }
+
+    #[test]
+    fn light_and_full() {
+        let header = keccak256(b"header");
+        let mut ethash = Ethash::new(&SMALL, 250);
+        assert_eq!(2, ethash.epoch());
+        assert_eq!(SMALL.cache_size(2) / HASH_BYTES, ethash.cache().len());
+        assert!(ethash.dataset().is_none());
+
+        let light = (0..8)
+            .map(|nonce| ethash.compute(&header, nonce))
+            .collect::<Vec<_>>();
+        ethash.generate_dataset();
+        let dataset = ethash.dataset().unwrap();
+        assert_eq!(ethash.dataset_size(), dataset.len() * HASH_BYTES);
+        assert_eq!(dataset_item(ethash.cache(), 7), dataset[7]);
+        for (nonce, res) in light.iter().enumerate() {
+            assert_eq!(*res, ethash.compute(&header, nonce as u64));
+        }
+        assert_ne!(light[0].result, light[1].result);
+        assert_ne!(light[0].mix_digest, light[1].mix_digest);
+
+        //another epoch gives other results
+        let other = Ethash::new(&SMALL, 350);
+        assert_ne!(light[0], other.compute(&header, 0));
+    }
+
+    #[test]
+    fn mine_verify() {
+        let header = keccak256(b"block 42");
+        let ethash = Ethash::new(&SMALL, 42);
+        let difficulty = 1000;
+        let (nonce, res) = ethash.mine(&header, 0, 100_000, difficulty).unwrap();
+        assert!(ethash.verify(&header, nonce, &res.mix_digest, difficulty));
+
+        //a wrong mix digest, header or a harder difficulty is rejected
+        let mut bad = res.mix_digest;
+        bad[0] ^= 1;
+        assert!(!ethash.verify(&header, nonce, &bad, difficulty));
+        assert!(!ethash.verify(&keccak256(b"block 43"), nonce, &res.mix_digest, difficulty));
+        assert!(!ethash.verify(&header, nonce, &res.mix_digest, u128::MAX));
+        assert!(!ethash.verify(&header, nonce, &res.mix_digest, 0));
+    }
+
+    #[test]
+    fn difficulty_boundary() {
+        let mut result = [0; 32];
+        //difficulty 0 is invalid, even for the smallest result
+        assert!(!check_difficulty(&result, 0));
+        assert!(!check_difficulty(&[0xff; 32], 0));
+        assert!(check_difficulty(&[0xff; 32], 1));
+        //2^256 / 2 = 2^255
+        result[0] = 0x80;
+        assert!(check_difficulty(&result, 2));
+        result[31] = 1;
+        assert!(!check_difficulty(&result, 2));
+        //2^256 / 3 = 0x5555...55
+        assert!(check_difficulty(&[0x55; 32], 3));
+        let mut above = [0x55; 32];
+        above[31] = 0x56;
+        assert!(!check_difficulty(&above, 3));
+    }
+
+    fn node_bytes(node: &Node) -> Vec<u8> {
+        let mut res = vec![0; HASH_BYTES];
+        to_bytes(node, &mut res);
+        res
+    }
+
+    //small vectors of go-ethereum: TestCacheGeneration, TestDatasetGeneration and TestHashimoto,
+    //a 1024 byte cache and a 32 KiB dataset
+    #[test]
+    fn geth() {
+        let cache = make_cache(1024, &seed_hash(0));
+        assert_eq!(16, cache.len());
+        assert_eq!(
+            hex("7ce2991c951f7bf4c4c1bb119887ee07871eb5339d7b97b8588e85c742de90e5bafd5bbe6ce93a134fb6be9ad3e30db99d9528a2ea7846833f52e9ca119b6b54"),
+            node_bytes(&cache[0])
+        );
+        let next = make_cache(1024, &seed_hash(1));
+        assert_eq!(
+            hex("1f56855d59cc5a085720899b4377a0198f1abe948d85fe5820dc0e346b7c0931b9cde8e541d751de3b2b3275d0aabfae316209d5879297d8bd99f8a033c9d4df"),
+            node_bytes(&next[0])
+        );
+
+        let dataset = make_dataset(&cache, 32 * 1024);
+        assert_eq!(512, dataset.len());
+        assert_eq!(
+            hex("4bc09fbd530a041dd2ec296110a29e8f130f179c59d223f51ecce3126e8b0c74326abc2f32ccd9d7f976bd0944e3ccf8479db39343cbbffa467046ca97e2da63"),
+            node_bytes(&dataset[0])
+        );
+
+        let header = h256("c9149cc0386e689d789a1c2f3d5d169a61a6218ed30e74414dc736e442ef3d1f");
+        let expected = PowResult {
+            mix_digest: h256("e4073cffaef931d37117cefd9afd27ea0f1cad6a981dd2605c4a1ac97c519800"),
+            result: h256("d3539235ee2e6f8db665c0a72169f55b7f6c605712330b778ec3944f0eb5a557"),
+        };
+        assert_eq!(expected, hashimoto_full(&dataset, &header, 0));
+        assert_eq!(expected, hashimoto_light(&cache, 32 * 1024, &header, 0));
+    }
+
+    //epoch 0 vector of the reference implementation, takes a few minutes without optimizations
+    #[test]
+    #[ignore]
+    fn mainnet_epoch0() {
+        let p = Params::MAINNET;
+        let cache = make_cache(p.cache_size(0), &seed_hash(0));
+        let header = h256("2a8de2adf89af77358250bf908bf04ba94a6e8c3ba87775564a41d269a05e4ce");
+        let res = hashimoto_light(&cache, p.dataset_size(0), &header, 0x4242_4242_4242_4242);
+        assert_eq!(
+            h256("58f759ede17a706c93f13030328bcea40c1d1341fb26f2facd21ceb0dae57017"),
+            res.mix_digest
+        );
+        assert_eq!(
+            h256("dd47fd2d98db51078356852d7c4014e6a5d6c387c35f40e2875b74a256ed7906"),
+            res.result
+        );
+    }
+}
@@ -1,5 +1,14 @@
-/// ## pow consensus algo
-/// - 以太坊的 POW 共识算法
+//! ## pow consensus algo
+//! - 以太坊的 POW 共识算法
+//!
+//! #### 算法说明
+//! - Ethash：内存困难的 PoW，矿工须保存随 epoch 增长的 dataset，轻节点只需保存小得多的 cache 即可验证；
+//! - 区块的 difficulty 由父区块的 difficulty 与出块间隔决定，使平均出块间隔保持稳定。
+//!
+//! #### 实现属性
+//! - <font color=Green>√</font> 多线程安全
+//! - <font color=Green>√</font> 无 unsafe 代码
+//!
 
-#[cfg(test)]
-mod tests {}
+pub mod difficulty;
+pub mod ethash;
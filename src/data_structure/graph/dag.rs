@@ -1,5 +0,0 @@
-#[cfg(test)]
-mod test {
-    #[test]
-    fn test() {}
-}
@@ -0,0 +1,51 @@
+use std::{error::Error, fmt};
+
+///- @XErr::Exists: 顶点已存在
+///- @XErr::NotExists: 顶点不存在
+///- @XErr::EdgeExists: 边已存在
+///- @XErr::Cycle: 添加该边会形成环路，参数为(parent, child)
+///- @XErr::HashMismatch: 内容寻址模式下，顶点 Id 与其内容的哈希不一致
+pub enum XErr<Id: fmt::Debug> {
+    Exists(Id),
+    NotExists(Id),
+    EdgeExists(Id, Id),
+    Cycle(Id, Id),
+    HashMismatch(Id),
+    Unknown,
+}
+
+impl<Id: fmt::Debug> fmt::Display for XErr<Id> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            XErr::Exists(id) => write!(f, "Vertex {:?} already exists!", id),
+            XErr::NotExists(id) => write!(f, "Vertex {:?} not exists!", id),
+            XErr::EdgeExists(p, c) => write!(f, "Edge {:?} -> {:?} already exists!", p, c),
+            XErr::Cycle(p, c) => write!(f, "Edge {:?} -> {:?} will form a cycle!", p, c),
+            XErr::HashMismatch(id) => write!(f, "Vertex {:?} hash mismatch!", id),
+            XErr::Unknown => write!(f, "Unknown error!"),
+        }
+    }
+}
+
+impl<Id: fmt::Debug> fmt::Debug for XErr<Id> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+impl<Id: fmt::Debug> Error for XErr<Id> {
+    fn description(&self) -> &str {
+        match self {
+            XErr::Exists(_) => "Vertex already exists!",
+            XErr::NotExists(_) => "Vertex not exists!",
+            XErr::EdgeExists(..) => "Edge already exists!",
+            XErr::Cycle(..) => "Cycle detected!",
+            XErr::HashMismatch(_) => "Vertex hash mismatch!",
+            XErr::Unknown => "Unknown error!",
+        }
+    }
+
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        None
+    }
+}
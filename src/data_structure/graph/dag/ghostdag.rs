@@ -0,0 +1,429 @@
+//! ## GHOSTDAG
+//!
+//! #### 算法说明
+//! - PHANTOM 协议的贪婪实现：在区块 DAG 中选出一个最大的 k-cluster 作为蓝色集合，其余区块视为红色；
+//! - k-cluster：集合内任一区块，其 anticone 与该集合的交集大小不超过 k；
+//! - 每个区块在其所有 parent 中，选择 blue score 最大者(相同时取 Id 最小者)作为 selected parent，
+//!   并继承其过去集合中的蓝色集合，再按 (blue score, Id) 升序检查 mergeset 中的其余区块能否加入蓝色集合；
+//! - blue score：区块过去集合(past)中蓝色区块的数量；
+//! - 整个 DAG 视为被一个以所有 tips 为 parent 的虚拟区块合并，由此得到全局的蓝色集合、selected parent 链与全序排列；
+//! - 全序排列规则：沿 selected parent 链自 genesis 向后，每个链上区块之前依次插入其 mergeset 中的其余区块。
+//!
+//! #### 应用场景
+//! - 区块 DAG 的共识排序，可容纳高出块率下大量并发产生的区块，同时排除攻击者私下构造的区块。
+//!
+//! #### 实现属性
+//! - <font color=Green>√</font> 多线程安全
+//! - <font color=Green>√</font> 无 unsafe 代码
+//!
+//! #### Example
+//!```
+//!    use bc_algo::dag::{ghostdag::*, *};
+//!
+//!    fn main() {
+//!        let mut dag = Dag::new();
+//!        dag.add_vertex("G", (), &[]).unwrap();
+//!        dag.add_vertex("A", (), &["G"]).unwrap();
+//!        dag.add_vertex("B", (), &["G"]).unwrap();
+//!        dag.add_vertex("C", (), &["A", "B"]).unwrap();
+//!
+//!        let gd = Ghostdag::new(&dag, 0);
+//!        assert!(gd.is_blue(&"A"));
+//!        assert!(!gd.is_blue(&"B"));
+//!        assert_eq!(Some(2), gd.blue_score(&"C"));
+//!        assert_eq!(&["G", "A", "C"], gd.selected_chain());
+//!        assert_eq!(&["G", "A", "B", "C"], gd.order());
+//!    }
+//!```
+
+use super::{Dag, VertexId};
+use std::collections::{HashMap, HashSet};
+
+//- @selected_parent: 所有 parent 中 blue score 最大者，无 parent 时为 None
+//- @blue_score: 过去集合中蓝色区块的数量
+//- @mergeset_blues: 相对 selected parent 新增的蓝色区块，首个元素即 selected parent
+//- @mergeset_reds: 相对 selected parent 新增的红色区块
+#[derive(Debug)]
+struct GhostData<Id: VertexId> {
+    selected_parent: Option<Id>,
+    blue_score: usize,
+    mergeset_blues: Vec<Id>,
+    mergeset_reds: Vec<Id>,
+}
+
+//- @k: 传播延迟参数，决定蓝色集合中允许的最大 anticone 大小
+//- @data: 每个区块的计算结果
+//- @past: 每个区块的过去集合，用于判定两个区块是否互在对方的 anticone 中
+//- @blues: 虚拟区块视角下的全局蓝色集合
+//- @chain: 自 genesis 至 selected tip 的 selected parent 链
+//- @order: 全部区块的全序排列
+#[derive(Debug)]
+pub struct Ghostdag<Id: VertexId> {
+    k: usize,
+    data: HashMap<Id, GhostData<Id>>,
+    past: HashMap<Id, HashSet<Id>>,
+
+    blues: HashSet<Id>,
+    chain: Vec<Id>,
+    order: Vec<Id>,
+}
+
+impl<Id: VertexId> Ghostdag<Id> {
+    ///#### 对整个 DAG 执行 GHOSTDAG
+    ///- @dag[in]: 区块 DAG
+    ///- @k[in]: 传播延迟参数
+    pub fn new<V>(dag: &Dag<Id, V>, k: usize) -> Ghostdag<Id> {
+        let mut res = Ghostdag {
+            k,
+            data: HashMap::with_capacity(dag.len()),
+            past: HashMap::with_capacity(dag.len()),
+            blues: HashSet::new(),
+            chain: vec![],
+            order: Vec::with_capacity(dag.len()),
+        };
+
+        for id in dag.topological_sort() {
+            let parents = dag.get(&id).unwrap().parents();
+            let mut past = HashSet::new();
+            for p in parents {
+                past.extend(res.past[p].iter().cloned());
+                past.insert(p.clone());
+            }
+
+            let gd = res.calc(parents, &past);
+            res.past.insert(id.clone(), past);
+            res.data.insert(id, gd);
+        }
+
+        if dag.is_empty() {
+            return res;
+        }
+
+        //虚拟区块：以所有 tips 为 parent，过去集合即全部区块
+        let all = dag.ids().into_iter().collect::<HashSet<Id>>();
+        let virtual_gd = res.calc(&dag.tips(), &all);
+
+        res.blues = res.blue_past(&virtual_gd);
+
+        let mut cur = virtual_gd.selected_parent.clone();
+        while let Some(id) = cur {
+            cur = res.data[&id].selected_parent.clone();
+            res.chain.push(id);
+        }
+        res.chain.reverse();
+
+        res.order.push(res.chain[0].clone());
+        for id in res.chain.iter().skip(1) {
+            res.order.extend(res.mergeset_rest(&res.data[id]));
+            res.order.push(id.clone());
+        }
+        let rest = res.mergeset_rest(&virtual_gd);
+        res.order.extend(rest);
+
+        res
+    }
+
+    //#### 计算某个区块(或虚拟区块)的 GHOSTDAG 数据
+    //- @parents[in]: 该区块的所有 parent
+    //- @past[in]: 该区块的过去集合
+    fn calc(&self, parents: &[Id], past: &HashSet<Id>) -> GhostData<Id> {
+        let sp = match parents.iter().max_by(|a, b| {
+            self.data[*a]
+                .blue_score
+                .cmp(&self.data[*b].blue_score)
+                .then_with(|| b.cmp(a))
+        }) {
+            Some(sp) => sp,
+            None => {
+                return GhostData {
+                    selected_parent: None,
+                    blue_score: 0,
+                    mergeset_blues: vec![],
+                    mergeset_reds: vec![],
+                };
+            }
+        };
+
+        let sp_past = &self.past[sp];
+        let mut mergeset = past
+            .iter()
+            .filter(|id| *id != sp && !sp_past.contains(id))
+            .collect::<Vec<&Id>>();
+        mergeset.sort_by(|a, b| {
+            self.data[*a]
+                .blue_score
+                .cmp(&self.data[*b].blue_score)
+                .then_with(|| a.cmp(b))
+        });
+
+        let mut blues = self.blue_past(&self.data[sp]);
+        blues.insert(sp.clone());
+
+        let mut res = GhostData {
+            selected_parent: Some(sp.clone()),
+            blue_score: 0,
+            mergeset_blues: vec![sp.clone()],
+            mergeset_reds: vec![],
+        };
+
+        for id in mergeset {
+            if self.can_be_blue(id, &blues) {
+                blues.insert(id.clone());
+                res.mergeset_blues.push(id.clone());
+            } else {
+                res.mergeset_reds.push(id.clone());
+            }
+        }
+
+        res.blue_score = self.data[sp].blue_score + res.mergeset_blues.len();
+        res
+    }
+
+    //#### 检查将 id 加入 blues 之后，是否仍然是一个 k-cluster
+    //- 新区块自身的 anticone 中至多包含 k 个蓝色区块；
+    //- 与其互为 anticone 的每个蓝色区块，加入新区块后其 anticone 中的蓝色区块数量亦不能超过 k
+    fn can_be_blue(&self, id: &Id, blues: &HashSet<Id>) -> bool {
+        let anticone_blues = blues
+            .iter()
+            .filter(|b| self.is_anticone(id, b))
+            .collect::<Vec<&Id>>();
+        if self.k < anticone_blues.len() {
+            return false;
+        }
+
+        anticone_blues
+            .iter()
+            .all(|b| blues.iter().filter(|x| self.is_anticone(b, x)).count() < self.k)
+    }
+
+    #[inline(always)]
+    fn is_anticone(&self, a: &Id, b: &Id) -> bool {
+        a != b && !self.past[a].contains(b) && !self.past[b].contains(a)
+    }
+
+    //#### 沿 selected parent 链回溯，汇总过去集合中的全部蓝色区块
+    fn blue_past(&self, gd: &GhostData<Id>) -> HashSet<Id> {
+        let mut res = gd.mergeset_blues.iter().cloned().collect::<HashSet<Id>>();
+        let mut cur = gd.selected_parent.as_ref();
+        while let Some(id) = cur {
+            let me = &self.data[id];
+            res.extend(me.mergeset_blues.iter().cloned());
+            cur = me.selected_parent.as_ref();
+        }
+        res
+    }
+
+    //#### mergeset 中除 selected parent 之外的所有区块，按 (blue score, Id) 升序排列
+    fn mergeset_rest(&self, gd: &GhostData<Id>) -> Vec<Id> {
+        let mut res = gd
+            .mergeset_blues
+            .iter()
+            .skip(1)
+            .chain(gd.mergeset_reds.iter())
+            .cloned()
+            .collect::<Vec<Id>>();
+        res.sort_by(|a, b| {
+            self.data[a]
+                .blue_score
+                .cmp(&self.data[b].blue_score)
+                .then_with(|| a.cmp(b))
+        });
+        res
+    }
+
+    ///- #: 传播延迟参数 k
+    #[inline(always)]
+    pub fn k(&self) -> usize {
+        self.k
+    }
+
+    ///- #: 全局蓝色集合
+    #[inline(always)]
+    pub fn blue_set(&self) -> &HashSet<Id> {
+        &self.blues
+    }
+
+    ///- #: 区块是否属于全局蓝色集合
+    #[inline(always)]
+    pub fn is_blue(&self, id: &Id) -> bool {
+        self.blues.contains(id)
+    }
+
+    ///- #: 区块过去集合中蓝色区块的数量，区块不存在时返回 None
+    #[inline(always)]
+    pub fn blue_score(&self, id: &Id) -> Option<usize> {
+        self.data.get(id).map(|gd| gd.blue_score)
+    }
+
+    ///- #: 区块的 selected parent，区块不存在或没有 parent 时返回 None
+    #[inline(always)]
+    pub fn selected_parent(&self, id: &Id) -> Option<&Id> {
+        self.data.get(id).and_then(|gd| gd.selected_parent.as_ref())
+    }
+
+    ///- #: 自 genesis 至 selected tip 的 selected parent 链
+    #[inline(always)]
+    pub fn selected_chain(&self) -> &[Id] {
+        &self.chain
+    }
+
+    ///- #: 全部区块的全序排列
+    #[inline(always)]
+    pub fn order(&self) -> &[Id] {
+        &self.order
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn build(edges: &[(&'static str, &[&'static str])]) -> Dag<&'static str, ()> {
+        let mut dag = Dag::new();
+        for (id, parents) in edges {
+            dag.add_vertex(*id, (), parents).unwrap();
+        }
+        dag
+    }
+
+    fn blues(gd: &Ghostdag<&'static str>) -> Vec<&'static str> {
+        let mut res = gd.blue_set().iter().cloned().collect::<Vec<_>>();
+        res.sort();
+        res
+    }
+
+    #[test]
+    fn chain() {
+        let dag = build(&[("G", &[]), ("A", &["G"]), ("B", &["A"]), ("C", &["B"])]);
+        let gd = Ghostdag::new(&dag, 0);
+        assert_eq!(vec!["A", "B", "C", "G"], blues(&gd));
+        assert_eq!(Some(3), gd.blue_score(&"C"));
+        assert_eq!(Some(&"B"), gd.selected_parent(&"C"));
+        assert_eq!(None, gd.selected_parent(&"G"));
+        assert_eq!(&["G", "A", "B", "C"], gd.selected_chain());
+        assert_eq!(&["G", "A", "B", "C"], gd.order());
+
+        let gd = Ghostdag::new(&Dag::<u8, ()>::new(), 3);
+        assert!(gd.order().is_empty());
+        assert!(gd.blue_set().is_empty());
+    }
+
+    //    G
+    //  / | \
+    // A  B  C
+    //  \ | /
+    //    D
+    #[test]
+    fn k_cluster() {
+        let dag = build(&[
+            ("G", &[]),
+            ("A", &["G"]),
+            ("B", &["G"]),
+            ("C", &["G"]),
+            ("D", &["A", "B", "C"]),
+        ]);
+
+        let gd = Ghostdag::new(&dag, 0);
+        assert_eq!(vec!["A", "D", "G"], blues(&gd));
+        assert_eq!(Some(2), gd.blue_score(&"D"));
+
+        let gd = Ghostdag::new(&dag, 1);
+        assert_eq!(vec!["A", "B", "D", "G"], blues(&gd));
+        assert_eq!(Some(3), gd.blue_score(&"D"));
+
+        let gd = Ghostdag::new(&dag, 2);
+        assert_eq!(vec!["A", "B", "C", "D", "G"], blues(&gd));
+        assert_eq!(Some(4), gd.blue_score(&"D"));
+        assert_eq!(&["G", "A", "D"], gd.selected_chain());
+        assert_eq!(&["G", "A", "B", "C", "D"], gd.order());
+    }
+
+    //honest blocks are well connected, the attacker withholds a private chain X1-X2-X3
+    //      G
+    //    / | \
+    //  H1  H2  X1
+    //  | \/ |   |
+    //  | /\ |  X2
+    //  H3  H4   |
+    //   \ /    X3
+    //    H5
+    #[test]
+    fn withheld_chain() {
+        let dag = build(&[
+            ("G", &[]),
+            ("H1", &["G"]),
+            ("H2", &["G"]),
+            ("H3", &["H1", "H2"]),
+            ("H4", &["H1", "H2"]),
+            ("H5", &["H3", "H4"]),
+            ("X1", &["G"]),
+            ("X2", &["X1"]),
+            ("X3", &["X2"]),
+        ]);
+
+        let gd = Ghostdag::new(&dag, 1);
+        assert_eq!(vec!["G", "H1", "H2", "H3", "H4", "H5"], blues(&gd));
+        assert_eq!(Some(5), gd.blue_score(&"H5"));
+        assert_eq!(Some(3), gd.blue_score(&"X3"));
+        assert_eq!(&["G", "H1", "H3", "H5"], gd.selected_chain());
+        assert_eq!(
+            &["G", "H1", "H2", "H3", "H4", "H5", "X1", "X2", "X3"],
+            gd.order()
+        );
+
+        //every block is ordered after its whole past
+        for (i, id) in gd.order().iter().enumerate() {
+            for p in dag.ancestors(id).unwrap() {
+                assert!(gd.order().iter().position(|x| *x == p).unwrap() < i);
+            }
+        }
+    }
+
+    //the k = 3 example DAG of the PHANTOM paper: E, H and L end up red
+    //ties between equal blue scores go to the smaller id, so F extends B rather than C
+    #[test]
+    fn phantom_paper() {
+        let dag = build(&[
+            ("G", &[]),
+            ("B", &["G"]),
+            ("C", &["G"]),
+            ("D", &["G"]),
+            ("E", &["G"]),
+            ("F", &["B", "C"]),
+            ("H", &["E"]),
+            ("I", &["C", "D"]),
+            ("J", &["F", "D"]),
+            ("K", &["J", "I", "E"]),
+            ("L", &["F"]),
+            ("M", &["L", "K"]),
+        ]);
+
+        let gd = Ghostdag::new(&dag, 3);
+        assert_eq!(
+            vec!["B", "C", "D", "F", "G", "I", "J", "K", "M"],
+            blues(&gd)
+        );
+        let scores = [
+            ("G", 0),
+            ("B", 1),
+            ("C", 1),
+            ("D", 1),
+            ("E", 1),
+            ("F", 3),
+            ("H", 2),
+            ("I", 3),
+            ("J", 5),
+            ("K", 7),
+            ("L", 4),
+            ("M", 8),
+        ];
+        for (id, score) in scores.iter() {
+            assert_eq!(Some(*score), gd.blue_score(id), "{}", id);
+        }
+        assert_eq!(&["G", "B", "F", "J", "K", "M"], gd.selected_chain());
+        assert_eq!(
+            &["G", "B", "C", "F", "D", "J", "E", "I", "K", "L", "M", "H"],
+            gd.order()
+        );
+    }
+}
@@ -0,0 +1,404 @@
+//! ## Merkle-DAG
+//!
+//! #### 算法说明
+//! - 内容寻址的 DAG：顶点 Id 即其内容的哈希，内容包括顶点数据与其所有 parent 的 Id(IPFS/git 风格)；
+//! - 哈希原象编码：`len(value) as u64 LE || value || parent_0 || parent_1 || ...`，parents 按升序排列并去重，
+//!   长度前缀用于消除数据与 parent Id 之间的拼接歧义；
+//! - 任何顶点的 Id 都承诺了其全部祖先，使用抗碰撞的哈希时环路在构造上不可能出现，自定义的弱哈希则不然，导入时须检查；
+//! - 从其它节点收到的子图，首先逐一校验哈希，再计算尚缺失的 parent(仅限直接引用的一层，
+//!   更深层的祖先只有在取回这些顶点之后才能获知)，全部齐备后按依赖顺序导入。
+//!
+//! #### 应用场景
+//! - 区块/交易同步、IPFS 式的内容分发，对端只需提供根 Id 即可校验收到的全部数据。
+//!
+//! #### 实现属性
+//! - <font color=Green>√</font> 多线程安全
+//! - <font color=Green>√</font> 无 unsafe 代码
+//!
+//! #### Example
+//!```
+//!    use bc_algo::dag::merkle::*;
+//!
+//!    fn main() {
+//!        let mut local = MerkleDag::default();
+//!        let g = local.put(b"genesis".to_vec(), &[]).unwrap();
+//!        let a = local.put(b"a".to_vec(), &[g.clone()]).unwrap();
+//!        let b = local.put(b"b".to_vec(), &[g.clone()]).unwrap();
+//!        let c = local.put(b"c".to_vec(), &[a.clone(), b.clone()]).unwrap();
+//!
+//!        let mut remote = MerkleDag::default();
+//!        remote.put(b"genesis".to_vec(), &[]).unwrap();
+//!
+//!        let received = local.subgraph(&c).unwrap();
+//!        assert!(remote.verify(&received).is_ok());
+//!        assert_eq!(2, remote.missing(&received[3..]).len());
+//!
+//!        remote.import(received).unwrap();
+//!        assert!(remote.contains(&c));
+//!    }
+//!```
+
+use super::{error::XErr, Dag};
+use crate::mpt::traits::AsBytes;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::ops::Deref;
+
+pub type HashSig = Box<[u8]>;
+pub type HashFunc = fn(&[&[u8]]) -> HashSig;
+
+///在节点之间传输的顶点形式
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MerkleVertex<V: AsBytes> {
+    pub id: HashSig,
+    pub value: V,
+    pub parents: Vec<HashSig>,
+}
+
+//- @dag: 以哈希值为 Id 的底层 DAG
+//- @hash: 哈希函数指针
+pub struct MerkleDag<V: AsBytes> {
+    dag: Dag<HashSig, V>,
+    hash: HashFunc,
+}
+
+#[inline(always)]
+fn sha256(item: &[&[u8]]) -> HashSig {
+    use ring::digest::{Context, SHA256};
+
+    let mut context = Context::new(&SHA256);
+    for x in item {
+        context.update(x);
+    }
+    context.finish().as_ref().to_vec().into_boxed_slice()
+}
+
+impl<V: AsBytes> Default for MerkleDag<V> {
+    ///#### 使用预置哈希函数(SHA256)初始化
+    fn default() -> MerkleDag<V> {
+        MerkleDag::new(sha256)
+    }
+}
+
+impl<V: AsBytes> Deref for MerkleDag<V> {
+    type Target = Dag<HashSig, V>;
+    fn deref(&self) -> &Self::Target {
+        &self.dag
+    }
+}
+
+impl<V: AsBytes> MerkleDag<V> {
+    ///#### 使用自定义哈希函数初始化
+    pub fn new(hash: HashFunc) -> MerkleDag<V> {
+        MerkleDag {
+            dag: Dag::new(),
+            hash,
+        }
+    }
+
+    ///#### 计算顶点 Id
+    ///- @value[in]: 顶点数据
+    ///- @parents[in]: 所有 parent 的 Id，须已按升序排列并去重
+    fn calc_id(&self, value: &V, parents: &[HashSig]) -> HashSig {
+        let bytes = value.as_bytes();
+        let len = (bytes.len() as u64).to_le_bytes();
+
+        let mut item = Vec::with_capacity(2 + parents.len());
+        item.push(&len[..]);
+        item.push(&bytes[..]);
+        parents.iter().for_each(|p| item.push(&p[..]));
+
+        (self.hash)(&item)
+    }
+
+    ///#### 插入新顶点
+    ///- #: 新顶点的 Id；相同内容重复插入视为成功，返回已有的 Id
+    ///- @value: 顶点数据
+    ///- @parents[in]: 所有 parent 的 Id，必须已存在
+    pub fn put(&mut self, value: V, parents: &[HashSig]) -> Result<HashSig, XErr<HashSig>> {
+        let mut parents = parents.to_vec();
+        parents.sort();
+        parents.dedup();
+
+        let id = self.calc_id(&value, &parents);
+        if self.dag.contains(&id) {
+            return Ok(id);
+        }
+
+        self.dag.add_vertex(id.clone(), value, &parents)?;
+        Ok(id)
+    }
+
+    ///#### 导出某个顶点及其全部祖先，用于发送给其它节点
+    ///- #: 按拓扑顺序排列，任一顶点都排在其全部后代之前
+    ///- @id[in]: 子图的根(最新的顶点)
+    pub fn subgraph(&self, id: &HashSig) -> Result<Vec<MerkleVertex<V>>, XErr<HashSig>> {
+        let mut set = self.dag.ancestors(id)?;
+        set.insert(id.clone());
+
+        Ok(self
+            .dag
+            .topological_sort()
+            .into_iter()
+            .filter(|i| set.contains(i))
+            .map(|i| {
+                let v = self.dag.get(&i).unwrap();
+                MerkleVertex {
+                    id: i,
+                    value: v.value().clone(),
+                    parents: v.parents().to_vec(),
+                }
+            })
+            .collect())
+    }
+
+    ///#### 校验收到的子图是否内部一致
+    ///- 每个顶点的 Id 都必须等于其内容的哈希，parents 须按升序排列且无重复
+    ///- @subgraph[in]: 从其它节点收到的顶点集合，顺序任意
+    pub fn verify(&self, subgraph: &[MerkleVertex<V>]) -> Result<(), XErr<HashSig>> {
+        for v in subgraph {
+            if v.parents.windows(2).any(|p| p[0] >= p[1])
+                || v.id != self.calc_id(&v.value, &v.parents)
+            {
+                return Err(XErr::HashMismatch(v.id.clone()));
+            }
+        }
+        Ok(())
+    }
+
+    ///#### 计算需要向对端请求的最小顶点集合
+    ///- #: 被子图中的顶点直接引用，但本地与子图中均不存在的 Id，按升序排列
+    ///- @subgraph[in]: 从其它节点收到的顶点集合，顺序任意
+    pub fn missing(&self, subgraph: &[MerkleVertex<V>]) -> Vec<HashSig> {
+        let received = subgraph
+            .iter()
+            .map(|v| &v.id)
+            .collect::<HashSet<&HashSig>>();
+
+        let mut res = subgraph
+            .iter()
+            .flat_map(|v| v.parents.iter())
+            .filter(|p| !received.contains(p) && !self.dag.contains(p))
+            .cloned()
+            .collect::<Vec<HashSig>>();
+        res.sort();
+        res.dedup();
+        res
+    }
+
+    ///#### 校验并导入收到的子图
+    ///- 存在缺失的 parent 时不导入任何顶点，返回 XErr::NotExists(首个缺失的 Id)
+    ///- 自定义的弱哈希可能使 Id 成环，此时同样不导入任何顶点，返回 XErr::Cycle(parent, child)，该边位于环上
+    ///- #: 新导入的顶点数量
+    ///- @subgraph: 从其它节点收到的顶点集合，顺序任意
+    pub fn import(&mut self, subgraph: Vec<MerkleVertex<V>>) -> Result<usize, XErr<HashSig>> {
+        self.verify(&subgraph)?;
+        if let Some(id) = self.missing(&subgraph).into_iter().next() {
+            return Err(XErr::NotExists(id));
+        }
+
+        let mut todo = subgraph
+            .into_iter()
+            .filter(|v| !self.dag.contains(&v.id))
+            .map(|v| (v.id.clone(), v))
+            .collect::<HashMap<HashSig, MerkleVertex<V>>>();
+
+        //Kahn 算法排定导入次序：入度只计尚未导入的 parent，入度同时归零的顶点按 Id 升序
+        let mut indegree = HashMap::with_capacity(todo.len());
+        let mut children = HashMap::<&HashSig, Vec<&HashSig>>::new();
+        for v in todo.values() {
+            let pending = v.parents.iter().filter(|p| todo.contains_key(*p));
+            indegree.insert(&v.id, pending.clone().count());
+            pending.for_each(|p| children.entry(p).or_default().push(&v.id));
+        }
+        let mut ready = indegree
+            .iter()
+            .filter(|(_, d)| 0 == **d)
+            .map(|(id, _)| *id)
+            .collect::<BTreeSet<&HashSig>>();
+
+        let mut order = Vec::with_capacity(todo.len());
+        while let Some(id) = ready.pop_first() {
+            for c in children.get(id).into_iter().flatten() {
+                let d = indegree.get_mut(*c).unwrap();
+                *d -= 1;
+                if 0 == *d {
+                    ready.insert(*c);
+                }
+            }
+            order.push(id.clone());
+        }
+
+        //剩余的顶点各有至少一个未排定的 parent，从最小的一个沿 parents 回溯，必然走回已经过的顶点
+        if order.len() < todo.len() {
+            let blocked = |id: &HashSig| {
+                todo[id]
+                    .parents
+                    .iter()
+                    .filter(|p| 0 < indegree.get(*p).copied().unwrap_or(0))
+                    .min()
+                    .cloned()
+            };
+            let mut seen = HashSet::new();
+            let mut cur = indegree
+                .iter()
+                .filter(|(_, d)| 0 < **d)
+                .map(|(id, _)| (*id).clone())
+                .min();
+            while let Some(child) = cur {
+                seen.insert(child.clone());
+                match blocked(&child) {
+                    Some(p) if seen.contains(&p) => return Err(XErr::Cycle(p, child)),
+                    p => cur = p,
+                }
+            }
+            return Err(XErr::Unknown);
+        }
+
+        let order = order
+            .into_iter()
+            .map(|id| todo.remove(&id).unwrap())
+            .collect::<Vec<_>>();
+        let cnt = order.len();
+        for v in order {
+            self.dag.add_vertex(v.id, v.value, &v.parents)?;
+        }
+        Ok(cnt)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::slice::from_ref;
+
+    fn sample() -> (MerkleDag<String>, Vec<HashSig>) {
+        let mut md = MerkleDag::default();
+        let g = md.put("genesis".to_owned(), &[]).unwrap();
+        let a = md.put("a".to_owned(), from_ref(&g)).unwrap();
+        let b = md.put("b".to_owned(), from_ref(&g)).unwrap();
+        let c = md.put("c".to_owned(), &[b.clone(), a.clone()]).unwrap();
+        let d = md.put("d".to_owned(), from_ref(&c)).unwrap();
+        (md, vec![g, a, b, c, d])
+    }
+
+    #[test]
+    fn put() {
+        let (mut md, ids) = sample();
+        assert_eq!(5, md.len());
+
+        //same content, same id
+        assert_eq!(ids[0], md.put("genesis".to_owned(), &[]).unwrap());
+        assert_eq!(
+            ids[3],
+            md.put(
+                "c".to_owned(),
+                &[ids[1].clone(), ids[2].clone(), ids[1].clone()]
+            )
+            .unwrap()
+        );
+        assert_eq!(5, md.len());
+
+        //parents are committed into the id
+        let x = md.put("c".to_owned(), &[ids[1].clone()]).unwrap();
+        assert_ne!(ids[3], x);
+        assert_eq!(6, md.len());
+
+        assert!(md.put("x".to_owned(), &[sha256(&[b"x"])]).is_err());
+        let mut tips = vec![ids[4].clone(), x];
+        tips.sort();
+        assert_eq!(tips, md.tips());
+    }
+
+    #[test]
+    fn verify() {
+        let (md, ids) = sample();
+        let sub = md.subgraph(&ids[4]).unwrap();
+        assert_eq!(5, sub.len());
+        assert_eq!(ids[0], sub[0].id);
+        assert!(md.verify(&sub).is_ok());
+
+        let mut bad = sub.clone();
+        bad[3].value = "evil".to_owned();
+        assert!(md.verify(&bad).is_err());
+
+        let mut bad = sub.clone();
+        bad[3].parents.reverse();
+        assert!(md.verify(&bad).is_err());
+
+        let mut bad = sub;
+        bad[3].parents.pop();
+        assert!(md.verify(&bad).is_err());
+    }
+
+    #[test]
+    fn sync() {
+        let (md, ids) = sample();
+        let mut peer = MerkleDag::<String>::default();
+        peer.put("genesis".to_owned(), &[]).unwrap();
+
+        let sub = md.subgraph(&ids[4]).unwrap();
+
+        //only d received, c is missing
+        assert_eq!(vec![ids[3].clone()], peer.missing(&sub[4..]));
+        assert!(peer.import(sub[4..].to_vec()).is_err());
+        assert_eq!(1, peer.len());
+
+        //c and d received, a and b are missing
+        let mut want = vec![ids[1].clone(), ids[2].clone()];
+        want.sort();
+        assert_eq!(want, peer.missing(&sub[3..]));
+
+        //everything above genesis received, nothing is missing
+        assert!(peer.missing(&sub[1..]).is_empty());
+
+        let mut sub = sub;
+        sub.reverse();
+        assert_eq!(4, peer.import(sub).unwrap());
+        assert_eq!(5, peer.len());
+        assert_eq!(md.topological_sort(), peer.topological_sort());
+    }
+
+    #[test]
+    fn weak_hash_cycle() {
+        //ignores the parents, so two vertices can name each other
+        fn weak(item: &[&[u8]]) -> HashSig {
+            item[1].to_vec().into_boxed_slice()
+        }
+        let vertex = |id: &HashSig, parent: &HashSig| MerkleVertex {
+            id: id.clone(),
+            value: String::from_utf8(id.to_vec()).unwrap(),
+            parents: vec![parent.clone()],
+        };
+        let mut md = MerkleDag::<String>::new(weak);
+        let (a, b, c): (HashSig, HashSig, HashSig) = (
+            b"a".to_vec().into(),
+            b"b".to_vec().into(),
+            b"0".to_vec().into(),
+        );
+        //c hangs below the cycle and is the smallest id, the reported edge is still on the cycle
+        let sub = vec![vertex(&a, &b), vertex(&b, &a), vertex(&c, &a)];
+        assert!(md.verify(&sub).is_ok());
+        assert!(md.missing(&sub).is_empty());
+        match md.import(sub) {
+            Err(XErr::Cycle(p, c)) => assert_eq!((a, b), (p, c)),
+            _ => panic!("a cycle must not be imported"),
+        }
+        assert_eq!(0, md.len());
+    }
+
+    #[test]
+    fn import_chain() {
+        let mut md = MerkleDag::default();
+        let mut tip = md.put(0u32.to_string(), &[]).unwrap();
+        for i in 1..5000u32 {
+            tip = md.put(i.to_string(), from_ref(&tip)).unwrap();
+        }
+
+        //a long chain received newest first is imported in one pass
+        let mut peer = MerkleDag::<String>::default();
+        let mut sub = md.subgraph(&tip).unwrap();
+        sub.reverse();
+        assert_eq!(5000, peer.import(sub).unwrap());
+        assert!(peer.contains(&tip));
+    }
+}
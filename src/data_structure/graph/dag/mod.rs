@@ -0,0 +1,437 @@
+//! ## DAG(Directed Acyclic Graph)
+//!
+//! #### 算法说明
+//! - 有向无环图，每条边由 parent 指向 child，新顶点只能引用已存在的顶点作为 parent；
+//! - 添加边时会检查 parent 是否已经是 child 的后代，以此拒绝任何可能形成环路的操作；
+//! - 拓扑排序使用 Kahn 算法，同一层级内按 Id 升序输出，结果是确定性的；
+//! - 最近公共祖先(LCA)：所有公共祖先中，不存在任何后代同为公共祖先的那些顶点，DAG 中结果可能不止一个。
+//!
+//! #### 应用场景
+//! - 区块 DAG(GHOST/PHANTOM 等)，每个区块可同时引用多个父区块。
+//!
+//! #### 实现属性
+//! - <font color=Green>√</font> 多线程安全
+//! - <font color=Green>√</font> 无 unsafe 代码
+//!
+//! #### Example
+//!```
+//!    use bc_algo::dag::*;
+//!
+//!    fn main() {
+//!        let mut dag = Dag::new();
+//!        dag.add_vertex(0, "genesis", &[]).unwrap();
+//!        dag.add_vertex(1, "a", &[0]).unwrap();
+//!        dag.add_vertex(2, "b", &[0]).unwrap();
+//!        dag.add_vertex(3, "c", &[1, 2]).unwrap();
+//!
+//!        assert!(dag.add_edge(3, 0).is_err());
+//!        assert_eq!(vec![0, 1, 2, 3], dag.topological_sort());
+//!        assert_eq!(vec![3], dag.tips());
+//!        assert_eq!(vec![0], dag.lca(&1, &2).unwrap());
+//!    }
+//!```
+
+pub mod error;
+pub mod ghostdag;
+pub mod merkle;
+
+use error::*;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt::Debug;
+use std::hash::Hash;
+
+///顶点 Id 需要满足的约束，Ord 用于保证各种遍历结果的确定性
+pub trait VertexId: Clone + Eq + Hash + Ord + Debug {}
+
+impl<T: Clone + Eq + Hash + Ord + Debug> VertexId for T {}
+
+//- @value: 顶点上承载的数据
+//- @parents: 所有直接父顶点，按 Id 升序排列
+//- @children: 所有直接子顶点，按 Id 升序排列
+#[derive(Debug)]
+pub struct Vertex<Id: VertexId, V> {
+    value: V,
+    parents: Vec<Id>,
+    children: Vec<Id>,
+}
+
+//- @vertexes: 全部顶点的集合
+#[derive(Debug)]
+pub struct Dag<Id: VertexId, V> {
+    vertexes: HashMap<Id, Vertex<Id, V>>,
+}
+
+impl<Id: VertexId, V> Default for Dag<Id, V> {
+    fn default() -> Dag<Id, V> {
+        Dag::new()
+    }
+}
+
+impl<Id: VertexId, V> Vertex<Id, V> {
+    ///- #: 顶点上承载的数据
+    #[inline(always)]
+    pub fn value(&self) -> &V {
+        &self.value
+    }
+
+    ///- #: 所有直接父顶点
+    #[inline(always)]
+    pub fn parents(&self) -> &[Id] {
+        &self.parents
+    }
+
+    ///- #: 所有直接子顶点
+    #[inline(always)]
+    pub fn children(&self) -> &[Id] {
+        &self.children
+    }
+}
+
+impl<Id: VertexId, V> Dag<Id, V> {
+    pub fn new() -> Dag<Id, V> {
+        Dag {
+            vertexes: HashMap::new(),
+        }
+    }
+
+    ///- #: 顶点总数
+    #[inline(always)]
+    pub fn len(&self) -> usize {
+        self.vertexes.len()
+    }
+
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.vertexes.is_empty()
+    }
+
+    #[inline(always)]
+    pub fn contains(&self, id: &Id) -> bool {
+        self.vertexes.contains_key(id)
+    }
+
+    ///- #: 查找成功返回顶点的引用
+    ///- @id[in]: 查找对象
+    #[inline(always)]
+    pub fn get(&self, id: &Id) -> Option<&Vertex<Id, V>> {
+        self.vertexes.get(id)
+    }
+
+    ///- #: 查找成功返回顶点数据的引用
+    ///- @id[in]: 查找对象
+    #[inline(always)]
+    pub fn value(&self, id: &Id) -> Option<&V> {
+        self.vertexes.get(id).map(|v| &v.value)
+    }
+
+    ///- #: 所有顶点 Id，按升序排列
+    pub fn ids(&self) -> Vec<Id> {
+        let mut res = self.vertexes.keys().cloned().collect::<Vec<Id>>();
+        res.sort();
+        res
+    }
+
+    ///#### 添加新顶点
+    ///- 新顶点不可能被任何已有顶点引用，故此操作永远不会形成环路
+    ///- @id[in]: 新顶点的 Id
+    ///- @value[in]: 新顶点承载的数据
+    ///- @parents[in]: 新顶点的所有父顶点，必须已存在
+    pub fn add_vertex(&mut self, id: Id, value: V, parents: &[Id]) -> Result<(), XErr<Id>> {
+        if self.contains(&id) {
+            return Err(XErr::Exists(id));
+        }
+        if let Some(p) = parents.iter().find(|p| !self.contains(p)) {
+            return Err(XErr::NotExists(p.clone()));
+        }
+
+        let mut parents = parents.to_vec();
+        parents.sort();
+        parents.dedup();
+
+        for p in parents.iter() {
+            let children = &mut self.vertexes.get_mut(p).unwrap().children;
+            let idx = children.binary_search(&id).unwrap_err();
+            children.insert(idx, id.clone());
+        }
+
+        self.vertexes.insert(
+            id,
+            Vertex {
+                value,
+                parents,
+                children: vec![],
+            },
+        );
+
+        Ok(())
+    }
+
+    ///#### 在两个已有顶点之间添加边
+    ///- 若 parent 与 child 相同，或 parent 已是 child 的后代，则拒绝添加
+    ///- @parent[in]: 被引用的顶点
+    ///- @child[in]: 引用方
+    pub fn add_edge(&mut self, parent: Id, child: Id) -> Result<(), XErr<Id>> {
+        if !self.contains(&parent) {
+            return Err(XErr::NotExists(parent));
+        }
+        if !self.contains(&child) {
+            return Err(XErr::NotExists(child));
+        }
+
+        let idx = match self.vertexes[&child].parents.binary_search(&parent) {
+            Ok(_) => return Err(XErr::EdgeExists(parent, child)),
+            Err(i) => i,
+        };
+
+        if parent == child || self.is_ancestor(&child, &parent) {
+            return Err(XErr::Cycle(parent, child));
+        }
+
+        self.vertexes
+            .get_mut(&child)
+            .unwrap()
+            .parents
+            .insert(idx, parent.clone());
+
+        let children = &mut self.vertexes.get_mut(&parent).unwrap().children;
+        let idx = children.binary_search(&child).unwrap_err();
+        children.insert(idx, child);
+
+        Ok(())
+    }
+
+    //#### 判断 a 是否是 b 的祖先(不含自身)，沿 b 的 parents 方向反向搜索
+    fn is_ancestor(&self, a: &Id, b: &Id) -> bool {
+        let mut visited = HashSet::new();
+        let mut todo = self.vertexes[b].parents.iter().collect::<Vec<&Id>>();
+        while let Some(id) = todo.pop() {
+            if id == a {
+                return true;
+            }
+            if visited.insert(id) {
+                todo.extend(self.vertexes[id].parents.iter());
+            }
+        }
+        false
+    }
+
+    ///#### 拓扑排序
+    ///- #: 所有顶点的有序集合，任一顶点都排在其全部后代之前；入度同时归零的顶点按 Id 升序输出，故结果是确定性的
+    pub fn topological_sort(&self) -> Vec<Id> {
+        let mut indegree = self
+            .vertexes
+            .iter()
+            .map(|(id, v)| (id, v.parents.len()))
+            .collect::<HashMap<&Id, usize>>();
+
+        let mut ready = indegree
+            .iter()
+            .filter(|(_, d)| 0 == **d)
+            .map(|(id, _)| *id)
+            .collect::<BTreeSet<&Id>>();
+
+        let mut res = Vec::with_capacity(self.len());
+        while let Some(id) = ready.iter().next().cloned() {
+            ready.remove(id);
+            for c in self.vertexes[id].children.iter() {
+                let d = indegree.get_mut(c).unwrap();
+                *d -= 1;
+                if 0 == *d {
+                    ready.insert(c);
+                }
+            }
+            res.push(id.clone());
+        }
+
+        res
+    }
+
+    //#### 沿 parents 或 children 方向遍历，结果不含起点自身
+    fn walk(&self, id: &Id, upward: bool) -> Result<HashSet<Id>, XErr<Id>> {
+        let next = |v: &Vertex<Id, V>| {
+            if upward {
+                v.parents.clone()
+            } else {
+                v.children.clone()
+            }
+        };
+
+        let mut todo = next(self.get(id).ok_or_else(|| XErr::NotExists(id.clone()))?);
+        let mut res = HashSet::new();
+        while let Some(i) = todo.pop() {
+            if !res.contains(&i) {
+                todo.extend(next(&self.vertexes[&i]));
+                res.insert(i);
+            }
+        }
+
+        Ok(res)
+    }
+
+    ///#### 获取所有祖先顶点(past set)
+    ///- #: 不含自身
+    ///- @id[in]: 查找对象
+    #[inline(always)]
+    pub fn ancestors(&self, id: &Id) -> Result<HashSet<Id>, XErr<Id>> {
+        self.walk(id, true)
+    }
+
+    ///#### 获取所有后代顶点(future set)
+    ///- #: 不含自身
+    ///- @id[in]: 查找对象
+    #[inline(always)]
+    pub fn descendants(&self, id: &Id) -> Result<HashSet<Id>, XErr<Id>> {
+        self.walk(id, false)
+    }
+
+    ///#### 获取所有与目标顶点之间不存在可达关系的顶点(anticone)
+    ///- #: 既非祖先、也非后代、也不是自身的顶点集合
+    ///- @id[in]: 查找对象
+    pub fn anticone(&self, id: &Id) -> Result<HashSet<Id>, XErr<Id>> {
+        let past = self.ancestors(id)?;
+        let future = self.descendants(id)?;
+        Ok(self
+            .vertexes
+            .keys()
+            .filter(|i| *i != id && !past.contains(i) && !future.contains(i))
+            .cloned()
+            .collect())
+    }
+
+    ///#### 所有没有子顶点的顶点
+    ///- #: 按 Id 升序排列
+    pub fn tips(&self) -> Vec<Id> {
+        let mut res = self
+            .vertexes
+            .iter()
+            .filter(|(_, v)| v.children.is_empty())
+            .map(|(id, _)| id.clone())
+            .collect::<Vec<Id>>();
+        res.sort();
+        res
+    }
+
+    ///#### 最近公共祖先
+    ///- 顶点自身也视为自己的祖先，故若 a 是 b 的祖先，结果即为 a
+    ///- #: 所有公共祖先中，不存在任何后代同为公共祖先的那些顶点，按 Id 升序排列；不存在公共祖先时返回空集
+    ///- @a[in]/@b[in]: 查找对象
+    pub fn lca(&self, a: &Id, b: &Id) -> Result<Vec<Id>, XErr<Id>> {
+        let mut past_a = self.ancestors(a)?;
+        past_a.insert(a.clone());
+        let mut past_b = self.ancestors(b)?;
+        past_b.insert(b.clone());
+
+        let common = past_a.intersection(&past_b).collect::<HashSet<&Id>>();
+
+        let mut res = common
+            .iter()
+            .filter(|id| {
+                !self.vertexes[id]
+                    .children
+                    .iter()
+                    .any(|c| common.contains(c))
+            })
+            .map(|id| (*id).clone())
+            .collect::<Vec<Id>>();
+        res.sort();
+
+        Ok(res)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    //    0
+    //   / \
+    //  1   2
+    //  |\ /|
+    //  | X |
+    //  |/ \|
+    //  3   4
+    //   \ /
+    //    5
+    fn sample() -> Dag<u32, String> {
+        let mut dag = Dag::new();
+        dag.add_vertex(0, "0".to_owned(), &[]).unwrap();
+        dag.add_vertex(1, "1".to_owned(), &[0]).unwrap();
+        dag.add_vertex(2, "2".to_owned(), &[0]).unwrap();
+        dag.add_vertex(3, "3".to_owned(), &[1, 2]).unwrap();
+        dag.add_vertex(4, "4".to_owned(), &[2, 1]).unwrap();
+        dag.add_vertex(5, "5".to_owned(), &[3, 4]).unwrap();
+        dag
+    }
+
+    fn sorted(set: HashSet<u32>) -> Vec<u32> {
+        let mut res = set.into_iter().collect::<Vec<u32>>();
+        res.sort();
+        res
+    }
+
+    #[test]
+    fn add() {
+        let mut dag = sample();
+        assert_eq!(6, dag.len());
+        assert_eq!("3", dag.value(&3).unwrap());
+        assert_eq!(&[1, 2], dag.get(&4).unwrap().parents());
+        assert_eq!(&[3, 4], dag.get(&2).unwrap().children());
+
+        assert!(dag.add_vertex(5, "x".to_owned(), &[]).is_err());
+        assert!(dag.add_vertex(6, "x".to_owned(), &[9]).is_err());
+        assert_eq!(6, dag.len());
+
+        assert!(dag.add_edge(5, 0).is_err());
+        assert!(dag.add_edge(4, 1).is_err());
+        assert!(dag.add_edge(3, 3).is_err());
+        assert!(dag.add_edge(1, 3).is_err());
+        dag.add_edge(3, 4).unwrap();
+        assert!(dag.add_edge(4, 3).is_err());
+        assert_eq!(&[1, 2, 3], dag.get(&4).unwrap().parents());
+    }
+
+    #[test]
+    fn topological_sort() {
+        let mut dag = sample();
+        assert_eq!(vec![0, 1, 2, 3, 4, 5], dag.topological_sort());
+
+        dag.add_edge(4, 3).unwrap();
+        let order = dag.topological_sort();
+        assert_eq!(vec![0, 1, 2, 4, 3, 5], order);
+        for id in dag.ids() {
+            let me = order.iter().position(|i| *i == id).unwrap();
+            for c in dag.get(&id).unwrap().children() {
+                assert!(me < order.iter().position(|i| i == c).unwrap());
+            }
+        }
+    }
+
+    #[test]
+    fn past_and_future() {
+        let mut dag = sample();
+        assert_eq!(vec![0, 1, 2], sorted(dag.ancestors(&3).unwrap()));
+        assert_eq!(vec![3, 4, 5], sorted(dag.descendants(&1).unwrap()));
+        assert_eq!(vec![4], sorted(dag.anticone(&3).unwrap()));
+        assert!(dag.ancestors(&0).unwrap().is_empty());
+        assert!(dag.descendants(&9).is_err());
+
+        dag.add_vertex(6, "6".to_owned(), &[1]).unwrap();
+        assert_eq!(vec![2, 3, 4, 5], sorted(dag.anticone(&6).unwrap()));
+    }
+
+    #[test]
+    fn tips_and_lca() {
+        let mut dag = sample();
+        assert_eq!(vec![5], dag.tips());
+        assert_eq!(vec![1, 2], dag.lca(&3, &4).unwrap());
+        assert_eq!(vec![0], dag.lca(&1, &2).unwrap());
+        assert_eq!(vec![3], dag.lca(&3, &5).unwrap());
+
+        dag.add_vertex(6, "6".to_owned(), &[1]).unwrap();
+        dag.add_vertex(7, "7".to_owned(), &[]).unwrap();
+        assert_eq!(vec![5, 6, 7], dag.tips());
+        assert_eq!(vec![1], dag.lca(&6, &5).unwrap());
+        assert!(dag.lca(&6, &7).unwrap().is_empty());
+        assert!(dag.lca(&6, &8).is_err());
+    }
+}
@@ -0,0 +1,625 @@
+//! ## 布隆过滤器
+//!
+//! #### 算法说明
+//! - BIP37 bloom filter，与比特币 Core 的 CBloomFilter 逐比特兼容；
+//! - 第 i 个哈希函数：MurmurHash3(i * 0xFBA4C795 + nTweak, data) % (nFilterBytes * 8)；
+//! - 参数计算：nFilterBytes = min(-n·ln(p)/(ln2)^2, 36000 * 8) / 8，nHashFuncs = min(nFilterBytes * 8 / n * ln2, 50)，
+//!   其中 nFilterBytes * 8 / n 为整数除法，与 Core 保持一致；
+//! - 交易匹配规则：txid；每个输出脚本中的每一段数据推送(命中后按 nFlags 决定是否将该输出的 outpoint 加入过滤器)；
+//!   每个输入引用的 outpoint；每个输入脚本中的每一段数据推送；
+//! - nFlags：BLOOM_UPDATE_NONE 从不更新，BLOOM_UPDATE_ALL 总是更新，BLOOM_UPDATE_P2PUBKEY_ONLY 仅当输出为 P2PK 或裸多签时更新。
+//!
+//! #### 应用场景
+//! - SPV 轻节点通过 `filterload` 消息将过滤器发送给全节点，全节点只转发与之匹配的交易及 merkleblock。
+//!
+//! #### 实现属性
+//! - <font color=Green>√</font> 多线程安全
+//! - <font color=Green>√</font> 无 unsafe 代码
+//!
+
+use std::f64::consts::LN_2;
+
+const LN2SQUARED: f64 = LN_2 * LN_2;
+
+///filterload 消息中过滤器的最大字节数
+pub const MAX_BLOOM_FILTER_SIZE: usize = 36000;
+///最大哈希次数
+pub const MAX_HASH_FUNCS: u32 = 50;
+
+pub const BLOOM_UPDATE_NONE: u8 = 0;
+pub const BLOOM_UPDATE_ALL: u8 = 1;
+pub const BLOOM_UPDATE_P2PUBKEY_ONLY: u8 = 2;
+pub const BLOOM_UPDATE_MASK: u8 = 3;
+
+const OP_PUSHDATA1: u8 = 0x4c;
+const OP_PUSHDATA2: u8 = 0x4d;
+const OP_PUSHDATA4: u8 = 0x4e;
+const OP_1: u8 = 0x51;
+const OP_16: u8 = 0x60;
+const OP_CHECKSIG: u8 = 0xac;
+const OP_CHECKMULTISIG: u8 = 0xae;
+
+//- @data: 索引数组
+//- @hash_funcs: 哈希次数，即 nHashFuncs
+//- @tweak: 哈希种子的随机偏移，即 nTweak
+//- @flags: 匹配时的更新策略，即 nFlags
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BloomFilter {
+    data: Vec<u8>,
+    hash_funcs: u32,
+    tweak: u32,
+    flags: u8,
+}
+
+///- @txid: 被引用交易的哈希，内部字节序(即直接由 double-SHA256 得到的顺序)
+///- @vout: 输出索引
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutPoint {
+    pub txid: [u8; 32],
+    pub vout: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TxIn {
+    pub prevout: OutPoint,
+    pub script_sig: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TxOut {
+    pub value: u64,
+    pub script_pubkey: Vec<u8>,
+}
+
+///匹配所需的交易信息，txid 由调用方给出，内部字节序
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Transaction {
+    pub txid: [u8; 32],
+    pub inputs: Vec<TxIn>,
+    pub outputs: Vec<TxOut>,
+}
+
+impl OutPoint {
+    ///- #: txid || vout(LE)，与比特币的 COutPoint 序列化格式一致
+    pub fn to_bytes(&self) -> [u8; 36] {
+        let mut res = [0; 36];
+        res[..32].copy_from_slice(&self.txid);
+        res[32..].copy_from_slice(&self.vout.to_le_bytes());
+        res
+    }
+}
+
+impl BloomFilter {
+    ///#### 按 BIP37 规则初始化
+    ///- @elements[in]: 预期被索引的元素总量，须大于 0
+    ///- @fp_rate[in]: 目标误判率
+    ///- @tweak[in]: nTweak
+    ///- @flags[in]: BLOOM_UPDATE_*
+    pub fn new(elements: u32, fp_rate: f64, tweak: u32, flags: u8) -> BloomFilter {
+        assert!(0 < elements, "elements must be positive");
+
+        let bits = (-1.0 / LN2SQUARED * f64::from(elements) * fp_rate.ln()) as usize;
+        let bytes = bits.min(MAX_BLOOM_FILTER_SIZE * 8) / 8;
+        let hash_funcs = ((bytes * 8 / elements as usize) as f64 * LN_2) as u32;
+
+        BloomFilter {
+            data: vec![0; bytes],
+            hash_funcs: hash_funcs.min(MAX_HASH_FUNCS),
+            tweak,
+            flags,
+        }
+    }
+
+    #[inline(always)]
+    fn hash(&self, n: u32, item: &[u8]) -> usize {
+        let seed = n.wrapping_mul(0xFBA4_C795).wrapping_add(self.tweak);
+        murmur3(seed, item) as usize % (self.data.len() * 8)
+    }
+
+    ///- @item[in]: 要添加的元素
+    pub fn insert(&mut self, item: &[u8]) {
+        if self.data.is_empty() {
+            return;
+        }
+        for i in 0..self.hash_funcs {
+            let idx = self.hash(i, item);
+            self.data[idx >> 3] |= 1 << (7 & idx);
+        }
+    }
+
+    ///- @item[in]: 要查找的元素
+    ///- #: 过滤器为空时总是返回 true，与 Core 一致(CVE-2013-5700)
+    pub fn contains(&self, item: &[u8]) -> bool {
+        if self.data.is_empty() {
+            return true;
+        }
+        (0..self.hash_funcs).all(|i| {
+            let idx = self.hash(i, item);
+            0 < self.data[idx >> 3] & (1 << (7 & idx))
+        })
+    }
+
+    #[inline(always)]
+    pub fn insert_outpoint(&mut self, outpoint: &OutPoint) {
+        self.insert(&outpoint.to_bytes());
+    }
+
+    #[inline(always)]
+    pub fn contains_outpoint(&self, outpoint: &OutPoint) -> bool {
+        self.contains(&outpoint.to_bytes())
+    }
+
+    ///- #: 是否满足 filterload 消息的大小限制
+    pub fn is_within_size_constraints(&self) -> bool {
+        self.data.len() <= MAX_BLOOM_FILTER_SIZE && self.hash_funcs <= MAX_HASH_FUNCS
+    }
+
+    ///#### 序列化为 filterload 消息的负载
+    ///- #: CompactSize(len) || data || nHashFuncs(u32 LE) || nTweak(u32 LE) || nFlags(u8)
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut res = Vec::with_capacity(self.data.len() + 18);
+        write_compact_size(&mut res, self.data.len() as u64);
+        res.extend_from_slice(&self.data);
+        res.extend_from_slice(&self.hash_funcs.to_le_bytes());
+        res.extend_from_slice(&self.tweak.to_le_bytes());
+        res.push(self.flags);
+        res
+    }
+
+    ///#### 从 filterload 消息的负载反序列化
+    ///- #: 格式错误、存在多余字节或超出大小限制时返回 None
+    pub fn from_bytes(bytes: &[u8]) -> Option<BloomFilter> {
+        let (len, mut pos) = read_compact_size(bytes)?;
+        //len 来自对端，不可信
+        if Some((bytes.len() - pos) as u64) != len.checked_add(9) {
+            return None;
+        }
+
+        let data = bytes[pos..pos + len as usize].to_vec();
+        pos += len as usize;
+
+        let mut buf = [0; 4];
+        buf.copy_from_slice(&bytes[pos..pos + 4]);
+        let hash_funcs = u32::from_le_bytes(buf);
+        buf.copy_from_slice(&bytes[pos + 4..pos + 8]);
+        let tweak = u32::from_le_bytes(buf);
+
+        let res = BloomFilter {
+            data,
+            hash_funcs,
+            tweak,
+            flags: bytes[pos + 8],
+        };
+
+        if res.is_within_size_constraints() {
+            Some(res)
+        } else {
+            None
+        }
+    }
+
+    ///#### 判断交易是否与过滤器匹配，并按 nFlags 将命中输出的 outpoint 加入过滤器
+    ///- @tx[in]: 待匹配的交易
+    pub fn is_relevant_and_update(&mut self, tx: &Transaction) -> bool {
+        let mut found = self.contains(&tx.txid);
+
+        for (i, out) in tx.outputs.iter().enumerate() {
+            let hit = pushes(&out.script_pubkey)
+                .iter()
+                .any(|d| !d.is_empty() && self.contains(d));
+            if !hit {
+                continue;
+            }
+
+            found = true;
+            let update = match self.flags & BLOOM_UPDATE_MASK {
+                BLOOM_UPDATE_ALL => true,
+                BLOOM_UPDATE_P2PUBKEY_ONLY => {
+                    is_pay_to_pubkey(&out.script_pubkey) || is_multisig(&out.script_pubkey)
+                }
+                _ => false,
+            };
+            if update {
+                self.insert_outpoint(&OutPoint {
+                    txid: tx.txid,
+                    vout: i as u32,
+                });
+            }
+        }
+
+        if found {
+            return true;
+        }
+
+        tx.inputs.iter().any(|input| {
+            self.contains_outpoint(&input.prevout)
+                || pushes(&input.script_sig)
+                    .iter()
+                    .any(|d| !d.is_empty() && self.contains(d))
+        })
+    }
+}
+
+///#### MurmurHash3 x86_32
+///- @seed[in]: 哈希种子
+///- @data[in]: 哈希对象
+pub fn murmur3(seed: u32, data: &[u8]) -> u32 {
+    const C1: u32 = 0xcc9e_2d51;
+    const C2: u32 = 0x1b87_3593;
+
+    let mut h1 = seed;
+    let blocks = data.chunks_exact(4);
+    let tail = blocks.remainder();
+
+    for block in blocks {
+        let mut k1 = u32::from_le_bytes([block[0], block[1], block[2], block[3]]);
+        k1 = k1.wrapping_mul(C1).rotate_left(15).wrapping_mul(C2);
+        h1 ^= k1;
+        h1 = h1.rotate_left(13).wrapping_mul(5).wrapping_add(0xe654_6b64);
+    }
+
+    let mut k1 = 0u32;
+    for (i, b) in tail.iter().enumerate() {
+        k1 ^= u32::from(*b) << (8 * i);
+    }
+    if !tail.is_empty() {
+        h1 ^= k1.wrapping_mul(C1).rotate_left(15).wrapping_mul(C2);
+    }
+
+    h1 ^= data.len() as u32;
+    h1 ^= h1 >> 16;
+    h1 = h1.wrapping_mul(0x85eb_ca6b);
+    h1 ^= h1 >> 13;
+    h1 = h1.wrapping_mul(0xc2b2_ae35);
+    h1 ^ (h1 >> 16)
+}
+
+///#### 以比特币的 CompactSize 格式追加写入
+pub fn write_compact_size(buf: &mut Vec<u8>, n: u64) {
+    if n < 0xfd {
+        buf.push(n as u8);
+    } else if n <= 0xffff {
+        buf.push(0xfd);
+        buf.extend_from_slice(&(n as u16).to_le_bytes());
+    } else if n <= 0xffff_ffff {
+        buf.push(0xfe);
+        buf.extend_from_slice(&(n as u32).to_le_bytes());
+    } else {
+        buf.push(0xff);
+        buf.extend_from_slice(&n.to_le_bytes());
+    }
+}
+
+///#### 读取比特币的 CompactSize 格式
+///- #: (数值, 占用的字节数)
+pub fn read_compact_size(bytes: &[u8]) -> Option<(u64, usize)> {
+    let siz = match bytes.first()? {
+        0xfd => 2,
+        0xfe => 4,
+        0xff => 8,
+        n => return Some((u64::from(*n), 1)),
+    };
+    if bytes.len() < 1 + siz {
+        return None;
+    }
+
+    let mut buf = [0; 8];
+    buf[..siz].copy_from_slice(&bytes[1..=siz]);
+    Some((u64::from_le_bytes(buf), 1 + siz))
+}
+
+//#### 依次解析脚本中的所有数据推送，遇到格式错误的脚本时提前终止
+//- #: 每一段被推送的数据，非推送类操作码不产生任何结果
+fn pushes(script: &[u8]) -> Vec<&[u8]> {
+    let mut res = vec![];
+    let mut pc = 0;
+    while pc < script.len() {
+        let op = script[pc];
+        pc += 1;
+        if OP_PUSHDATA4 < op {
+            continue;
+        }
+
+        let (len, siz) = match op {
+            OP_PUSHDATA1 => (script.get(pc).map(|n| *n as usize), 1),
+            OP_PUSHDATA2 => (
+                script
+                    .get(pc..pc + 2)
+                    .map(|n| u16::from_le_bytes([n[0], n[1]]) as usize),
+                2,
+            ),
+            OP_PUSHDATA4 => (
+                script
+                    .get(pc..pc + 4)
+                    .map(|n| u32::from_le_bytes([n[0], n[1], n[2], n[3]]) as usize),
+                4,
+            ),
+            n => (Some(n as usize), 0),
+        };
+
+        match len {
+            Some(len) if pc + siz + len <= script.len() => {
+                pc += siz;
+                res.push(&script[pc..pc + len]);
+                pc += len;
+            }
+            _ => break,
+        }
+    }
+    res
+}
+
+//与 bitcoin core 的 CPubKey::ValidSize 一致：头部字节须与长度相符
+//0x02/0x03 为 33 字节的压缩公钥，0x04/0x06/0x07 为 65 字节的非压缩(或混合)公钥
+fn is_pubkey(data: &[u8]) -> bool {
+    match data.first() {
+        Some(2) | Some(3) => 33 == data.len(),
+        Some(4) | Some(6) | Some(7) => 65 == data.len(),
+        _ => false,
+    }
+}
+
+//<pubkey> OP_CHECKSIG
+fn is_pay_to_pubkey(script: &[u8]) -> bool {
+    2 < script.len()
+        && script[0] as usize == script.len() - 2
+        && is_pubkey(&script[1..script.len() - 1])
+        && Some(&OP_CHECKSIG) == script.last()
+}
+
+//OP_m <pubkey>... OP_n OP_CHECKMULTISIG
+fn is_multisig(script: &[u8]) -> bool {
+    if script.len() < 3 || Some(&OP_CHECKMULTISIG) != script.last() {
+        return false;
+    }
+
+    let m = script[0];
+    let n = script[script.len() - 2];
+    if !(OP_1..=OP_16).contains(&m) || !(m..=OP_16).contains(&n) {
+        return false;
+    }
+
+    let keys = pushes(&script[1..script.len() - 2]);
+    keys.len() == (n - OP_1 + 1) as usize
+        && keys.iter().all(|k| is_pubkey(k))
+        && keys.iter().map(|k| k.len() + 1).sum::<usize>() == script.len() - 3
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::consensus::bitcoin_pow::header::double_sha256;
+    use crate::testutil::{hash, hex};
+
+    //test vectors from bitcoin core: src/test/hash_tests.cpp
+    #[test]
+    fn murmur3() {
+        for (expected, seed, data) in &[
+            (0x0000_0000u32, 0x0000_0000u32, ""),
+            (0x6a39_6f08, 0xFBA4_C795, ""),
+            (0x81f1_6f39, 0xffff_ffff, ""),
+            (0x514e_28b7, 0x0000_0000, "00"),
+            (0xea3f_0b17, 0xFBA4_C795, "00"),
+            (0xfd6c_f10d, 0x0000_0000, "ff"),
+            (0x16c6_b7ab, 0x0000_0000, "0011"),
+            (0x8eb5_1c3d, 0x0000_0000, "001122"),
+            (0xb447_1bf8, 0x0000_0000, "00112233"),
+            (0xe230_1fa8, 0x0000_0000, "0011223344"),
+            (0xfc2e_4a15, 0x0000_0000, "001122334455"),
+            (0xb074_502c, 0x0000_0000, "00112233445566"),
+            (0x8034_d2a0, 0x0000_0000, "0011223344556677"),
+            (0xb469_8def, 0x0000_0000, "001122334455667788"),
+        ] {
+            assert_eq!(*expected, super::murmur3(*seed, &hex(data)));
+        }
+    }
+
+    //test vectors from bitcoin core: src/test/bloom_tests.cpp
+    #[test]
+    fn insert_serialize() {
+        for (tweak, expected) in &[
+            (0, "03614e9b050000000000000001"),
+            (2_147_483_649, "03ce4299050000000100008001"),
+        ] {
+            let mut bf = BloomFilter::new(3, 0.01, *tweak, BLOOM_UPDATE_ALL);
+            bf.insert(&hex("99108ad8ed9bb6274d3980bab5a85c048f0950c8"));
+            assert!(bf.contains(&hex("99108ad8ed9bb6274d3980bab5a85c048f0950c8")));
+            assert!(!bf.contains(&hex("19108ad8ed9bb6274d3980bab5a85c048f0950c8")));
+
+            bf.insert(&hex("b5a2c786d9ef4658287ced5914b37a1b4aa32eee"));
+            assert!(bf.contains(&hex("b5a2c786d9ef4658287ced5914b37a1b4aa32eee")));
+            bf.insert(&hex("b9300670b4c5366e95b2699e8b18bc75e5f729c5"));
+            assert!(bf.contains(&hex("b9300670b4c5366e95b2699e8b18bc75e5f729c5")));
+
+            assert_eq!(hex(expected), bf.to_bytes());
+            assert_eq!(Some(bf), BloomFilter::from_bytes(&hex(expected)));
+        }
+
+        assert!(BloomFilter::from_bytes(&hex("03614e9b0500000000000000")).is_none());
+        assert!(BloomFilter::from_bytes(&hex("03614e9b05000000000000000100")).is_none());
+
+        let bf = BloomFilter::new(100_0000, 0.000_001, 0, BLOOM_UPDATE_NONE);
+        assert_eq!(MAX_BLOOM_FILTER_SIZE, bf.data.len());
+        assert!(bf.is_within_size_constraints());
+    }
+
+    fn p2pkh(hash: &[u8]) -> Vec<u8> {
+        let mut res = vec![0x76, 0xa9, 20];
+        res.extend_from_slice(hash);
+        res.extend_from_slice(&[0x88, OP_CHECKSIG]);
+        res
+    }
+
+    fn p2pk(pubkey: &[u8]) -> Vec<u8> {
+        let mut res = vec![pubkey.len() as u8];
+        res.extend_from_slice(pubkey);
+        res.push(OP_CHECKSIG);
+        res
+    }
+
+    //displayed (big endian) hash to internal byte order
+    fn take<'a>(raw: &'a [u8], pos: &mut usize, n: usize) -> &'a [u8] {
+        *pos += n;
+        &raw[*pos - n..*pos]
+    }
+
+    fn take_var<'a>(raw: &'a [u8], pos: &mut usize) -> &'a [u8] {
+        let (len, siz) = read_compact_size(&raw[*pos..]).unwrap();
+        *pos += siz;
+        take(raw, pos, len as usize)
+    }
+
+    fn take_u32(raw: &[u8], pos: &mut usize) -> u32 {
+        let mut buf = [0; 4];
+        buf.copy_from_slice(take(raw, pos, 4));
+        u32::from_le_bytes(buf)
+    }
+
+    //a raw legacy transaction, the txid is computed from the bytes
+    fn parse_tx(raw: &[u8]) -> Transaction {
+        let mut pos = 4;
+        let (n, siz) = read_compact_size(&raw[pos..]).unwrap();
+        pos += siz;
+        let inputs = (0..n)
+            .map(|_| {
+                let mut txid = [0; 32];
+                txid.copy_from_slice(take(raw, &mut pos, 32));
+                let vout = take_u32(raw, &mut pos);
+                let script_sig = take_var(raw, &mut pos).to_vec();
+                take_u32(raw, &mut pos);
+                TxIn {
+                    prevout: OutPoint { txid, vout },
+                    script_sig,
+                }
+            })
+            .collect();
+
+        let (n, siz) = read_compact_size(&raw[pos..]).unwrap();
+        pos += siz;
+        let outputs = (0..n)
+            .map(|_| {
+                let mut value = [0; 8];
+                value.copy_from_slice(take(raw, &mut pos, 8));
+                TxOut {
+                    value: u64::from_le_bytes(value),
+                    script_pubkey: take_var(raw, &mut pos).to_vec(),
+                }
+            })
+            .collect();
+        assert_eq!(raw.len(), pos + 4);
+
+        Transaction {
+            txid: double_sha256(raw),
+            inputs,
+            outputs,
+        }
+    }
+
+    #[test]
+    fn script() {
+        let key = [2u8; 33];
+        assert!(is_pay_to_pubkey(&p2pk(&key)));
+        assert!(!is_pay_to_pubkey(&p2pkh(&[1; 20])));
+        assert!(!is_multisig(&p2pk(&key)));
+
+        let mut multisig = vec![OP_1];
+        multisig.extend(p2pk(&key).iter().take(34));
+        multisig.extend(p2pk(&[4u8; 65]).iter().take(66));
+        multisig.extend(&[OP_1 + 1, OP_CHECKMULTISIG]);
+        assert!(is_multisig(&multisig));
+        //the header byte of the 65-byte key must be 0x04, 0x06 or 0x07
+        for header in [0x02, 0x03, 0x05, 0x00] {
+            let mut bad = multisig.clone();
+            bad[36] = header;
+            assert!(!is_multisig(&bad), "{}", header);
+        }
+        multisig[0] = OP_1 + 2;
+        assert!(!is_multisig(&multisig));
+
+        //the header byte of a 33-byte key must be 0x02 or 0x03
+        for header in [0x00, 0x04, 0x06, 0x07] {
+            let mut key = key;
+            key[0] = header;
+            assert!(!is_pay_to_pubkey(&p2pk(&key)), "{}", header);
+        }
+        assert!(is_pay_to_pubkey(&p2pk(&[3u8; 33])));
+        assert!(is_pay_to_pubkey(&p2pk(&[6u8; 65])));
+        assert!(!is_pay_to_pubkey(&p2pk(&[2u8; 65])));
+        assert!(!is_pay_to_pubkey(&p2pk(&[])));
+
+        let mut script = vec![0x00, 0x02, 0xaa, 0xbb, OP_PUSHDATA1, 0x01, 0xcc];
+        script.extend(&[OP_PUSHDATA2, 0x01, 0x00, 0xdd, 0x87, OP_PUSHDATA4, 0x05]);
+        assert_eq!(
+            vec![&[][..], &[0xaa, 0xbb][..], &[0xcc][..], &[0xdd][..]],
+            pushes(&script)
+        );
+    }
+
+    //test vectors from bitcoin core: src/test/bloom_tests.cpp, bloom_match
+    const TX: &str = "01000000010b26e9b7735eb6aabdf358bab62f9816a21ba9ebdb719d5299e88607d722c190000000008b4830450220070aca44506c5cef3a16ed519d7c3c39f8aab192c4e1c90d065f37b8a4af6141022100a8e160b856c2d43d27d8fba71e5aef6405b8643ac4cb7cb3c462aced7f14711a0141046d11fee51b0e60666d5049a9101a72741df480b96ee26488a4d3466b95c9a40ac5eeef87e10a5cd336c19a84565f80fa6c547957b7700ff4dfbdefe76036c339ffffffff021bff3d11000000001976a91404943fdd508053c75000106d3bc6e2754dbcff1988ac2f15de00000000001976a914a266436d2965547608b9e15d9032a7b9d64fa43188ac00000000";
+
+    #[test]
+    fn bloom_match() {
+        let tx = parse_tx(&hex(TX));
+        assert_eq!(
+            hash("b4749f017444b051c44dfd2720e88f314ff94f3dd6d56d40ef65854fcd7fff6b"),
+            tx.txid
+        );
+
+        let prev = hash("90c122d70786e899529d71dbeba91ba216982fb6ba58f3bdaab65e73b7e9260b");
+        let outpoint = |txid, vout| OutPoint { txid, vout }.to_bytes().to_vec();
+        for (item, expected) in &[
+            //tx hash
+            (tx.txid.to_vec(), true),
+            (hex("6bff7fcd4f8565ef406dd5d63d4ff94f318fe82027fd4dc451b04474019f74b4"), true),
+            //input signature
+            (hex("30450220070aca44506c5cef3a16ed519d7c3c39f8aab192c4e1c90d065f37b8a4af6141022100a8e160b856c2d43d27d8fba71e5aef6405b8643ac4cb7cb3c462aced7f14711a01"), true),
+            //input pubkey
+            (hex("046d11fee51b0e60666d5049a9101a72741df480b96ee26488a4d3466b95c9a40ac5eeef87e10a5cd336c19a84565f80fa6c547957b7700ff4dfbdefe76036c339"), true),
+            //output addresses
+            (hex("04943fdd508053c75000106d3bc6e2754dbcff19"), true),
+            (hex("a266436d2965547608b9e15d9032a7b9d64fa431"), true),
+            //spent outpoint
+            (outpoint(prev, 0), true),
+            (hex("0b26e9b7735eb6aabdf358bab62f9816a21ba9ebdb719d5299e88607d722c19000000000"), true),
+            //random tx hash, address and outpoints
+            (hash("00000009e784f32f62ef849763d4f45b98e07ba658647343b915ff832b110436").to_vec(), false),
+            (hex("0000006d2965547608b9e15d9032a7b9d64fa431"), false),
+            (outpoint(prev, 1), false),
+            (outpoint(hash("000000d70786e899529d71dbeba91ba216982fb6ba58f3bdaab65e73b7e9260b"), 0), false),
+        ] {
+            let mut bf = BloomFilter::new(10, 0.000_001, 0, BLOOM_UPDATE_ALL);
+            bf.insert(item);
+            assert_eq!(*expected, bf.is_relevant_and_update(&tx));
+        }
+
+        //a matched p2pkh output is added under BLOOM_UPDATE_ALL only
+        let spent = OutPoint {
+            txid: tx.txid,
+            vout: 0,
+        };
+        for (flags, expected) in &[
+            (BLOOM_UPDATE_NONE, false),
+            (BLOOM_UPDATE_ALL, true),
+            (BLOOM_UPDATE_P2PUBKEY_ONLY, false),
+        ] {
+            let mut bf = BloomFilter::new(10, 0.000_001, 0, *flags);
+            bf.insert(&hex("04943fdd508053c75000106d3bc6e2754dbcff19"));
+            assert!(bf.is_relevant_and_update(&tx));
+            assert_eq!(*expected, bf.contains_outpoint(&spent));
+        }
+    }
+
+    #[test]
+    fn malformed() {
+        //an empty filter matches everything, as in core (CVE-2013-5700)
+        let mut bf = BloomFilter::from_bytes(&hex("00000000000000000000")).unwrap();
+        assert!(bf.contains(b"anything"));
+        assert!(bf.is_relevant_and_update(&parse_tx(&hex(TX))));
+
+        //the data length must not overflow
+        assert!(BloomFilter::from_bytes(&hex("ffffffffffffffffff")).is_none());
+        assert!(BloomFilter::from_bytes(&hex("fffffffffffffffffff7")).is_none());
+        assert!(BloomFilter::from_bytes(&hex("fe0000000100000000000000000000")).is_none());
+    }
+}
@@ -0,0 +1,318 @@
+//! ## 布隆过滤器
+//!
+//! #### 算法说明
+//! - counting bloom filter
+//! - 将原版布隆过滤器的每个 bit 替换为一个计数器，添加时计数器加一，删除时减一，从而支持删除操作；
+//! - 计数器宽度可选 4 bit(每字节存放两个)或 8 bit，4 bit 在绝大多数场景下已足够，且内存仅为后者的一半；
+//! - 计数器达到上限后即饱和，饱和的计数器不再增减：其真实计数已无法得知，若继续递减可能导致漏判；
+//! - 只应删除确实添加过的元素，否则会破坏其它元素的计数，产生漏判。
+//!
+//! #### 应用场景
+//! - 交易池成员过滤：交易被打包或被驱逐之后需要从过滤器中移除。
+//!
+//! #### 实现属性
+//! - <font color=Green>√</font> 多线程安全
+//! - <font color=Green>√</font> 无 unsafe 代码
+//!
+
+use ring::digest::{Context, SHA1};
+
+const USIZE_SIZ: usize = std::mem::size_of::<usize>();
+const BYTE_BITS: usize = 8;
+
+///计数器宽度
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CounterBits {
+    Four,
+    Eight,
+}
+
+impl CounterBits {
+    #[inline(always)]
+    fn width(self) -> usize {
+        match self {
+            CounterBits::Four => 4,
+            CounterBits::Eight => 8,
+        }
+    }
+
+    #[inline(always)]
+    fn max(self) -> u8 {
+        match self {
+            CounterBits::Four => 0x0f,
+            CounterBits::Eight => 0xff,
+        }
+    }
+}
+
+//- @counters: 计数器数组，4 bit 计数器每字节存放两个，低半字节在前
+//- @m: 计数器总数
+//- @k: 哈希次数
+//- @bits: 计数器宽度
+//- @item_cnt: 当前元素总数，即添加次数减去删除次数
+//- @counter_used: 非零计数器的总数
+//- @saturated: 已饱和的计数器总数
+#[derive(Debug)]
+pub struct BloomFilter {
+    counters: Vec<u8>,
+    m: usize,
+    k: usize,
+    bits: CounterBits,
+    item_cnt: usize,
+    counter_used: usize,
+    saturated: usize,
+}
+
+///元素位置：计数器所在的字节，及其在字节内的起始 bit
+#[derive(Default)]
+pub struct Position {
+    byte_idx: usize,
+    bit_idx: usize,
+}
+
+impl BloomFilter {
+    ///#### 根据预期元素数量与目标误判率，使用最优的 m 与 k 初始化
+    ///- @expected_items[in]: 预期被索引的元素总量
+    ///- @fp_rate[in]: 目标误判率，取值范围 (0, 1)
+    ///- @bits[in]: 计数器宽度
+    pub fn with_capacity(expected_items: usize, fp_rate: f64, bits: CounterBits) -> BloomFilter {
+        let (m, k) = super::optimal_params(expected_items, fp_rate);
+        BloomFilter::with_params(m, k, bits)
+    }
+
+    ///#### 使用指定的参数初始化
+    ///- @m[in]: 计数器总数，向上对齐到整字节
+    ///- @k[in]: 哈希次数
+    ///- @bits[in]: 计数器宽度
+    pub fn with_params(m: usize, k: usize, bits: CounterBits) -> BloomFilter {
+        assert!(0 < m && 0 < k, "m and k must be positive");
+        let per_byte = BYTE_BITS / bits.width();
+        let siz = m.div_ceil(per_byte);
+        BloomFilter {
+            counters: vec![0; siz],
+            m: siz * per_byte,
+            k,
+            bits,
+            item_cnt: 0,
+            counter_used: 0,
+            saturated: 0,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.counters = vec![0; self.counters.len()];
+        self.item_cnt = 0;
+        self.counter_used = 0;
+        self.saturated = 0;
+    }
+
+    ///- #: 计数器总数
+    #[inline(always)]
+    pub fn m(&self) -> usize {
+        self.m
+    }
+
+    ///- #: 哈希次数
+    #[inline(always)]
+    pub fn k(&self) -> usize {
+        self.k
+    }
+
+    ///- #: 当前元素总数
+    #[inline(always)]
+    pub fn len(&self) -> usize {
+        self.item_cnt
+    }
+
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        0 == self.item_cnt
+    }
+
+    ///- #: 已饱和的计数器总数
+    #[inline(always)]
+    pub fn saturated_cnt(&self) -> usize {
+        self.saturated
+    }
+
+    //第 i 个哈希函数：以 i 为盐值的 SHA1，i 为 0 时不加盐
+    fn hash(&self, item: &[u8]) -> Vec<Position> {
+        let width = self.bits.width();
+        let per_byte = BYTE_BITS / width;
+        (0..self.k)
+            .map(|i| {
+                let mut context = Context::new(&SHA1);
+                if 0 < i {
+                    context.update(&(i as u64).to_le_bytes());
+                }
+                context.update(item);
+                let id = context.finish();
+                let id = id.as_ref();
+                assert!(USIZE_SIZ < id.len());
+
+                let mut buf = [0; USIZE_SIZ];
+                buf.copy_from_slice(&id[0..USIZE_SIZ]);
+                let id = usize::from_le_bytes(buf) % self.m;
+
+                Position {
+                    byte_idx: id / per_byte,
+                    bit_idx: id % per_byte * width,
+                }
+            })
+            .collect()
+    }
+
+    ///- #: 指定位置上的计数值
+    pub fn count_by_position(&self, p: &Position) -> u8 {
+        (self.counters[p.byte_idx] >> p.bit_idx) & self.bits.max()
+    }
+
+    fn store(&mut self, p: &Position, v: u8) {
+        let mask = self.bits.max() << p.bit_idx;
+        self.counters[p.byte_idx] = (self.counters[p.byte_idx] & !mask) | (v << p.bit_idx);
+    }
+
+    ///- @item[in]: 要添加的元素
+    pub fn set(&mut self, item: &[u8]) -> Vec<Position> {
+        let ps = self.hash(item);
+        for p in ps.iter() {
+            let v = self.count_by_position(p);
+            if self.bits.max() == v {
+                continue;
+            }
+            if 0 == v {
+                self.counter_used += 1;
+            }
+            if self.bits.max() == v + 1 {
+                self.saturated += 1;
+            }
+            self.store(p, v + 1);
+        }
+        self.item_cnt += 1;
+        ps
+    }
+
+    ///- @item[in]: 要查找的元素
+    pub fn find(&self, item: &[u8]) -> Option<Vec<Position>> {
+        let ps = self.hash(item);
+        if ps.iter().all(|p| self.find_by_position(p)) {
+            Some(ps)
+        } else {
+            None
+        }
+    }
+
+    pub fn find_by_position(&self, p: &Position) -> bool {
+        0 < self.count_by_position(p)
+    }
+
+    ///#### 删除元素
+    ///- #: 元素可能存在时返回其位置，否则返回 None 且不做任何修改
+    ///- @item[in]: 要删除的元素，须确实添加过
+    pub fn remove(&mut self, item: &[u8]) -> Option<Vec<Position>> {
+        let ps = self.find(item)?;
+        for p in ps.iter() {
+            let v = self.count_by_position(p);
+            //删除误判的元素时，其多个哈希若落在同一计数器上，该计数器可能已被减至零
+            if 0 == v || self.bits.max() == v {
+                continue;
+            }
+            if 1 == v {
+                self.counter_used -= 1;
+            }
+            self.store(p, v - 1);
+        }
+        self.item_cnt = self.item_cnt.saturating_sub(1);
+        Some(ps)
+    }
+
+    ///#### 根据非零计数器的占比估算当前的误判率
+    ///- #: (counter_used / m) ^ k
+    pub fn estimated_fp_rate(&self) -> f64 {
+        (self.counter_used as f64 / self.m as f64).powi(self.k as i32)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn counting_bloom_filter() {
+        for bits in &[CounterBits::Four, CounterBits::Eight] {
+            let n = 1_0000usize;
+            let mut bf = BloomFilter::with_capacity(n, 0.01, *bits);
+            assert_eq!(7, bf.k());
+
+            for i in 0..n {
+                bf.set(&i.to_le_bytes());
+            }
+            assert_eq!(n, bf.len());
+            assert_eq!(0, bf.saturated_cnt());
+            for i in 0..n {
+                assert!(bf.find(&i.to_le_bytes()).is_some());
+            }
+            let fp = bf.estimated_fp_rate();
+            assert!(0.005 < fp && fp < 0.015);
+
+            //evict the first half, the rest must still be found
+            for i in 0..n / 2 {
+                assert!(bf.remove(&i.to_le_bytes()).is_some());
+            }
+            assert_eq!(n / 2, bf.len());
+            for i in n / 2..n {
+                assert!(bf.find(&i.to_le_bytes()).is_some());
+            }
+            let fp_cnt = (0..n / 2)
+                .filter(|i| bf.find(&i.to_le_bytes()).is_some())
+                .count();
+            assert!(fp_cnt < n / 100);
+
+            for i in n / 2..n {
+                assert!(bf.remove(&i.to_le_bytes()).is_some());
+            }
+            assert!(bf.is_empty());
+            assert_eq!(0.0, bf.estimated_fp_rate());
+            assert!(bf.counters.iter().all(|c| 0 == *c));
+        }
+    }
+
+    #[test]
+    fn saturation() {
+        let mut bf = BloomFilter::with_params(64, 1, CounterBits::Four);
+        let item = 1u32.to_le_bytes();
+        for _ in 0..20 {
+            bf.set(&item);
+        }
+        let p = bf.find(&item).unwrap();
+        assert_eq!(15, bf.count_by_position(&p[0]));
+        assert_eq!(1, bf.saturated_cnt());
+
+        //saturated counters never decrease
+        for _ in 0..20 {
+            assert!(bf.remove(&item).is_some());
+        }
+        assert_eq!(15, bf.count_by_position(&p[0]));
+
+        let mut bf = BloomFilter::with_params(64, 1, CounterBits::Eight);
+        for _ in 0..300 {
+            bf.set(&item);
+        }
+        assert_eq!(255, bf.count_by_position(&bf.find(&item).unwrap()[0]));
+
+        //neighbouring 4-bit counters never overflow into each other
+        let mut bf = BloomFilter::with_params(2, 1, CounterBits::Four);
+        assert_eq!(2, bf.m());
+        let a = 0u32.to_le_bytes();
+        let b = (1u32..)
+            .map(|i| i.to_le_bytes())
+            .find(|b| bf.hash(b)[0].bit_idx != bf.hash(&a)[0].bit_idx)
+            .unwrap();
+        for _ in 0..40 {
+            bf.set(&a);
+        }
+        bf.set(&b);
+        assert_eq!(15, bf.count_by_position(&bf.hash(&a)[0]));
+        assert_eq!(1, bf.count_by_position(&bf.hash(&b)[0]));
+    }
+}
@@ -0,0 +1,358 @@
+//! ## 布谷鸟过滤器
+//!
+//! #### 算法说明
+//! - cuckoo filter
+//! - 每个桶包含 b 个槽位，每个槽位存放元素的 f bit 指纹，指纹全零表示空槽位，故合法指纹永不为零；
+//! - hash(x) 取 seed 为 0 的 xxHash64，不随 Rust 版本变化，同一元素总落在同样的桶中；
+//! - partial-key cuckoo hashing：i1 = hash(x)，i2 = i1 ^ hash(fingerprint)，
+//!   仅凭指纹与当前桶号即可算出另一个候选桶，因此无需保存原始元素即可完成"踢出-重新安置"；
+//! - 两个候选桶均满时，随机踢出其中一个指纹，将其迁往它的另一个候选桶，最多重复 MAX_KICKS 次；
+//!   仍然失败时，最后被踢出的指纹存入 victim 槽位，此后的插入一律失败，保证已插入的元素不会漏判；
+//! - 桶的数量为 2 的幂，使得异或运算得到的候选桶总在合法范围内，且两个候选桶互为对方的另一个候选桶；
+//! - 误判率上界约为 2b / 2^f，与元素数量无关；理论上误判率低于 3% 时空间效率优于布隆过滤器，
+//!   但桶的数量须对齐到 2 的幂，实际装载率可能明显低于上限(见 benches/bloomfilter.rs)；
+//! - 支持删除，但只应删除确实插入过的元素，否则可能删掉其它元素的相同指纹。
+//!
+//! #### 应用场景
+//! - 对端交易/区块清单(inventory)跟踪：需要在对端确认或超时之后移除记录。
+//!
+//! #### 实现属性
+//! - <font color=Green>√</font> 多线程安全
+//! - <font color=Green>√</font> 无 unsafe 代码
+//!
+
+use super::fasthash::xxh64;
+
+const BYTE_BITS: usize = 8;
+const MAX_KICKS: usize = 500;
+//with_capacity 按此装载率计算桶的数量，b = 4 时实际可达到的装载率约为 95%
+const LOAD_FACTOR: f64 = 0.9;
+
+//- @slots: 全部槽位，按 f bit 紧凑排列，末尾留出 8 字节便于整字读写
+//- @bucket_cnt: 桶的数量，2 的幂
+//- @bucket_siz: 每个桶的槽位数量 b
+//- @fp_bits: 指纹的 bit 数 f
+//- @victim: 插入失败时最后被踢出的指纹及其所在桶
+//- @item_cnt: 当前元素总数
+//- @rng: 踢出时使用的 xorshift 随机数状态，固定种子，保证行为可复现
+#[derive(Debug, Clone)]
+pub struct CuckooFilter {
+    slots: Vec<u8>,
+    bucket_cnt: usize,
+    bucket_siz: usize,
+    fp_bits: usize,
+    victim: Option<(usize, u32)>,
+    item_cnt: usize,
+    rng: u64,
+}
+
+impl CuckooFilter {
+    ///#### 根据预期元素数量与目标误判率初始化，每个桶 4 个槽位
+    ///- f = ceil(log2(2b / fp_rate))
+    ///- @expected_items[in]: 预期被索引的元素总量
+    ///- @fp_rate[in]: 目标误判率，取值范围 (0, 1)
+    pub fn with_capacity(expected_items: usize, fp_rate: f64) -> CuckooFilter {
+        assert!(0.0 < fp_rate && fp_rate < 1.0, "fp_rate must be in (0, 1)");
+        let bucket_siz = 4;
+        let fp_bits = (2.0 * bucket_siz as f64 / fp_rate).log2().ceil() as usize;
+        let bucket_cnt = (expected_items as f64 / bucket_siz as f64 / LOAD_FACTOR).ceil() as usize;
+        CuckooFilter::with_params(bucket_cnt, bucket_siz, fp_bits.clamp(1, 32))
+    }
+
+    ///#### 使用指定的参数初始化
+    ///- @bucket_cnt[in]: 桶的数量，向上对齐到 2 的幂
+    ///- @bucket_siz[in]: 每个桶的槽位数量
+    ///- @fp_bits[in]: 指纹的 bit 数，取值范围 [1, 32]
+    pub fn with_params(bucket_cnt: usize, bucket_siz: usize, fp_bits: usize) -> CuckooFilter {
+        assert!(0 < bucket_siz, "bucket_siz must be positive");
+        assert!(0 < fp_bits && fp_bits <= 32, "fp_bits must be in [1, 32]");
+        let bucket_cnt = bucket_cnt.max(1).next_power_of_two();
+        CuckooFilter {
+            slots: vec![0; (bucket_cnt * bucket_siz * fp_bits).div_ceil(BYTE_BITS) + 8],
+            bucket_cnt,
+            bucket_siz,
+            fp_bits,
+            victim: None,
+            item_cnt: 0,
+            rng: 0x9E37_79B9_7F4A_7C15,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.slots = vec![0; self.slots.len()];
+        self.victim = None;
+        self.item_cnt = 0;
+    }
+
+    ///- #: 当前元素总数
+    #[inline(always)]
+    pub fn len(&self) -> usize {
+        self.item_cnt
+    }
+
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        0 == self.item_cnt
+    }
+
+    ///- #: 槽位总数
+    #[inline(always)]
+    pub fn capacity(&self) -> usize {
+        self.bucket_cnt * self.bucket_siz
+    }
+
+    ///- #: 已用槽位的占比
+    #[inline(always)]
+    pub fn load_factor(&self) -> f64 {
+        self.item_cnt as f64 / self.capacity() as f64
+    }
+
+    ///- #: 指纹占用的内存，以字节为单位
+    #[inline(always)]
+    pub fn size_in_bytes(&self) -> usize {
+        (self.capacity() * self.fp_bits).div_ceil(BYTE_BITS)
+    }
+
+    ///- #: 误判率上界 1 - (1 - 1/2^f)^(2b)
+    pub fn fp_rate_upper_bound(&self) -> f64 {
+        1.0 - (1.0 - 0.5f64.powi(self.fp_bits as i32)).powi(2 * self.bucket_siz as i32)
+    }
+
+    //- #: (i1, fingerprint)
+    fn hash(&self, item: &[u8]) -> (usize, u32) {
+        let h = xxh64(0, item);
+
+        let mask = (1u64 << self.fp_bits) - 1;
+        let fp = ((h >> 32) & mask) as u32;
+        let fp = if 0 == fp { 1 } else { fp };
+        (h as usize & (self.bucket_cnt - 1), fp)
+    }
+
+    //- #: 另一个候选桶，i 与返回值互为对方的另一个候选桶
+    #[inline(always)]
+    fn alt_index(&self, i: usize, fp: u32) -> usize {
+        (i ^ (fp as usize).wrapping_mul(0x5bd1_e995)) & (self.bucket_cnt - 1)
+    }
+
+    fn read(&self, slot: usize) -> u32 {
+        let bit = slot * self.fp_bits;
+        let mut buf = [0; 8];
+        buf.copy_from_slice(&self.slots[bit / BYTE_BITS..bit / BYTE_BITS + 8]);
+        ((u64::from_le_bytes(buf) >> (bit % BYTE_BITS)) & ((1 << self.fp_bits) - 1)) as u32
+    }
+
+    fn write(&mut self, slot: usize, fp: u32) {
+        let bit = slot * self.fp_bits;
+        let range = bit / BYTE_BITS..bit / BYTE_BITS + 8;
+        let mut buf = [0; 8];
+        buf.copy_from_slice(&self.slots[range.clone()]);
+
+        let mask = ((1u64 << self.fp_bits) - 1) << (bit % BYTE_BITS);
+        let word = (u64::from_le_bytes(buf) & !mask) | (u64::from(fp) << (bit % BYTE_BITS));
+        self.slots[range].copy_from_slice(&word.to_le_bytes());
+    }
+
+    //- #: 桶 i 中首个满足条件的槽位
+    fn find_slot(&self, i: usize, fp: u32) -> Option<usize> {
+        (i * self.bucket_siz..(i + 1) * self.bucket_siz).find(|s| fp == self.read(*s))
+    }
+
+    //- #: 将指纹放入桶 i 的空槽位，桶已满时返回 false
+    fn put(&mut self, i: usize, fp: u32) -> bool {
+        match self.find_slot(i, 0) {
+            Some(s) => {
+                self.write(s, fp);
+                true
+            }
+            None => false,
+        }
+    }
+
+    #[inline(always)]
+    fn next_rand(&mut self) -> usize {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 7;
+        self.rng ^= self.rng << 17;
+        self.rng as usize
+    }
+
+    ///#### 插入元素
+    ///- #: 过滤器已满时返回 false
+    ///- @item[in]: 要插入的元素
+    pub fn insert(&mut self, item: &[u8]) -> bool {
+        if self.victim.is_some() {
+            return false;
+        }
+
+        let (i1, fp) = self.hash(item);
+        let i2 = self.alt_index(i1, fp);
+        if self.put(i1, fp) || self.put(i2, fp) {
+            self.item_cnt += 1;
+            return true;
+        }
+
+        let mut i = if 0 == self.next_rand() & 1 { i1 } else { i2 };
+        let mut fp = fp;
+        for _ in 0..MAX_KICKS {
+            let s = i * self.bucket_siz + self.next_rand() % self.bucket_siz;
+            let kicked = self.read(s);
+            self.write(s, fp);
+            fp = kicked;
+            i = self.alt_index(i, fp);
+            if self.put(i, fp) {
+                self.item_cnt += 1;
+                return true;
+            }
+        }
+
+        //新元素已经入桶，只是最后被踢出的指纹无处安放
+        self.victim = Some((i, fp));
+        self.item_cnt += 1;
+        true
+    }
+
+    ///- @item[in]: 要查找的元素
+    pub fn lookup(&self, item: &[u8]) -> bool {
+        let (i1, fp) = self.hash(item);
+        let i2 = self.alt_index(i1, fp);
+        self.find_slot(i1, fp).is_some()
+            || self.find_slot(i2, fp).is_some()
+            || self
+                .victim
+                .is_some_and(|(i, v)| v == fp && (i == i1 || i == i2))
+    }
+
+    ///#### 删除元素
+    ///- #: 找到并删除了一个匹配的指纹时返回 true
+    ///- @item[in]: 要删除的元素，须确实插入过
+    pub fn delete(&mut self, item: &[u8]) -> bool {
+        let (i1, fp) = self.hash(item);
+        let i2 = self.alt_index(i1, fp);
+
+        if let Some((i, v)) = self.victim {
+            if v == fp && (i == i1 || i == i2) {
+                self.victim = None;
+                self.item_cnt -= 1;
+                return true;
+            }
+        }
+
+        match self.find_slot(i1, fp).or_else(|| self.find_slot(i2, fp)) {
+            Some(s) => {
+                self.write(s, 0);
+                self.item_cnt -= 1;
+
+                //腾出了空间，尝试重新安置 victim
+                if let Some((i, v)) = self.victim {
+                    let alt = self.alt_index(i, v);
+                    if self.put(i, v) || self.put(alt, v) {
+                        self.victim = None;
+                    }
+                }
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    //xxh64(0, "") = 0xef46db3751d8e999, buckets and fingerprints stay the same across Rust releases
+    #[test]
+    fn stable_hash() {
+        let cf = CuckooFilter::with_params(1024, 4, 16);
+        assert_eq!((0x199, 0xdb37), cf.hash(&[]));
+    }
+
+    #[test]
+    fn cuckoo_filter() {
+        let n = 10_0000usize;
+        let mut cf = CuckooFilter::with_capacity(n, 0.001);
+        assert_eq!(13, cf.fp_bits);
+        assert!(cf.fp_rate_upper_bound() < 0.001);
+
+        for i in 0..n {
+            assert!(cf.insert(&i.to_le_bytes()));
+        }
+        assert_eq!(n, cf.len());
+        assert!(cf.victim.is_none());
+        for i in 0..n {
+            assert!(cf.lookup(&i.to_le_bytes()));
+        }
+
+        let fp_cnt = (n..2 * n).filter(|i| cf.lookup(&i.to_le_bytes())).count();
+        assert!((fp_cnt as f64) < 0.001 * n as f64);
+
+        for i in 0..n / 2 {
+            assert!(cf.delete(&i.to_le_bytes()));
+        }
+        assert_eq!(n / 2, cf.len());
+        for i in n / 2..n {
+            assert!(cf.lookup(&i.to_le_bytes()));
+        }
+        let fp_cnt = (0..n / 2).filter(|i| cf.lookup(&i.to_le_bytes())).count();
+        assert!((fp_cnt as f64) < 0.001 * n as f64);
+
+        cf.clear();
+        assert!(cf.is_empty());
+        assert!(!cf.lookup(&1usize.to_le_bytes()));
+    }
+
+    #[test]
+    fn params() {
+        for fp_bits in &[1, 7, 8, 12, 16, 25, 32] {
+            for bucket_siz in &[1, 2, 4, 8] {
+                let mut cf = CuckooFilter::with_params(100, *bucket_siz, *fp_bits);
+                assert_eq!(128, cf.bucket_cnt);
+                assert_eq!(128 * bucket_siz * fp_bits / 8, cf.size_in_bytes());
+
+                //neighbouring slots never overlap
+                let max = ((1u64 << fp_bits) - 1) as u32;
+                for s in 0..cf.capacity() {
+                    cf.write(s, if 0 == s & 1 { max } else { 1 });
+                }
+                for s in 0..cf.capacity() {
+                    assert_eq!(if 0 == s & 1 { max } else { 1 }, cf.read(s));
+                }
+
+                for s in 0..cf.capacity() {
+                    let i = s / bucket_siz;
+                    assert_eq!(i, cf.alt_index(cf.alt_index(i, s as u32 + 1), s as u32 + 1));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn full() {
+        let mut cf = CuckooFilter::with_params(4, 2, 16);
+        let mut inserted = vec![];
+        for i in 0usize.. {
+            if !cf.insert(&i.to_le_bytes()) {
+                break;
+            }
+            inserted.push(i);
+        }
+
+        //every accepted item is still found, including the one parked in victim
+        assert!(cf.victim.is_some());
+        assert!(inserted.len() <= cf.capacity() + 1);
+        assert_eq!(inserted.len(), cf.len());
+        for i in inserted.iter() {
+            assert!(cf.lookup(&i.to_le_bytes()));
+        }
+
+        //deletion eventually makes room for the victim again
+        while cf.victim.is_some() {
+            assert!(cf.delete(&inserted.remove(0).to_le_bytes()));
+            for i in inserted.iter() {
+                assert!(cf.lookup(&i.to_le_bytes()));
+            }
+        }
+        assert_eq!(inserted.len(), cf.len());
+        assert!(cf.insert(&usize::MAX.to_le_bytes()));
+    }
+}
@@ -0,0 +1,47 @@
+use std::{error::Error, fmt};
+
+///- @XErr::Incompatible: 两个过滤器的 m、k 或哈希方案不一致，无法合并或反序列化
+///- @XErr::UnknownScheme: 序列化数据中的哈希方案编号未定义
+///- @XErr::LengthMismatch: 序列化数据的长度与头部记录的 m 不符
+///- @XErr::InvalidParams: 头部记录的 m 或 k 不合法，k 须在 1..=MAX_K 之内
+pub enum XErr {
+    Incompatible,
+    UnknownScheme(u8),
+    LengthMismatch,
+    InvalidParams,
+    Unknown,
+}
+
+impl fmt::Display for XErr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            XErr::Incompatible => write!(f, "Incompatible filter parameters!"),
+            XErr::UnknownScheme(s) => write!(f, "Unknown hash scheme {}!", s),
+            XErr::LengthMismatch => write!(f, "Data length mismatch!"),
+            XErr::InvalidParams => write!(f, "Invalid filter parameters!"),
+            XErr::Unknown => write!(f, "Unknown error!"),
+        }
+    }
+}
+
+impl fmt::Debug for XErr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+impl Error for XErr {
+    fn description(&self) -> &str {
+        match self {
+            XErr::Incompatible => "Incompatible filter parameters!",
+            XErr::UnknownScheme(_) => "Unknown hash scheme!",
+            XErr::LengthMismatch => "Data length mismatch!",
+            XErr::InvalidParams => "Invalid filter parameters!",
+            XErr::Unknown => "Unknown error!",
+        }
+    }
+
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        None
+    }
+}
@@ -0,0 +1,221 @@
+//! ## 非密码学哈希
+//!
+//! #### 算法说明
+//! - 布隆过滤器只要求哈希值分布均匀，并不需要抗碰撞等密码学性质，使用快速哈希可大幅降低计算开销；
+//! - Kirsch–Mitzenmacher double hashing：只需两个独立的基础哈希 h1、h2，第 i 个索引取 (h1 + i·h2) mod m，
+//!   渐近误判率与 k 个完全独立的哈希函数相同；
+//! - 两个基础哈希由同一哈希函数以不同的 seed 计算得出；
+//! - 预置 SipHash-2-4 与 xxHash64，亦可传入自定义的 FastHasher，其 id 会被写入序列化头部；
+//! - id 的取值范围为 [0, 127]：[0, 63] 保留给预置哈希，自定义哈希只能使用 [64, 127]，
+//!   反序列化时预置编号直接还原，自定义编号须由调用方显式给出哈希函数，避免被静默替换为其它哈希而产生漏判。
+//!
+//! #### 实现属性
+//! - <font color=Green>√</font> 多线程安全
+//! - <font color=Green>√</font> 无 unsafe 代码
+//!
+
+use super::super::gcs::siphash24;
+use super::error::XErr;
+use std::ops::RangeInclusive;
+
+///预置哈希的编号范围
+pub const PRESET_IDS: RangeInclusive<u8> = 0..=0x3f;
+
+///自定义哈希的编号范围
+pub const CUSTOM_IDS: RangeInclusive<u8> = 0x40..=0x7f;
+
+///可插拔的快速哈希函数
+//- @id: 写入序列化头部的编号，预置哈希位于 PRESET_IDS，自定义哈希位于 CUSTOM_IDS
+//- @hash: (seed, item) -> 64 bit 哈希值
+#[derive(Clone, Copy)]
+pub struct FastHasher {
+    id: u8,
+    hash: fn(u64, &[u8]) -> u64,
+}
+
+pub const SIPHASH: FastHasher = FastHasher { id: 1, hash: sip };
+
+pub const XXHASH64: FastHasher = FastHasher { id: 2, hash: xxh64 };
+
+//全部预置的哈希函数
+const PRESETS: [FastHasher; 2] = [SIPHASH, XXHASH64];
+
+impl std::fmt::Debug for FastHasher {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "FastHasher({})", self.id)
+    }
+}
+
+///编号与哈希函数均相同
+impl PartialEq for FastHasher {
+    fn eq(&self, other: &FastHasher) -> bool {
+        self.id == other.id && std::ptr::fn_addr_eq(self.hash, other.hash)
+    }
+}
+
+impl Eq for FastHasher {}
+
+impl FastHasher {
+    ///#### 自定义快速哈希
+    ///- #: id 不在 CUSTOM_IDS 之内时返回 XErr::InvalidParams
+    ///- @id[in]: 写入序列化头部的编号
+    ///- @hash[in]: (seed, item) -> 64 bit 哈希值
+    pub fn custom(id: u8, hash: fn(u64, &[u8]) -> u64) -> Result<FastHasher, XErr> {
+        if CUSTOM_IDS.contains(&id) {
+            Ok(FastHasher { id, hash })
+        } else {
+            Err(XErr::InvalidParams)
+        }
+    }
+
+    #[inline(always)]
+    pub fn id(&self) -> u8 {
+        self.id
+    }
+
+    ///- #: 是否为预置哈希
+    #[inline(always)]
+    pub fn is_preset(&self) -> bool {
+        PRESET_IDS.contains(&self.id)
+    }
+
+    ///- #: 编号对应的预置哈希函数
+    pub fn preset(id: u8) -> Option<FastHasher> {
+        PRESETS.iter().find(|h| id == h.id).cloned()
+    }
+
+    ///#### 计算 double hashing 所需的两个基础哈希
+    ///- #: (h1, h2)
+    #[inline(always)]
+    pub fn double(&self, item: &[u8]) -> (u64, u64) {
+        ((self.hash)(0, item), (self.hash)(1, item))
+    }
+}
+
+fn sip(seed: u64, item: &[u8]) -> u64 {
+    siphash24(seed, 0, item)
+}
+
+const PRIME64_1: u64 = 0x9E37_79B1_85EB_CA87;
+const PRIME64_2: u64 = 0xC2B2_AE3D_27D4_EB4F;
+const PRIME64_3: u64 = 0x1656_67B1_9E37_79F9;
+const PRIME64_4: u64 = 0x85EB_CA77_C2B2_AE63;
+const PRIME64_5: u64 = 0x27D4_EB2F_1656_67C5;
+
+#[inline(always)]
+fn read_u64(data: &[u8]) -> u64 {
+    let mut buf = [0; 8];
+    buf.copy_from_slice(&data[..8]);
+    u64::from_le_bytes(buf)
+}
+
+#[inline(always)]
+fn xxh_round(acc: u64, input: u64) -> u64 {
+    acc.wrapping_add(input.wrapping_mul(PRIME64_2))
+        .rotate_left(31)
+        .wrapping_mul(PRIME64_1)
+}
+
+#[inline(always)]
+fn xxh_merge(acc: u64, v: u64) -> u64 {
+    (acc ^ xxh_round(0, v))
+        .wrapping_mul(PRIME64_1)
+        .wrapping_add(PRIME64_4)
+}
+
+///#### xxHash64
+///- @seed[in]: 种子
+///- @data[in]: 消息
+pub fn xxh64(seed: u64, data: &[u8]) -> u64 {
+    let mut rest = data;
+    let mut h = if 32 <= data.len() {
+        let mut v = [
+            seed.wrapping_add(PRIME64_1).wrapping_add(PRIME64_2),
+            seed.wrapping_add(PRIME64_2),
+            seed,
+            seed.wrapping_sub(PRIME64_1),
+        ];
+        while 32 <= rest.len() {
+            for (i, x) in v.iter_mut().enumerate() {
+                *x = xxh_round(*x, read_u64(&rest[8 * i..]));
+            }
+            rest = &rest[32..];
+        }
+
+        let h = v[0]
+            .rotate_left(1)
+            .wrapping_add(v[1].rotate_left(7))
+            .wrapping_add(v[2].rotate_left(12))
+            .wrapping_add(v[3].rotate_left(18));
+        v.iter().fold(h, |h, x| xxh_merge(h, *x))
+    } else {
+        seed.wrapping_add(PRIME64_5)
+    };
+    h = h.wrapping_add(data.len() as u64);
+
+    while 8 <= rest.len() {
+        h ^= xxh_round(0, read_u64(rest));
+        h = h
+            .rotate_left(27)
+            .wrapping_mul(PRIME64_1)
+            .wrapping_add(PRIME64_4);
+        rest = &rest[8..];
+    }
+    if 4 <= rest.len() {
+        let mut buf = [0; 4];
+        buf.copy_from_slice(&rest[..4]);
+        h ^= u64::from(u32::from_le_bytes(buf)).wrapping_mul(PRIME64_1);
+        h = h
+            .rotate_left(23)
+            .wrapping_mul(PRIME64_2)
+            .wrapping_add(PRIME64_3);
+        rest = &rest[4..];
+    }
+    for b in rest {
+        h ^= u64::from(*b).wrapping_mul(PRIME64_5);
+        h = h.rotate_left(11).wrapping_mul(PRIME64_1);
+    }
+
+    h ^= h >> 33;
+    h = h.wrapping_mul(PRIME64_2);
+    h ^= h >> 29;
+    h = h.wrapping_mul(PRIME64_3);
+    h ^ (h >> 32)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn xxhash64() {
+        assert_eq!(0xEF46_DB37_51D8_E999, xxh64(0, b""));
+        assert_eq!(0xD24E_C4F1_A98C_6E5B, xxh64(0, b"a"));
+        assert_eq!(0x44BC_2CF5_AD77_0999, xxh64(0, b"abc"));
+        assert_eq!(
+            0xFBCE_A83C_8A37_8BF1,
+            xxh64(0, b"Nobody inspects the spammish repetition")
+        );
+
+        assert_eq!(Some(XXHASH64), FastHasher::preset(XXHASH64.id()));
+        assert!(FastHasher::preset(0).is_none());
+        let (h1, h2) = SIPHASH.double(b"abc");
+        assert_ne!(h1, h2);
+    }
+
+    #[test]
+    fn custom() {
+        //preset ids are reserved, ids above 0x7f would alias in the header
+        for id in &[0, 1, 2, 0x3f, 0x80, 0x81, 0xff] {
+            assert!(FastHasher::custom(*id, xxh64).is_err());
+        }
+        let h = FastHasher::custom(0x40, xxh64).unwrap();
+        assert!(!h.is_preset() && SIPHASH.is_preset());
+        assert!(FastHasher::preset(h.id()).is_none());
+
+        //same id, different functions
+        assert_eq!(h, FastHasher::custom(0x40, xxh64).unwrap());
+        assert_ne!(h, FastHasher::custom(0x40, sip).unwrap());
+        assert_ne!(h, FastHasher::custom(0x41, xxh64).unwrap());
+    }
+}
@@ -0,0 +1,245 @@
+//! ## 布隆过滤器
+//!
+//! #### 算法说明
+//! - 以太坊区块头与交易收据中的 logsBloom，固定 2048 bit(256 字节)；
+//! - 每条日志的合约地址及其每个 topic 分别被加入过滤器，日志的 data 部分不参与；
+//! - 对每个输入取 keccak-256，以前 6 个字节组成 3 个大端 u16，各取低 11 bit 作为索引，
+//!   索引按大端顺序对应整个 2048 bit，即第 idx 位位于第 255 - idx / 8 个字节的第 idx % 8 位；
+//! - 收据的 bloom 由其全部日志累加而成，区块的 bloom 是其全部收据 bloom 的按位或。
+//!
+//! #### 应用场景
+//! - 日志检索：仅凭区块头即可排除不可能包含目标合约地址或事件 topic 的区块，只对可能命中的区块读取收据。
+//!
+//! #### 实现属性
+//! - <font color=Green>√</font> 多线程安全
+//! - <font color=Green>√</font> 无 unsafe 代码
+//!
+
+use std::ops::{BitOr, BitOrAssign};
+use tiny_keccak::keccak256;
+
+pub const BLOOM_SIZ: usize = 256;
+
+pub type Address = [u8; 20];
+pub type Topic = [u8; 32];
+
+///以太坊日志，仅 address 与 topics 参与 bloom 的计算
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Log {
+    pub address: Address,
+    pub topics: Vec<Topic>,
+    pub data: Vec<u8>,
+}
+
+#[derive(Clone)]
+pub struct LogsBloom([u8; BLOOM_SIZ]);
+
+impl Default for LogsBloom {
+    fn default() -> LogsBloom {
+        LogsBloom::new()
+    }
+}
+
+impl LogsBloom {
+    pub fn new() -> LogsBloom {
+        LogsBloom([0; BLOOM_SIZ])
+    }
+
+    ///#### 由区块头或收据中的原始字节构造
+    pub fn from_bytes(bytes: [u8; BLOOM_SIZ]) -> LogsBloom {
+        LogsBloom(bytes)
+    }
+
+    #[inline(always)]
+    pub fn as_bytes(&self) -> &[u8; BLOOM_SIZ] {
+        &self.0
+    }
+
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.0.iter().all(|b| 0 == *b)
+    }
+
+    //- #: 3 个 (字节索引, 比特掩码)
+    fn positions(input: &[u8]) -> [(usize, u8); 3] {
+        let h = keccak256(input);
+        let mut res = [(0, 0); 3];
+        for (i, r) in res.iter_mut().enumerate() {
+            let idx = (((h[2 * i] as usize) << 8) | h[2 * i + 1] as usize) & 0x7ff;
+            *r = (BLOOM_SIZ - 1 - idx / 8, 1 << (idx % 8));
+        }
+        res
+    }
+
+    ///- @input[in]: 要添加的元素，即合约地址或某个 topic
+    pub fn accrue(&mut self, input: &[u8]) {
+        for (byte_idx, mask) in Self::positions(input).iter() {
+            self.0[*byte_idx] |= mask;
+        }
+    }
+
+    ///- @log[in]: 将日志的合约地址及全部 topic 加入过滤器
+    pub fn accrue_log(&mut self, log: &Log) {
+        self.accrue(&log.address);
+        log.topics.iter().for_each(|t| self.accrue(t));
+    }
+
+    ///#### 按位或合并另一个 bloom
+    pub fn accrue_bloom(&mut self, other: &LogsBloom) {
+        self.0
+            .iter_mut()
+            .zip(other.0.iter())
+            .for_each(|(me, x)| *me |= x);
+    }
+
+    ///#### 计算单个收据的 bloom
+    ///- @logs[in]: 收据中的全部日志
+    pub fn from_logs(logs: &[Log]) -> LogsBloom {
+        let mut res = LogsBloom::new();
+        logs.iter().for_each(|l| res.accrue_log(l));
+        res
+    }
+
+    ///#### 计算区块的 bloom
+    ///- @receipts[in]: 区块中全部收据的 bloom
+    pub fn from_receipts(receipts: &[LogsBloom]) -> LogsBloom {
+        receipts.iter().fold(LogsBloom::new(), |acc, r| acc | r)
+    }
+
+    ///- @input[in]: 要查找的元素，即合约地址或某个 topic
+    pub fn contains_input(&self, input: &[u8]) -> bool {
+        Self::positions(input)
+            .iter()
+            .all(|(byte_idx, mask)| mask == &(self.0[*byte_idx] & mask))
+    }
+
+    ///- #: other 中的每个比特位在 self 中是否均已置位
+    pub fn contains_bloom(&self, other: &LogsBloom) -> bool {
+        self.0
+            .iter()
+            .zip(other.0.iter())
+            .all(|(me, x)| x == &(me & x))
+    }
+
+    ///#### 判断区块是否可能包含满足条件的日志
+    ///- #: false 表示一定不包含；true 表示可能包含，须读取收据确认
+    ///- @address[in]: 合约地址，None 表示不限
+    ///- @topics[in]: 日志须同时包含的全部 topic
+    pub fn might_contain(&self, address: Option<&Address>, topics: &[Topic]) -> bool {
+        address.is_none_or(|a| self.contains_input(a))
+            && topics.iter().all(|t| self.contains_input(t))
+    }
+}
+
+impl<'a> BitOr<&'a LogsBloom> for LogsBloom {
+    type Output = LogsBloom;
+    fn bitor(mut self, rhs: &'a LogsBloom) -> LogsBloom {
+        self.accrue_bloom(rhs);
+        self
+    }
+}
+
+impl<'a> BitOrAssign<&'a LogsBloom> for LogsBloom {
+    fn bitor_assign(&mut self, rhs: &'a LogsBloom) {
+        self.accrue_bloom(rhs);
+    }
+}
+
+impl PartialEq for LogsBloom {
+    fn eq(&self, other: &LogsBloom) -> bool {
+        self.0[..] == other.0[..]
+    }
+}
+
+impl Eq for LogsBloom {}
+
+impl std::fmt::Debug for LogsBloom {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        self.0.iter().try_for_each(|b| write!(f, "{:02x}", b))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn log(address: u8, topics: &[u8]) -> Log {
+        Log {
+            address: [address; 20],
+            topics: topics.iter().map(|t| [*t; 32]).collect(),
+            data: vec![address; 64],
+        }
+    }
+
+    //keccak256("") = c5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470,
+    //indices: 0xc5d2 & 0x7ff = 1490, 0x4601 & 0x7ff = 1537, 0x86f7 & 0x7ff = 1783
+    #[test]
+    fn accrue() {
+        let mut bloom = LogsBloom::new();
+        assert!(bloom.is_empty());
+        bloom.accrue(&[]);
+
+        let mut expected = [0u8; BLOOM_SIZ];
+        expected[255 - 1490 / 8] = 1 << (1490 % 8);
+        expected[255 - 1537 / 8] = 1 << (1537 % 8);
+        expected[255 - 1783 / 8] = 1 << (1783 % 8);
+        assert_eq!(LogsBloom::from_bytes(expected), bloom);
+        assert_eq!(
+            3,
+            bloom.as_bytes().iter().map(|b| b.count_ones()).sum::<u32>()
+        );
+        assert!(bloom.contains_input(&[]));
+    }
+
+    #[test]
+    fn block_bloom() {
+        let r0 = LogsBloom::from_logs(&[log(1, &[10, 11]), log(2, &[])]);
+        let r1 = LogsBloom::from_logs(&[log(3, &[12])]);
+        let r2 = LogsBloom::from_logs(&[]);
+        assert!(r2.is_empty());
+
+        let block = LogsBloom::from_receipts(&[r0.clone(), r1.clone(), r2]);
+        assert!(block.contains_bloom(&r0));
+        assert!(block.contains_bloom(&r1));
+        assert!(!r0.contains_bloom(&block));
+
+        let mut acc = r0.clone();
+        acc |= &r1;
+        assert_eq!(acc, block);
+
+        assert!(block.might_contain(Some(&[1; 20]), &[[10; 32]]));
+        assert!(block.might_contain(Some(&[1; 20]), &[[10; 32], [11; 32]]));
+        assert!(block.might_contain(Some(&[3; 20]), &[[12; 32]]));
+        assert!(block.might_contain(None, &[[12; 32]]));
+        assert!(block.might_contain(Some(&[2; 20]), &[]));
+        assert!(!r1.might_contain(Some(&[1; 20]), &[]));
+        assert!(!r0.might_contain(None, &[[12; 32]]));
+
+        //log data never takes part
+        assert!(!block.contains_input(&[1; 64]));
+    }
+    //TestBloom and TestBloomExtensively of go-ethereum core/types/bloom9_test.go
+    #[test]
+    fn go_ethereum() {
+        let mut bloom = LogsBloom::new();
+        for x in ["testtest", "test", "hallo", "other"].iter() {
+            bloom.accrue(x.as_bytes());
+        }
+        assert!(["testtest", "test", "hallo", "other"]
+            .iter()
+            .all(|x| bloom.contains_input(x.as_bytes())));
+        assert!(!bloom.contains_input(b"tes"));
+        assert!(!bloom.contains_input(b"lo"));
+
+        let mut bloom = LogsBloom::new();
+        for i in 0..100 {
+            bloom.accrue(format!("xxxxxxxxxx data {} yyyyyyyyyyyyyy", i).as_bytes());
+        }
+        let expected = [
+            0xc8, 0xd3, 0xca, 0x65, 0xcd, 0xb4, 0x87, 0x43, 0x00, 0xa9, 0xe3, 0x94, 0x75, 0x50,
+            0x8f, 0x23, 0xed, 0x6d, 0xa0, 0x9f, 0xdb, 0xc4, 0x87, 0xf8, 0x9a, 0x2d, 0xcf, 0x50,
+            0xb0, 0x9e, 0xb2, 0x63,
+        ];
+        assert_eq!(expected, keccak256(bloom.as_bytes()));
+    }
+}
@@ -1,4 +1,168 @@
 //! ## 布隆过滤器
 
-mod origin;
-mod partial;
+pub mod bip37;
+pub mod counting;
+pub mod cuckoo;
+pub mod error;
+pub mod fasthash;
+pub mod logs_bloom;
+pub mod origin;
+pub mod partial;
+pub mod scalable;
+
+use error::XErr;
+use std::f64::consts::LN_2;
+
+///序列化头部的长度：哈希方案(1 字节) + k(u32 LE) + m(u64 LE)
+pub const HEADER_SIZ: usize = 13;
+
+///哈希次数的上限，反序列化时拒绝更大的 k，以免构造出每次插入都要计算海量哈希的过滤器
+pub const MAX_K: usize = 64;
+
+///索引的计算方式，记录在序列化头部中，方案不同的过滤器不能合并
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HashScheme {
+    ///origin::BloomFilter：第 i 个哈希为以 i 为盐值的 SHA1
+    SaltedSha1,
+    ///partial::ParBloomFilter：各分区轮流使用 SHA1/SHA256/SHA384
+    ShaRotation,
+    ///partial::ParBloomFilter：以编号为 id 的 FastHasher 做 double hashing
+    DoubleHashing(u8),
+}
+
+impl HashScheme {
+    fn to_u8(self) -> u8 {
+        match self {
+            HashScheme::SaltedSha1 => 1,
+            HashScheme::ShaRotation => 2,
+            HashScheme::DoubleHashing(id) => {
+                //FastHasher 的编号不超过 0x7f，不会互相混淆
+                debug_assert!(id <= 0x7f);
+                0x80 | id
+            }
+        }
+    }
+
+    fn from_u8(n: u8) -> Result<HashScheme, XErr> {
+        match n {
+            1 => Ok(HashScheme::SaltedSha1),
+            2 => Ok(HashScheme::ShaRotation),
+            n if 0 < n & 0x80 => Ok(HashScheme::DoubleHashing(n & 0x7f)),
+            _ => Err(XErr::UnknownScheme(n)),
+        }
+    }
+}
+
+//- #: 序列化头部
+fn encode_header(scheme: HashScheme, m: usize, k: usize) -> Vec<u8> {
+    let mut res = Vec::with_capacity(HEADER_SIZ + m / 8);
+    res.push(scheme.to_u8());
+    res.extend_from_slice(&(k as u32).to_le_bytes());
+    res.extend_from_slice(&(m as u64).to_le_bytes());
+    res
+}
+
+//- #: (scheme, m, k, 索引数组)，索引数组的长度须恰好为 m / 8 字节
+fn decode_header(bytes: &[u8]) -> Result<(HashScheme, usize, usize, &[u8]), XErr> {
+    if bytes.len() < HEADER_SIZ {
+        return Err(XErr::LengthMismatch);
+    }
+    let scheme = HashScheme::from_u8(bytes[0])?;
+
+    let mut k = [0; 4];
+    k.copy_from_slice(&bytes[1..5]);
+    let k = u32::from_le_bytes(k) as usize;
+    let mut m = [0; 8];
+    m.copy_from_slice(&bytes[5..HEADER_SIZ]);
+    let m = u64::from_le_bytes(m) as usize;
+
+    if 0 == m || !(1..=MAX_K).contains(&k) || !m.is_multiple_of(8) {
+        return Err(XErr::InvalidParams);
+    }
+    let body = &bytes[HEADER_SIZ..];
+    if body.len() * 8 != m {
+        return Err(XErr::LengthMismatch);
+    }
+    Ok((scheme, m, k, body))
+}
+
+//根据置位 bit 的数量反推元素数量，用于合并或反序列化之后：n = -(m/k)·ln(1 - X/m)
+//全部 bit 均已置位时 ln(0) 为 -inf，此时及估算超出 m 时均取 m
+//- @m[in]: 索引数组的容量
+//- @k[in]: 哈希次数
+//- @bit_used[in]: 已被置为 1 的 bit 总数
+fn estimate_items(m: usize, k: usize, bit_used: usize) -> usize {
+    if m <= bit_used {
+        return m;
+    }
+    let est = -(m as f64 / k as f64) * (1.0 - bit_used as f64 / m as f64).ln();
+    (est.round() as usize).min(m)
+}
+
+///#### 根据预期元素数量与目标误判率，计算最优的索引数组容量与哈希次数
+///- m = -n * ln(p) / (ln2)^2，向上对齐到整字节
+///- k = m / n * ln2，至少为 1
+///- #: (m, k)，m 以 bit 为单位
+///- @expected_items[in]: 预期被索引的元素总量，须大于 0
+///- @fp_rate[in]: 目标误判率，取值范围 (0, 1)
+pub fn optimal_params(expected_items: usize, fp_rate: f64) -> (usize, usize) {
+    assert!(0 < expected_items, "expected_items must be positive");
+    assert!(0.0 < fp_rate && fp_rate < 1.0, "fp_rate must be in (0, 1)");
+
+    let n = expected_items as f64;
+    let m = (-n * fp_rate.ln() / (LN_2 * LN_2)).ceil() as usize;
+    let m = m.div_ceil(8) * 8;
+    let k = ((m as f64 / n) * LN_2).round().max(1.0) as usize;
+
+    (m, k)
+}
+
+#[cfg(test)]
+mod test {
+    #[test]
+    fn optimal_params() {
+        let (m, k) = super::optimal_params(100_0000, 0.01);
+        assert_eq!(0, m % 8);
+        assert!(9_585_000 < m && m < 9_586_000);
+        assert_eq!(7, k);
+
+        assert_eq!(1, super::optimal_params(100, 0.5).1);
+    }
+
+    #[test]
+    fn header() {
+        use super::*;
+
+        let mut bytes = encode_header(HashScheme::ShaRotation, 16, 3);
+        bytes.extend_from_slice(&[0xff, 0x01]);
+        let (scheme, m, k, body) = decode_header(&bytes).unwrap();
+        assert_eq!((HashScheme::ShaRotation, 16, 3), (scheme, m, k));
+        assert_eq!(&[0xff, 0x01], body);
+
+        assert!(decode_header(&bytes[..HEADER_SIZ + 1]).is_err());
+        assert!(decode_header(&bytes[..HEADER_SIZ - 1]).is_err());
+        bytes[0] = 0x82;
+        assert_eq!(
+            HashScheme::DoubleHashing(2),
+            decode_header(&bytes).unwrap().0
+        );
+        bytes[0] = 0;
+        assert!(decode_header(&bytes).is_err());
+
+        //k is bounded, even when the body length is consistent
+        for (k, ok) in [(0, false), (1, true), (MAX_K, true), (MAX_K + 1, false)] {
+            let mut bytes = encode_header(HashScheme::SaltedSha1, 16, k);
+            bytes.extend_from_slice(&[0; 2]);
+            assert_eq!(ok, decode_header(&bytes).is_ok(), "{}", k);
+        }
+        let mut bytes = encode_header(HashScheme::SaltedSha1, 16, 1);
+        bytes[1..5].copy_from_slice(&u32::MAX.to_le_bytes());
+        bytes.extend_from_slice(&[0; 2]);
+        assert!(matches!(decode_header(&bytes), Err(XErr::InvalidParams)));
+
+        assert_eq!(0, estimate_items(1024, 7, 0));
+        assert_eq!(102, estimate_items(1024, 1, 97));
+        assert_eq!(1024, estimate_items(1024, 7, 1024));
+        assert_eq!(1024, estimate_items(1024, 1, 1023));
+    }
+}
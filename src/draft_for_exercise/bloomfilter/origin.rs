@@ -6,7 +6,11 @@
 //! - 但对于类似区块链这种只增不删的场景，特别适合；
 //! - 用于快速检索大数据集，以允许少量错判为代价，兼顾时间与空间两方面的效率；
 //! - 难点在于如何根据具体的场景，选定最优的哈希函数、哈希次数及索引数组的容量；
-//! - 合适的索引数组容量计算公式：m = kn / LN_2， k指哈希函数数量，n指被索引的数据总量。
+//! - 合适的索引数组容量计算公式：m = kn / LN_2， k指哈希函数数量，n指被索引的数据总量；
+//! - 也可根据预期元素数量 n 与目标误判率 p 计算最优参数：m = -n·ln(p)/(ln2)^2，k = m/n·ln2；
+//! - 误判率可根据索引数组的填充率实时估算：(bit_used / m) ^ k；
+//! - m、k 及哈希方案均相同的两个过滤器可以合并：按位或得到并集，按位与得到交集(的近似)；
+//! - 序列化格式为 HEADER_SIZ 字节的头部(哈希方案、k、m)后接索引数组，便于节点之间交换清单过滤器。
 //!
 //! #### 应用场景
 //! - 区块链轻节点校验交易：由于轻节点仅有区块头信息，并无完整的交易数据，故首先要粗略定位至可能含有目标交易的区块，之后只向全节点请求经过布隆过滤器筛选出的一个或多个区块的数据。
@@ -16,6 +20,7 @@
 //! - <font color=Green>√</font> 无 unsafe 代码
 //!
 
+use super::{error::XErr, HashScheme};
 use ring::digest::{Context, SHA1};
 
 const USIZE_SIZ: usize = std::mem::size_of::<usize>();
@@ -31,16 +36,25 @@ const BIT_SET: [u8; BYTE_BITS] = [
     0b1000_0000,
 ];
 
+//默认参数，仅用于 BloomFilter::new()
 const N: usize = 100_0000;
 const K: usize = 1;
 const M: usize = ((K * N) as f32 / std::f32::consts::LN_2) as usize / BYTE_BITS * BYTE_BITS;
-const BLOOM_SIZ: usize = M / BYTE_BITS;
 
-#[derive(Debug, Default)]
+//- @filter: 索引数组
+//- @m: 索引数组的容量，以 bit 为单位
+//- @k: 哈希次数
+//- @item_cnt: 已添加的元素总数
+//- @bit_used: 已被置为 1 的 bit 总数
+//- @fp_cnt: 添加时全部 k 个 bit 均已被置位的次数，即发生误判(或重复添加)的次数
+#[derive(Debug)]
 pub struct BloomFilter {
     filter: Vec<u8>,
+    m: usize,
+    k: usize,
     item_cnt: usize,
     bit_used: usize,
+    fp_cnt: usize,
 }
 
 ///元素位置
@@ -50,57 +64,124 @@ pub struct Position {
     bit_idx: usize,
 }
 
+impl Default for BloomFilter {
+    fn default() -> BloomFilter {
+        BloomFilter::new()
+    }
+}
+
 impl BloomFilter {
+    ///#### 使用默认参数初始化：N = 100_0000, K = 1
     pub fn new() -> BloomFilter {
+        BloomFilter::with_params(M, K)
+    }
+
+    ///#### 根据预期元素数量与目标误判率，使用最优的 m 与 k 初始化
+    ///- @expected_items[in]: 预期被索引的元素总量
+    ///- @fp_rate[in]: 目标误判率，取值范围 (0, 1)
+    pub fn with_capacity(expected_items: usize, fp_rate: f64) -> BloomFilter {
+        let (m, k) = super::optimal_params(expected_items, fp_rate);
+        BloomFilter::with_params(m, k)
+    }
+
+    ///#### 使用指定的参数初始化
+    ///- @m[in]: 索引数组的容量，以 bit 为单位，向上对齐到整字节
+    ///- @k[in]: 哈希次数，不超过 MAX_K
+    pub fn with_params(m: usize, k: usize) -> BloomFilter {
+        assert!(0 < m && 0 < k, "m and k must be positive");
+        assert!(k <= super::MAX_K, "k must not exceed MAX_K");
+        let bloom_siz = m.div_ceil(BYTE_BITS);
         BloomFilter {
-            filter: vec![0; BLOOM_SIZ],
+            filter: vec![0; bloom_siz],
+            m: bloom_siz * BYTE_BITS,
+            k,
             item_cnt: 0,
             bit_used: 0,
+            fp_cnt: 0,
         }
     }
 
     pub fn clear(&mut self) {
-        self.filter = vec![0; BLOOM_SIZ];
+        self.filter = vec![0; self.filter.len()];
         self.item_cnt = 0;
         self.bit_used = 0;
+        self.fp_cnt = 0;
     }
 
-    fn hash(item: &[u8]) -> Position {
-        let mut context = Context::new(&SHA1);
-        context.update(item);
-        let id = context.finish();
-        let id = id.as_ref();
-        assert!(USIZE_SIZ < id.len());
+    ///- #: 索引数组的容量，以 bit 为单位
+    #[inline(always)]
+    pub fn m(&self) -> usize {
+        self.m
+    }
 
-        let mut buf = [0; USIZE_SIZ];
-        id[0..USIZE_SIZ]
-            .iter()
-            .enumerate()
-            .for_each(|(i, v)| buf[i] = *v);
-        let id = usize::from_le_bytes(buf) % M;
+    ///- #: 哈希次数
+    #[inline(always)]
+    pub fn k(&self) -> usize {
+        self.k
+    }
 
-        Position {
-            byte_idx: id / BYTE_BITS,
-            bit_idx: id % BYTE_BITS,
-        }
+    ///- #: 已添加的元素总数
+    #[inline(always)]
+    pub fn len(&self) -> usize {
+        self.item_cnt
+    }
+
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        0 == self.item_cnt
+    }
+
+    //第 i 个哈希函数：以 i 为盐值的 SHA1，i 为 0 时不加盐
+    fn hash(&self, item: &[u8]) -> Vec<Position> {
+        (0..self.k)
+            .map(|i| {
+                let mut context = Context::new(&SHA1);
+                if 0 < i {
+                    context.update(&(i as u64).to_le_bytes());
+                }
+                context.update(item);
+                let id = context.finish();
+                let id = id.as_ref();
+                assert!(USIZE_SIZ < id.len());
+
+                let mut buf = [0; USIZE_SIZ];
+                id[0..USIZE_SIZ]
+                    .iter()
+                    .enumerate()
+                    .for_each(|(i, v)| buf[i] = *v);
+                let id = usize::from_le_bytes(buf) % self.m;
+
+                Position {
+                    byte_idx: id / BYTE_BITS,
+                    bit_idx: id % BYTE_BITS,
+                }
+            })
+            .collect()
     }
 
     ///- @item[in]: 要添加的元素
-    pub fn set(&mut self, item: &[u8]) -> Position {
-        let p = Self::hash(item);
-        if !self.find_by_position(&p) {
-            self.bit_used += 1;
+    pub fn set(&mut self, item: &[u8]) -> Vec<Position> {
+        let ps = self.hash(item);
+        let mut hit = true;
+        for p in ps.iter() {
+            if !self.find_by_position(p) {
+                hit = false;
+                self.bit_used += 1;
+                self.filter[p.byte_idx] = set_bit(self.filter[p.byte_idx], p.bit_idx);
+            }
+        }
+        if hit {
+            self.fp_cnt += 1;
         }
-        self.item_cnt += 1;
-        self.filter[p.byte_idx] = set_bit(self.filter[p.byte_idx], p.bit_idx);
-        p
+        self.item_cnt = self.item_cnt.saturating_add(1);
+        ps
     }
 
     ///- @item[in]: 要查找的元素
-    pub fn find(&self, item: &[u8]) -> Option<Position> {
-        let p = Self::hash(item);
-        if self.find_by_position(&p) {
-            Some(p)
+    pub fn find(&self, item: &[u8]) -> Option<Vec<Position>> {
+        let ps = self.hash(item);
+        if ps.iter().all(|p| self.find_by_position(p)) {
+            Some(ps)
         } else {
             None
         }
@@ -110,8 +191,76 @@ impl BloomFilter {
         check_bit(self.filter[p.byte_idx], p.bit_idx)
     }
 
+    //- #: 参数不一致时返回 XErr::Incompatible
+    fn check_compatible(&self, other: &BloomFilter) -> Result<(), XErr> {
+        if self.m != other.m || self.k != other.k {
+            return Err(XErr::Incompatible);
+        }
+        Ok(())
+    }
+
+    //按位运算之后重新统计 bit_used，并据此估算元素数量
+    fn recount(&mut self) {
+        self.bit_used = self.filter.iter().map(|b| b.count_ones() as usize).sum();
+        self.item_cnt = super::estimate_items(self.m, self.k, self.bit_used);
+    }
+
+    ///#### 并集：结果与对两个集合的并集重新构建的过滤器完全相同
+    ///- @other[in]: m、k 均相同的过滤器
+    pub fn union(&mut self, other: &BloomFilter) -> Result<(), XErr> {
+        self.check_compatible(other)?;
+        self.filter
+            .iter_mut()
+            .zip(other.filter.iter())
+            .for_each(|(me, x)| *me |= x);
+        self.fp_cnt += other.fp_cnt;
+        self.recount();
+        Ok(())
+    }
+
+    ///#### 交集：交集中的元素一定命中，但误判率高于对交集重新构建的过滤器
+    ///- @other[in]: m、k 均相同的过滤器
+    pub fn intersect(&mut self, other: &BloomFilter) -> Result<(), XErr> {
+        self.check_compatible(other)?;
+        self.filter
+            .iter_mut()
+            .zip(other.filter.iter())
+            .for_each(|(me, x)| *me &= x);
+        self.fp_cnt = 0;
+        self.recount();
+        Ok(())
+    }
+
+    ///#### 序列化
+    ///- #: 头部(哈希方案、k、m) + 索引数组
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut res = super::encode_header(HashScheme::SaltedSha1, self.m, self.k);
+        res.extend_from_slice(&self.filter);
+        res
+    }
+
+    ///#### 反序列化，元素数量根据置位 bit 的数量估算
+    ///- @bytes[in]: to_bytes 的输出
+    pub fn from_bytes(bytes: &[u8]) -> Result<BloomFilter, XErr> {
+        let (scheme, m, k, body) = super::decode_header(bytes)?;
+        if HashScheme::SaltedSha1 != scheme {
+            return Err(XErr::Incompatible);
+        }
+
+        let mut res = BloomFilter::with_params(m, k);
+        res.filter.copy_from_slice(body);
+        res.recount();
+        Ok(res)
+    }
+
     pub fn false_positive_cnt(&self) -> usize {
-        self.item_cnt - self.bit_used
+        self.fp_cnt
+    }
+
+    ///#### 根据索引数组的填充率估算当前的误判率
+    ///- #: (bit_used / m) ^ k
+    pub fn estimated_fp_rate(&self) -> f64 {
+        (self.bit_used as f64 / self.m as f64).powi(self.k as i32)
     }
 }
 
@@ -153,4 +302,110 @@ mod test {
         let item = 2u64.to_le_bytes();
         assert!(bf.find(&item).is_none());
     }
+
+    #[test]
+    fn with_capacity() {
+        let n = 10_0000;
+        let mut bf = BloomFilter::with_capacity(n, 0.01);
+        assert_eq!(7, bf.k());
+        assert_eq!(0, bf.m() % BYTE_BITS);
+        assert_eq!(0.0, bf.estimated_fp_rate());
+
+        for i in 0..n {
+            bf.set(&i.to_le_bytes());
+        }
+        for i in 0..n {
+            assert!(bf.find(&i.to_le_bytes()).is_some());
+        }
+        assert!(bf.bit_used <= n * bf.k());
+
+        let fp = bf.estimated_fp_rate();
+        assert!(0.005 < fp && fp < 0.015);
+        let fp_cnt = (n..2 * n)
+            .filter(|i| bf.find(&i.to_le_bytes()).is_some())
+            .count();
+        assert!((fp_cnt as f64) < 0.015 * n as f64);
+        assert!(bf.false_positive_cnt() < n / 50);
+
+        let bf = BloomFilter::with_params(1001, 3);
+        assert_eq!(1008, bf.m());
+        assert_eq!(3, bf.k());
+    }
+
+    #[test]
+    fn combine() {
+        let mut a = BloomFilter::with_capacity(1000, 0.01);
+        let mut b = BloomFilter::with_capacity(1000, 0.01);
+        let mut ab = BloomFilter::with_capacity(1000, 0.01);
+        for i in 0..600usize {
+            a.set(&i.to_le_bytes());
+            ab.set(&i.to_le_bytes());
+        }
+        for i in 400..1000usize {
+            b.set(&i.to_le_bytes());
+            ab.set(&i.to_le_bytes());
+        }
+
+        let mut union = BloomFilter::from_bytes(&a.to_bytes()).unwrap();
+        union.union(&b).unwrap();
+        assert_eq!(ab.filter, union.filter);
+        assert_eq!(ab.bit_used, union.bit_used);
+        assert!(950 < union.item_cnt && union.item_cnt < 1050);
+
+        let mut inter = BloomFilter::from_bytes(&a.to_bytes()).unwrap();
+        inter.intersect(&b).unwrap();
+        for i in 400..600usize {
+            assert!(inter.find(&i.to_le_bytes()).is_some());
+        }
+        let fp_cnt = (0..400usize)
+            .chain(600..1000)
+            .filter(|i| inter.find(&i.to_le_bytes()).is_some())
+            .count();
+        assert!(fp_cnt < 100);
+
+        assert!(a.union(&BloomFilter::with_capacity(1000, 0.001)).is_err());
+        assert!(a.intersect(&BloomFilter::with_params(a.m(), 1)).is_err());
+    }
+
+    #[test]
+    fn serialize() {
+        let mut bf = BloomFilter::with_params(1001, 3);
+        for i in 0..100usize {
+            bf.set(&i.to_le_bytes());
+        }
+
+        let bytes = bf.to_bytes();
+        assert_eq!(super::super::HEADER_SIZ + 126, bytes.len());
+        assert_eq!(&[1, 3, 0, 0, 0, 0xf0, 0x03, 0, 0, 0, 0, 0, 0], &bytes[..13]);
+
+        let de = BloomFilter::from_bytes(&bytes).unwrap();
+        assert_eq!((bf.m(), bf.k()), (de.m(), de.k()));
+        assert_eq!(bf.filter, de.filter);
+        assert_eq!(bf.bit_used, de.bit_used);
+        for i in 0..100usize {
+            assert!(de.find(&i.to_le_bytes()).is_some());
+        }
+
+        //truncated, wrong scheme, bogus parameters
+        assert!(BloomFilter::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        let mut bad = bytes.clone();
+        bad[0] = 2;
+        assert!(BloomFilter::from_bytes(&bad).is_err());
+        let mut bad = bytes;
+        bad[1] = 0;
+        assert!(BloomFilter::from_bytes(&bad).is_err());
+
+        //a saturated filter, e.g. the union of two full ones, keeps a finite estimate
+        let mut full = BloomFilter::with_params(1024, 3).to_bytes();
+        full[super::super::HEADER_SIZ..]
+            .iter_mut()
+            .for_each(|b| *b = 0xff);
+        let mut de = BloomFilter::from_bytes(&full).unwrap();
+        assert_eq!(1024, de.len());
+        de.union(&BloomFilter::from_bytes(&full).unwrap()).unwrap();
+        assert_eq!(1024, de.len());
+        de.set(b"item");
+        assert_eq!(1025, de.len());
+        assert!(de.find(b"anything").is_some());
+    }
 }
@@ -5,7 +5,13 @@
 //! - 较原始版本准确率更高，且分区间互相独立，可并发计算哈希，效率也更高；
 //! - 用于快速检索大数据集，以允许少量错判为代价，兼顾时间与空间两方面的效率；
 //! - 难点在于如何根据具体的场景，选定最优的哈希函数、哈希次数及索引数组的容量；
-//! - 合适的索引数组容量计算公式：m = kn/ln2， k指哈希函数数量，n指被索引的数据总量。
+//! - 合适的索引数组容量计算公式：m = kn/ln2， k指哈希函数数量，n指被索引的数据总量；
+//! - 也可根据预期元素数量 n 与目标误判率 p 计算最优参数：m = -n·ln(p)/(ln2)^2，k = m/n·ln2；
+//! - 每个分区的容量为 m/k，误判率可根据各分区的填充率实时估算：∏(bit_used_i / (m/k))；
+//! - m、k 及哈希方案均相同的两个过滤器可以逐分区合并：按位或得到并集，按位与得到交集(的近似)；
+//! - 序列化格式为 HEADER_SIZ 字节的头部(哈希方案、k、m)后接各分区的索引数组；
+//! - 默认每个分区各算一次完整的密码学摘要，开销较大；可改用 double hashing 模式：
+//!   以快速哈希算出 h1、h2，第 i 个分区的索引取 (h1 + i·h2) mod (m/k)，见 fasthash 模块。
 //!
 //! #### 应用场景
 //! - 区块链轻节点校验交易：由于轻节点仅有区块头信息，并无完整的交易数据，故首先要粗略定位至可能含有目标交易的区块，之后只向全节点请求经过布隆过滤器筛选出的一个或多个区块的数据。
@@ -15,7 +21,8 @@
 //! - <font color=Green>√</font> 无 unsafe 代码
 //!
 
-use ring::digest::{Context, SHA1, SHA256, SHA384};
+use super::{error::XErr, fasthash::FastHasher, HashScheme};
+use ring::digest::{Algorithm, Context, SHA1, SHA256, SHA384};
 use std::ops::{Deref, DerefMut};
 
 const USIZE_SIZ: usize = std::mem::size_of::<usize>();
@@ -31,10 +38,13 @@ const BIT_SET: [u8; BYTE_BITS] = [
     0b1000_0000,
 ];
 
+//默认参数，仅用于 ParBloomFilter::new()
 const N: usize = 100_0000;
 const K: usize = 3;
 const M: usize = (N as f32 / std::f32::consts::LN_2) as usize / BYTE_BITS * BYTE_BITS;
-const BLOOM_SIZ: usize = M / BYTE_BITS;
+
+//各分区依次循环使用的哈希算法
+const ALGOS: [&Algorithm; 3] = [&SHA1, &SHA256, &SHA384];
 
 #[derive(Debug, Default)]
 pub struct BloomFilter {
@@ -42,7 +52,13 @@ pub struct BloomFilter {
     item_cnt: usize,
     bit_used: usize,
 }
-pub struct ParBloomFilter(Vec<BloomFilter>);
+
+//- @partitions: 各分区
+//- @hasher: double hashing 模式所用的快速哈希，None 表示各分区轮流使用 SHA 摘要
+pub struct ParBloomFilter {
+    partitions: Vec<BloomFilter>,
+    hasher: Option<FastHasher>,
+}
 
 ///元素位置
 #[derive(Default)]
@@ -52,12 +68,40 @@ pub struct Position {
 }
 pub struct ParPosition(Vec<Position>);
 
+impl Default for ParBloomFilter {
+    fn default() -> ParBloomFilter {
+        ParBloomFilter::new()
+    }
+}
+
 impl ParBloomFilter {
+    ///#### 使用默认参数初始化：N = 100_0000, K = 3，每个分区的容量均为 M
     pub fn new() -> ParBloomFilter {
-        let mut res = ParBloomFilter(vec![]);
-        for _ in 0..K {
+        ParBloomFilter::with_params(M * K, K)
+    }
+
+    ///#### 根据预期元素数量与目标误判率，使用最优的 m 与 k 初始化
+    ///- @expected_items[in]: 预期被索引的元素总量
+    ///- @fp_rate[in]: 目标误判率，取值范围 (0, 1)
+    pub fn with_capacity(expected_items: usize, fp_rate: f64) -> ParBloomFilter {
+        let (m, k) = super::optimal_params(expected_items, fp_rate);
+        ParBloomFilter::with_params(m, k)
+    }
+
+    ///#### 使用指定的参数初始化
+    ///- @m[in]: 索引数组的总容量，以 bit 为单位，平均分配到 k 个分区，每个分区向上对齐到整字节
+    ///- @k[in]: 哈希次数，即分区数量，不超过 MAX_K
+    pub fn with_params(m: usize, k: usize) -> ParBloomFilter {
+        assert!(0 < m && 0 < k, "m and k must be positive");
+        assert!(k <= super::MAX_K, "k must not exceed MAX_K");
+        let bloom_siz = m.div_ceil(k * BYTE_BITS);
+        let mut res = ParBloomFilter {
+            partitions: Vec::with_capacity(k),
+            hasher: None,
+        };
+        for _ in 0..k {
             res.push(BloomFilter {
-                filter: vec![0; BLOOM_SIZ],
+                filter: vec![0; bloom_siz],
                 item_cnt: 0,
                 bit_used: 0,
             });
@@ -66,18 +110,58 @@ impl ParBloomFilter {
         res
     }
 
+    ///#### 使用 double hashing 模式初始化
+    ///- @m[in]: 索引数组的总容量，以 bit 为单位，平均分配到 k 个分区，每个分区向上对齐到整字节
+    ///- @k[in]: 哈希次数，即分区数量
+    ///- @hasher[in]: 快速哈希函数，如 fasthash::XXHASH64
+    pub fn with_double_hashing(m: usize, k: usize, hasher: FastHasher) -> ParBloomFilter {
+        let mut res = ParBloomFilter::with_params(m, k);
+        res.hasher = Some(hasher);
+        res
+    }
+
+    ///- #: 当前使用的哈希方案
+    pub fn scheme(&self) -> HashScheme {
+        match self.hasher {
+            Some(h) => HashScheme::DoubleHashing(h.id()),
+            None => HashScheme::ShaRotation,
+        }
+    }
+
     pub fn clear(&mut self) {
         self.iter_mut().for_each(|me| {
-            me.filter = vec![0; BLOOM_SIZ];
+            me.filter = vec![0; me.filter.len()];
             me.item_cnt = 0;
             me.bit_used = 0;
         })
     }
 
-    fn hash(item: &[u8]) -> ParPosition {
+    ///- #: 索引数组的总容量，以 bit 为单位
+    #[inline(always)]
+    pub fn m(&self) -> usize {
+        self.len() * self.partition_bits()
+    }
+
+    ///- #: 哈希次数，即分区数量
+    #[inline(always)]
+    pub fn k(&self) -> usize {
+        self.len()
+    }
+
+    #[inline(always)]
+    fn partition_bits(&self) -> usize {
+        self[0].filter.len() * BYTE_BITS
+    }
+
+    //第 i 个分区使用 ALGOS[i % 3]，超出一轮之后以轮次 i / 3 为盐值
+    fn digest_hash(&self, item: &[u8]) -> Vec<usize> {
+        let partition_bits = self.partition_bits();
         let mut idset = vec![];
-        for algo in &[&SHA1, &SHA256, &SHA384] {
-            let mut context = Context::new(algo);
+        for i in 0..self.k() {
+            let mut context = Context::new(ALGOS[i % ALGOS.len()]);
+            if ALGOS.len() <= i {
+                context.update(&((i / ALGOS.len()) as u64).to_le_bytes());
+            }
             context.update(item);
             let id = context.finish();
             let id = id.as_ref();
@@ -88,9 +172,26 @@ impl ParBloomFilter {
                 .iter()
                 .enumerate()
                 .for_each(|(i, v)| buf[i] = *v);
-            let id = usize::from_le_bytes(buf) % M;
+            let id = usize::from_le_bytes(buf) % partition_bits;
             idset.push(id);
         }
+        idset
+    }
+
+    //Kirsch–Mitzenmacher：第 i 个分区取 (h1 + i·h2) mod (m/k)
+    fn double_hash(&self, hasher: FastHasher, item: &[u8]) -> Vec<usize> {
+        let partition_bits = self.partition_bits() as u64;
+        let (h1, h2) = hasher.double(item);
+        (0..self.k() as u64)
+            .map(|i| (h1.wrapping_add(i.wrapping_mul(h2)) % partition_bits) as usize)
+            .collect()
+    }
+
+    fn hash(&self, item: &[u8]) -> ParPosition {
+        let idset = match self.hasher {
+            Some(hasher) => self.double_hash(hasher, item),
+            None => self.digest_hash(item),
+        };
 
         let mut res = ParPosition(vec![]);
         for id in idset {
@@ -105,13 +206,13 @@ impl ParBloomFilter {
 
     ///- @item[in]: 要添加的元素
     pub fn set(&mut self, item: &[u8]) -> ParPosition {
-        let par_p = Self::hash(item);
+        let par_p = self.hash(item);
 
         self.iter_mut().zip(par_p.iter()).for_each(|(me, p)| {
             if !check_bit(me.filter[p.byte_idx], p.bit_idx) {
                 me.bit_used += 1;
             }
-            me.item_cnt += 1;
+            me.item_cnt = me.item_cnt.saturating_add(1);
             me.filter[p.byte_idx] = set_bit(me.filter[p.byte_idx], p.bit_idx);
         });
 
@@ -120,7 +221,7 @@ impl ParBloomFilter {
 
     ///- @item[in]: 要查找的元素
     pub fn find(&self, item: &[u8]) -> Option<ParPosition> {
-        let par_p = Self::hash(item);
+        let par_p = self.hash(item);
         if self
             .iter()
             .zip(par_p.iter())
@@ -132,6 +233,100 @@ impl ParBloomFilter {
         }
     }
 
+    //- #: 参数不一致时返回 XErr::Incompatible，double hashing 模式要求编号与哈希函数均相同
+    fn check_compatible(&self, other: &ParBloomFilter) -> Result<(), XErr> {
+        if self.k() != other.k()
+            || self.partition_bits() != other.partition_bits()
+            || self.hasher != other.hasher
+        {
+            return Err(XErr::Incompatible);
+        }
+        Ok(())
+    }
+
+    //按位运算之后重新统计各分区的 bit_used，并据此估算元素数量
+    fn recount(&mut self) {
+        let partition_bits = self.partition_bits();
+        self.iter_mut().for_each(|me| {
+            me.bit_used = me.filter.iter().map(|b| b.count_ones() as usize).sum();
+            me.item_cnt = super::estimate_items(partition_bits, 1, me.bit_used);
+        });
+    }
+
+    ///#### 并集：结果与对两个集合的并集重新构建的过滤器完全相同
+    ///- @other[in]: m、k 均相同的过滤器
+    pub fn union(&mut self, other: &ParBloomFilter) -> Result<(), XErr> {
+        self.check_compatible(other)?;
+        self.iter_mut().zip(other.iter()).for_each(|(me, x)| {
+            me.filter
+                .iter_mut()
+                .zip(x.filter.iter())
+                .for_each(|(me, x)| *me |= x)
+        });
+        self.recount();
+        Ok(())
+    }
+
+    ///#### 交集：交集中的元素一定命中，但误判率高于对交集重新构建的过滤器
+    ///- @other[in]: m、k 均相同的过滤器
+    pub fn intersect(&mut self, other: &ParBloomFilter) -> Result<(), XErr> {
+        self.check_compatible(other)?;
+        self.iter_mut().zip(other.iter()).for_each(|(me, x)| {
+            me.filter
+                .iter_mut()
+                .zip(x.filter.iter())
+                .for_each(|(me, x)| *me &= x)
+        });
+        self.recount();
+        Ok(())
+    }
+
+    ///#### 序列化
+    ///- #: 头部(哈希方案、k、m) + 依次排列的各分区索引数组
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut res = super::encode_header(self.scheme(), self.m(), self.k());
+        self.iter().for_each(|me| res.extend_from_slice(&me.filter));
+        res
+    }
+
+    ///#### 反序列化，各分区的元素数量根据置位 bit 的数量估算
+    ///- double hashing 模式只能还原预置的快速哈希，自定义哈希返回 XErr::UnknownScheme，须改用 from_bytes_with
+    ///- @bytes[in]: to_bytes 的输出
+    pub fn from_bytes(bytes: &[u8]) -> Result<ParBloomFilter, XErr> {
+        ParBloomFilter::decode(bytes, None)
+    }
+
+    ///#### 反序列化使用自定义快速哈希的过滤器
+    ///- 头部记录的编号须与 hasher 一致，否则返回 XErr::Incompatible
+    ///- @bytes[in]: to_bytes 的输出
+    ///- @hasher[in]: 序列化时所用的快速哈希
+    pub fn from_bytes_with(bytes: &[u8], hasher: FastHasher) -> Result<ParBloomFilter, XErr> {
+        ParBloomFilter::decode(bytes, Some(hasher))
+    }
+
+    fn decode(bytes: &[u8], custom: Option<FastHasher>) -> Result<ParBloomFilter, XErr> {
+        let (scheme, m, k, body) = super::decode_header(bytes)?;
+        let hasher = match (scheme, custom) {
+            (HashScheme::ShaRotation, None) => None,
+            (HashScheme::DoubleHashing(id), None) => {
+                Some(FastHasher::preset(id).ok_or(XErr::UnknownScheme(0x80 | id))?)
+            }
+            (HashScheme::DoubleHashing(id), Some(h)) if id == h.id() => Some(h),
+            _ => return Err(XErr::Incompatible),
+        };
+        if !m.is_multiple_of(k * BYTE_BITS) {
+            return Err(XErr::InvalidParams);
+        }
+
+        let mut res = ParBloomFilter::with_params(m, k);
+        res.hasher = hasher;
+        res.iter_mut()
+            .zip(body.chunks(m / k / BYTE_BITS))
+            .for_each(|(me, x)| me.filter.copy_from_slice(x));
+        res.recount();
+        Ok(res)
+    }
+
     pub fn false_positive_cnt(&self) -> Vec<usize> {
         let mut res = vec![];
         self.iter().for_each(|me| {
@@ -139,6 +334,15 @@ impl ParBloomFilter {
         });
         res
     }
+
+    ///#### 根据各分区的填充率估算当前的误判率
+    ///- #: 各分区 bit_used / (m / k) 之积
+    pub fn estimated_fp_rate(&self) -> f64 {
+        let partition_bits = self.partition_bits() as f64;
+        self.iter()
+            .map(|me| me.bit_used as f64 / partition_bits)
+            .product()
+    }
 }
 
 //n取值范围：[0, 7]
@@ -157,7 +361,7 @@ fn set_bit(data: u8, n: usize) -> u8 {
 impl Deref for ParBloomFilter {
     type Target = Vec<BloomFilter>;
     fn deref(&self) -> &Self::Target {
-        &self.0
+        &self.partitions
     }
 }
 
@@ -170,7 +374,7 @@ impl Deref for ParPosition {
 
 impl DerefMut for ParBloomFilter {
     fn deref_mut(&mut self) -> &mut Self::Target {
-        &mut self.0
+        &mut self.partitions
     }
 }
 
@@ -209,4 +413,182 @@ mod test {
         let item = 2u64.to_le_bytes();
         assert!(bf.find(&item).is_none());
     }
+
+    #[test]
+    fn with_capacity() {
+        let n = 10_0000;
+        let mut bf = ParBloomFilter::with_capacity(n, 0.01);
+        assert_eq!(7, bf.k());
+        assert_eq!(0, bf.m() % (bf.k() * BYTE_BITS));
+        assert_eq!(0.0, bf.estimated_fp_rate());
+
+        for i in 0..n {
+            bf.set(&i.to_le_bytes());
+        }
+        for i in 0..n {
+            assert!(bf.find(&i.to_le_bytes()).is_some());
+        }
+
+        let fp = bf.estimated_fp_rate();
+        assert!(0.005 < fp && fp < 0.015);
+        let fp_cnt = (n..2 * n)
+            .filter(|i| bf.find(&i.to_le_bytes()).is_some())
+            .count();
+        assert!((fp_cnt as f64) < 0.015 * n as f64);
+
+        let bf = ParBloomFilter::with_params(1001, 3);
+        assert_eq!(1008, bf.m());
+        assert_eq!(3, bf.k());
+    }
+
+    #[test]
+    fn combine() {
+        let mut a = ParBloomFilter::with_capacity(1000, 0.01);
+        let mut b = ParBloomFilter::with_capacity(1000, 0.01);
+        let mut ab = ParBloomFilter::with_capacity(1000, 0.01);
+        for i in 0..600usize {
+            a.set(&i.to_le_bytes());
+            ab.set(&i.to_le_bytes());
+        }
+        for i in 400..1000usize {
+            b.set(&i.to_le_bytes());
+            ab.set(&i.to_le_bytes());
+        }
+
+        let mut union = ParBloomFilter::from_bytes(&a.to_bytes()).unwrap();
+        union.union(&b).unwrap();
+        for (me, x) in union.iter().zip(ab.iter()) {
+            assert_eq!(x.filter, me.filter);
+            assert_eq!(x.bit_used, me.bit_used);
+            assert!(950 < me.item_cnt && me.item_cnt < 1050);
+        }
+
+        let mut inter = ParBloomFilter::from_bytes(&a.to_bytes()).unwrap();
+        inter.intersect(&b).unwrap();
+        for i in 400..600usize {
+            assert!(inter.find(&i.to_le_bytes()).is_some());
+        }
+        let fp_cnt = (0..400usize)
+            .chain(600..1000)
+            .filter(|i| inter.find(&i.to_le_bytes()).is_some())
+            .count();
+        assert!(fp_cnt < 100);
+
+        assert!(a
+            .union(&ParBloomFilter::with_capacity(1000, 0.001))
+            .is_err());
+        assert!(a.intersect(&ParBloomFilter::with_params(a.m(), 1)).is_err());
+    }
+
+    #[test]
+    fn serialize() {
+        let mut bf = ParBloomFilter::with_params(1001, 3);
+        for i in 0..100usize {
+            bf.set(&i.to_le_bytes());
+        }
+
+        let bytes = bf.to_bytes();
+        assert_eq!(super::super::HEADER_SIZ + 126, bytes.len());
+        assert_eq!(&[2, 3, 0, 0, 0, 0xf0, 0x03, 0, 0, 0, 0, 0, 0], &bytes[..13]);
+
+        let de = ParBloomFilter::from_bytes(&bytes).unwrap();
+        assert_eq!((bf.m(), bf.k()), (de.m(), de.k()));
+        for (me, x) in de.iter().zip(bf.iter()) {
+            assert_eq!(x.filter, me.filter);
+            assert_eq!(x.bit_used, me.bit_used);
+        }
+        for i in 0..100usize {
+            assert!(de.find(&i.to_le_bytes()).is_some());
+        }
+
+        //truncated, wrong scheme, m not divisible into k partitions
+        assert!(ParBloomFilter::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        let mut bad = bytes.clone();
+        bad[0] = 1;
+        assert!(ParBloomFilter::from_bytes(&bad).is_err());
+        let mut bad = bytes;
+        bad[1] = 4;
+        assert!(ParBloomFilter::from_bytes(&bad).is_err());
+
+        //a saturated filter keeps a finite estimate in every partition
+        let mut full = ParBloomFilter::with_params(1024, 4).to_bytes();
+        full[super::super::HEADER_SIZ..]
+            .iter_mut()
+            .for_each(|b| *b = 0xff);
+        let mut de = ParBloomFilter::from_bytes(&full).unwrap();
+        de.union(&ParBloomFilter::from_bytes(&full).unwrap())
+            .unwrap();
+        assert!(de.iter().all(|me| 256 == me.item_cnt));
+        de.set(b"item");
+        assert!(de.iter().all(|me| 257 == me.item_cnt));
+        assert!(de.find(b"anything").is_some());
+    }
+
+    #[test]
+    fn double_hashing() {
+        use super::super::fasthash::{SIPHASH, XXHASH64};
+
+        let n = 10_0000;
+        let (m, k) = super::super::optimal_params(n, 0.01);
+        for hasher in &[SIPHASH, XXHASH64] {
+            let mut bf = ParBloomFilter::with_double_hashing(m, k, *hasher);
+            assert_eq!(HashScheme::DoubleHashing(hasher.id()), bf.scheme());
+            for i in 0..n {
+                bf.set(&i.to_le_bytes());
+            }
+            for i in 0..n {
+                assert!(bf.find(&i.to_le_bytes()).is_some());
+            }
+
+            let fp = bf.estimated_fp_rate();
+            assert!(0.005 < fp && fp < 0.015);
+            let fp_cnt = (n..2 * n)
+                .filter(|i| bf.find(&i.to_le_bytes()).is_some())
+                .count();
+            assert!((fp_cnt as f64) < 0.015 * n as f64);
+
+            //the scheme survives serialization
+            let de = ParBloomFilter::from_bytes(&bf.to_bytes()).unwrap();
+            assert_eq!(bf.scheme(), de.scheme());
+            assert!(de.find(&0usize.to_le_bytes()).is_some());
+
+            //filters with different hash schemes never combine
+            assert!(bf.union(&ParBloomFilter::with_params(m, k)).is_err());
+        }
+
+        let mut bytes = ParBloomFilter::with_double_hashing(1024, 4, SIPHASH).to_bytes();
+        bytes[0] = 0x80 | 0x3f;
+        assert!(ParBloomFilter::from_bytes(&bytes).is_err());
+    }
+
+    #[test]
+    fn custom_hasher() {
+        use super::super::fasthash::{xxh64, CUSTOM_IDS, SIPHASH};
+
+        fn rev(seed: u64, item: &[u8]) -> u64 {
+            xxh64(!seed, item)
+        }
+        let custom = FastHasher::custom(*CUSTOM_IDS.start(), xxh64).unwrap();
+        let mut bf = ParBloomFilter::with_double_hashing(1024, 4, custom);
+        for i in 0..50usize {
+            bf.set(&i.to_le_bytes());
+        }
+
+        //a custom id is never restored as some other hash
+        let bytes = bf.to_bytes();
+        assert!(ParBloomFilter::from_bytes(&bytes).is_err());
+        assert!(ParBloomFilter::from_bytes_with(&bytes, SIPHASH).is_err());
+        let de = ParBloomFilter::from_bytes_with(&bytes, custom).unwrap();
+        for i in 0..50usize {
+            assert!(de.find(&i.to_le_bytes()).is_some());
+        }
+
+        //the same id with another function does not combine
+        let other = FastHasher::custom(custom.id(), rev).unwrap();
+        let mut x = ParBloomFilter::with_double_hashing(1024, 4, other);
+        assert!(x.union(&bf).is_err());
+        assert!(x.intersect(&bf).is_err());
+        let mut y = ParBloomFilter::with_double_hashing(1024, 4, custom);
+        assert!(y.union(&bf).is_ok());
+    }
 }
@@ -0,0 +1,241 @@
+//! ## 布隆过滤器
+//!
+//! #### 算法说明
+//! - scalable bloom filter
+//! - 由一串容量逐级增长的子过滤器组成，当前子过滤器达到其设计容量后，追加一个新的子过滤器，新元素只写入最新的子过滤器；
+//! - 第 i 个子过滤器的容量为 n0 · s^i，误判率为 P · (1 - r) · r^i，其中 s 为增长倍数，r 为收紧系数；
+//! - 查找时依次检查全部子过滤器，总误判率不超过各子过滤器误判率之和：P · (1 - r) · Σr^i < P；
+//! - 子过滤器即原版布隆过滤器，复用其 Position 及 check_bit/set_bit；
+//! - 误判率逐级收紧使每一级的最优 k 递增，k 以 MAX_K 为上限，达到上限之后的子过滤器只靠增大容量降低误判率，
+//!   此时实际误判率可能略高于 P · (1 - r) · r^i；容量的增长在 usize::MAX 处饱和；
+//! - 添加前先查找，已命中的元素(含误判)不再写入，避免重复元素占用容量。
+//!
+//! #### 应用场景
+//! - 各区块的过滤器：区块中的交易数量差异巨大，固定容量的过滤器要么浪费内存，要么因过度填充而失效。
+//!
+//! #### 实现属性
+//! - <font color=Green>√</font> 多线程安全
+//! - <font color=Green>√</font> 无 unsafe 代码
+//!
+
+use super::origin::{BloomFilter, Position};
+use super::{optimal_params, MAX_K};
+
+//默认参数，仅用于 ScalableBloomFilter::with_capacity()
+const GROWTH: usize = 2;
+const TIGHTENING: f64 = 0.8;
+
+//- @filters: 全部子过滤器，最后一个为当前写入的子过滤器
+//- @initial_capacity: 第一个子过滤器的设计容量
+//- @capacity: 当前子过滤器的设计容量
+//- @fp_rate: 全局误判率上限 P
+//- @growth: 容量增长倍数 s
+//- @tightening: 误判率收紧系数 r
+#[derive(Debug)]
+pub struct ScalableBloomFilter {
+    filters: Vec<BloomFilter>,
+    initial_capacity: usize,
+    capacity: usize,
+    fp_rate: f64,
+    growth: usize,
+    tightening: f64,
+}
+
+impl ScalableBloomFilter {
+    ///#### 使用默认的增长倍数(2)与收紧系数(0.8)初始化
+    ///- @initial_capacity[in]: 第一个子过滤器的设计容量
+    ///- @fp_rate[in]: 全局误判率上限，取值范围 (0, 1)
+    pub fn with_capacity(initial_capacity: usize, fp_rate: f64) -> ScalableBloomFilter {
+        ScalableBloomFilter::with_params(initial_capacity, fp_rate, GROWTH, TIGHTENING)
+    }
+
+    ///#### 使用指定的参数初始化
+    ///- @initial_capacity[in]: 第一个子过滤器的设计容量
+    ///- @fp_rate[in]: 全局误判率上限，取值范围 (0, 1)
+    ///- @growth[in]: 容量增长倍数，至少为 2
+    ///- @tightening[in]: 误判率收紧系数，取值范围 (0, 1)
+    pub fn with_params(
+        initial_capacity: usize,
+        fp_rate: f64,
+        growth: usize,
+        tightening: f64,
+    ) -> ScalableBloomFilter {
+        assert!(0 < initial_capacity, "initial_capacity must be positive");
+        assert!(0.0 < fp_rate && fp_rate < 1.0, "fp_rate must be in (0, 1)");
+        assert!(2 <= growth, "growth must be at least 2");
+        assert!(
+            0.0 < tightening && tightening < 1.0,
+            "tightening must be in (0, 1)"
+        );
+
+        let mut res = ScalableBloomFilter {
+            filters: vec![],
+            initial_capacity,
+            capacity: initial_capacity,
+            fp_rate,
+            growth,
+            tightening,
+        };
+        res.filters.push(res.sub_filter(0));
+        res
+    }
+
+    pub fn clear(&mut self) {
+        *self = ScalableBloomFilter::with_params(
+            self.initial_capacity,
+            self.fp_rate,
+            self.growth,
+            self.tightening,
+        );
+    }
+
+    //- #: 第 i 个子过滤器的误判率 P · (1 - r) · r^i
+    #[inline(always)]
+    fn sub_fp_rate(&self, i: usize) -> f64 {
+        self.fp_rate * (1.0 - self.tightening) * self.tightening.powi(i as i32)
+    }
+
+    //- #: 第 i 个子过滤器，容量为当前的设计容量，k 不超过 MAX_K
+    fn sub_filter(&self, i: usize) -> BloomFilter {
+        let fp = self.sub_fp_rate(i).max(f64::MIN_POSITIVE);
+        let (m, k) = optimal_params(self.capacity, fp);
+        BloomFilter::with_params(m, k.min(MAX_K))
+    }
+
+    ///- #: 已写入的元素总数
+    pub fn len(&self) -> usize {
+        self.filters.iter().map(|f| f.len()).sum()
+    }
+
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.filters.iter().all(|f| f.is_empty())
+    }
+
+    ///- #: 子过滤器的数量
+    #[inline(always)]
+    pub fn filter_cnt(&self) -> usize {
+        self.filters.len()
+    }
+
+    ///- #: 全部子过滤器的索引数组总容量，以 bit 为单位
+    pub fn m(&self) -> usize {
+        self.filters.iter().map(|f| f.m()).sum()
+    }
+
+    ///- #: 当前各子过滤器误判率之和，恒小于全局上限 P
+    pub fn fp_rate_bound(&self) -> f64 {
+        (0..self.filters.len()).map(|i| self.sub_fp_rate(i)).sum()
+    }
+
+    ///#### 根据各子过滤器的填充率估算当前的误判率
+    ///- #: 1 - ∏(1 - fp_i)
+    pub fn estimated_fp_rate(&self) -> f64 {
+        1.0 - self
+            .filters
+            .iter()
+            .map(|f| 1.0 - f.estimated_fp_rate())
+            .product::<f64>()
+    }
+
+    ///#### 添加元素，当前子过滤器已满时先追加新的子过滤器
+    ///- #: (子过滤器序号, 元素位置)；元素已命中时返回命中的子过滤器及位置，不做任何修改
+    ///- @item[in]: 要添加的元素
+    pub fn set(&mut self, item: &[u8]) -> (usize, Vec<Position>) {
+        if let Some(res) = self.find(item) {
+            return res;
+        }
+
+        if self.capacity <= self.filters.last().unwrap().len() {
+            self.capacity = self.capacity.saturating_mul(self.growth);
+            self.filters.push(self.sub_filter(self.filters.len()));
+        }
+
+        let idx = self.filters.len() - 1;
+        (idx, self.filters[idx].set(item))
+    }
+
+    ///- #: (首个命中的子过滤器序号, 元素位置)
+    ///- @item[in]: 要查找的元素
+    pub fn find(&self, item: &[u8]) -> Option<(usize, Vec<Position>)> {
+        self.filters
+            .iter()
+            .enumerate()
+            .find_map(|(i, f)| f.find(item).map(|ps| (i, ps)))
+    }
+
+    ///- @idx[in]: 子过滤器序号
+    ///- @p[in]: 元素位置
+    pub fn find_by_position(&self, idx: usize, p: &Position) -> bool {
+        self.filters[idx].find_by_position(p)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn scalable_bloom_filter() {
+        let fp_rate = 0.01;
+        let mut bf = ScalableBloomFilter::with_capacity(500, fp_rate);
+        assert_eq!(1, bf.filter_cnt());
+        assert!(bf.is_empty());
+
+        //500 + 1000 + 2000 + 4000 < 1_0000 <= 500 + ... + 8000,
+        //a few items are dropped as false positives of earlier sub-filters
+        let n = 1_0000usize;
+        for i in 0..n {
+            let (idx, ps) = bf.set(&i.to_le_bytes());
+            assert!(ps.iter().all(|p| bf.find_by_position(idx, p)));
+        }
+        assert_eq!(5, bf.filter_cnt());
+        assert!(n - n / 100 < bf.len() && bf.len() <= n);
+        for i in 0..n {
+            assert!(bf.find(&i.to_le_bytes()).is_some());
+        }
+
+        //duplicates are not written again
+        let len = bf.len();
+        assert_eq!(0, bf.set(&0usize.to_le_bytes()).0);
+        assert_eq!(len, bf.len());
+
+        let bound = bf.fp_rate_bound();
+        assert!(bound < fp_rate);
+        assert!(bf.estimated_fp_rate() < fp_rate);
+        let fp_cnt = (n..6 * n)
+            .filter(|i| bf.find(&i.to_le_bytes()).is_some())
+            .count();
+        assert!((fp_cnt as f64) < fp_rate * (5 * n) as f64);
+
+        bf.clear();
+        assert_eq!(1, bf.filter_cnt());
+        assert_eq!(500, bf.capacity);
+        assert!(bf.is_empty());
+    }
+
+    #[test]
+    fn tightening() {
+        let bf = ScalableBloomFilter::with_params(100, 0.01, 4, 0.5);
+        assert!((bf.sub_fp_rate(0) - 0.005).abs() < 1e-12);
+        assert!((bf.sub_fp_rate(3) - 0.000_625).abs() < 1e-12);
+
+        //the sum over an unbounded chain never reaches the global bound
+        let sum = (0..64).map(|i| bf.sub_fp_rate(i)).sum::<f64>();
+        assert!(sum < 0.01);
+
+        //a steep tightening reaches MAX_K after a few sub-filters instead of panicking
+        let mut bf = ScalableBloomFilter::with_params(1, 0.01, 2, 0.01);
+        for i in 0..600usize {
+            bf.set(&i.to_le_bytes());
+        }
+        assert_eq!(10, bf.filter_cnt());
+        assert!(bf.filters.iter().all(|f| f.k() <= MAX_K));
+        assert_eq!(MAX_K, bf.filters.last().unwrap().k());
+        assert!((0..600usize).all(|i| bf.find(&i.to_le_bytes()).is_some()));
+
+        //clear() restores the initial capacity without dividing it back out
+        bf.clear();
+        assert_eq!((1, 1), (bf.filter_cnt(), bf.capacity));
+    }
+}
@@ -0,0 +1,568 @@
+//! ## Golomb-coded set
+//!
+//! #### 算法说明
+//! - BIP158 compact block filter，较布隆过滤器更紧凑，且由全节点确定性地生成，轻节点无需暴露自己关心的地址；
+//! - 以密钥 k(区块哈希的前 16 字节)对每个元素计算 SipHash-2-4，再映射到 [0, N * M)：(hash * N * M) >> 64；
+//! - 映射值排序之后，依次对相邻两值之差做 Golomb-Rice 编码：商以一元码写出(q 个 1 后接一个 0)，余数写出低 P 位；
+//! - 误判率约为 1/M，BIP158 basic filter 取 P = 19，M = 784931；P 限制在 [1, 32]，N * M 不得超出 u64；
+//! - 匹配时对查询元素做同样的映射，与解码出的有序序列逐一比较，多个元素可排序后一次扫描完成；
+//! - basic filter 的元素为区块中每个输出的 scriptPubKey(空脚本与 OP_RETURN 除外)及每个输入所花费的 scriptPubKey，去重后构建；
+//! - filter header 将所有 filter 串联成链：dSHA256(dSHA256(filter) || prev_header)。
+//!
+//! #### 应用场景
+//! - 轻节点从全节点下载每个区块的 filter，在本地匹配自己的脚本，仅下载可能相关的区块。
+//!
+//! #### 实现属性
+//! - <font color=Green>√</font> 多线程安全
+//! - <font color=Green>√</font> 无 unsafe 代码
+//!
+
+use super::bloomfilter::bip37::{read_compact_size, write_compact_size};
+use super::bloomfilter::error::XErr;
+use ring::digest::{digest, SHA256};
+use std::collections::BTreeSet;
+
+///BIP158 basic filter 的参数
+pub const BASIC_P: u8 = 19;
+pub const BASIC_M: u64 = 784_931;
+
+///Golomb-Rice 参数 P 的上限，余数位数再多已无压缩意义
+pub const MAX_P: u8 = 32;
+
+const OP_RETURN: u8 = 0x6a;
+
+//- @k0, k1: SipHash 密钥
+//- @p: Golomb-Rice 余数的 bit 数
+//- @m: 误判率的倒数
+//- @n: 元素数量
+//- @data: Golomb-Rice 编码之后的比特流，不含开头的 N
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GcsFilter {
+    k0: u64,
+    k1: u64,
+    p: u8,
+    m: u64,
+    n: u64,
+    data: Vec<u8>,
+}
+
+impl GcsFilter {
+    ///#### 由元素集合构建
+    ///- #: P 不在 [1, MAX_P] 内、M 为 0 或 N * M 溢出时返回 XErr::InvalidParams
+    ///- @key[in]: SipHash 密钥
+    ///- @p[in]: Golomb-Rice 参数
+    ///- @m[in]: 误判率的倒数
+    ///- @items[in]: 元素集合，重复的元素只计一次
+    pub fn new(key: &[u8; 16], p: u8, m: u64, items: &[&[u8]]) -> Result<GcsFilter, XErr> {
+        let items = items.iter().collect::<BTreeSet<_>>();
+        let n = items.len() as u64;
+        if !is_valid(p, m, n) {
+            return Err(XErr::InvalidParams);
+        }
+        let (k0, k1) = split_key(key);
+        let mut res = GcsFilter {
+            k0,
+            k1,
+            p,
+            m,
+            n,
+            data: vec![],
+        };
+
+        let mut hashed = items
+            .iter()
+            .map(|i| res.hash_to_range(i))
+            .collect::<Vec<u64>>();
+        hashed.sort_unstable();
+
+        let mut writer = BitWriter::default();
+        let mut last = 0;
+        for h in hashed {
+            writer.write_golomb(h - last, p);
+            last = h;
+        }
+        res.data = writer.data;
+        Ok(res)
+    }
+
+    ///#### 构建 BIP158 basic filter
+    ///- @block_hash[in]: 区块哈希，内部字节序(即显示形式的逆序)
+    ///- @scripts[in]: 区块中全部输出脚本及全部输入所花费的输出脚本
+    pub fn basic(block_hash: &[u8; 32], scripts: &[&[u8]]) -> GcsFilter {
+        let scripts = scripts
+            .iter()
+            .filter(|s| s.first().is_some_and(|op| OP_RETURN != *op))
+            .cloned()
+            .collect::<Vec<&[u8]>>();
+        GcsFilter::new(&basic_key(block_hash), BASIC_P, BASIC_M, &scripts)
+            .expect("BIP158 parameters are valid")
+    }
+
+    ///#### 解析 BIP158 basic filter
+    ///- @block_hash[in]: 区块哈希，内部字节序
+    ///- @bytes[in]: CompactSize(N) || 比特流
+    pub fn basic_from_bytes(block_hash: &[u8; 32], bytes: &[u8]) -> Option<GcsFilter> {
+        GcsFilter::from_bytes(&basic_key(block_hash), BASIC_P, BASIC_M, bytes)
+    }
+
+    ///#### 反序列化
+    ///- #: 数据为空、N 的编码不完整或参数不合法时返回 None
+    pub fn from_bytes(key: &[u8; 16], p: u8, m: u64, bytes: &[u8]) -> Option<GcsFilter> {
+        let (n, siz) = read_compact_size(bytes)?;
+        if !is_valid(p, m, n) {
+            return None;
+        }
+        let (k0, k1) = split_key(key);
+        Some(GcsFilter {
+            k0,
+            k1,
+            p,
+            m,
+            n,
+            data: bytes[siz..].to_vec(),
+        })
+    }
+
+    ///#### 序列化：CompactSize(N) || 比特流
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut res = vec![];
+        write_compact_size(&mut res, self.n);
+        res.extend_from_slice(&self.data);
+        res
+    }
+
+    ///- #: 元素数量
+    #[inline(always)]
+    pub fn len(&self) -> u64 {
+        self.n
+    }
+
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        0 == self.n
+    }
+
+    //- #: (siphash(item) * N * M) >> 64
+    fn hash_to_range(&self, item: &[u8]) -> u64 {
+        let f = u128::from(self.n) * u128::from(self.m);
+        ((u128::from(siphash24(self.k0, self.k1, item)) * f) >> 64) as u64
+    }
+
+    ///- @item[in]: 要查找的元素
+    pub fn match_one(&self, item: &[u8]) -> bool {
+        self.match_any(&[item])
+    }
+
+    ///#### 判断多个元素中是否至少有一个可能存在，只需扫描一遍比特流
+    ///- #: false 表示一定均不存在；数据损坏时同样返回 false
+    ///- @items[in]: 要查找的元素
+    pub fn match_any(&self, items: &[&[u8]]) -> bool {
+        if self.is_empty() || items.is_empty() {
+            return false;
+        }
+
+        let mut targets = items
+            .iter()
+            .map(|i| self.hash_to_range(i))
+            .collect::<Vec<u64>>();
+        targets.sort_unstable();
+
+        let mut reader = BitReader::new(&self.data);
+        let mut value = 0u64;
+        let mut targets = targets.into_iter().peekable();
+        for _ in 0..self.n {
+            match reader
+                .read_golomb(self.p)
+                .and_then(|d| value.checked_add(d))
+            {
+                Some(v) => value = v,
+                None => return false,
+            }
+            while let Some(t) = targets.peek() {
+                if *t == value {
+                    return true;
+                } else if *t < value {
+                    targets.next();
+                } else {
+                    break;
+                }
+            }
+            if targets.peek().is_none() {
+                return false;
+            }
+        }
+        false
+    }
+}
+
+//- #: P 在 [1, MAX_P] 内，M 非 0，且 N * M 不超出 u64
+fn is_valid(p: u8, m: u64, n: u64) -> bool {
+    (1..=MAX_P).contains(&p) && 0 < m && n.checked_mul(m).is_some()
+}
+
+///#### 计算 filter header
+///- #: dSHA256(dSHA256(filter) || prev_header)，内部字节序
+///- @filter[in]: 序列化之后的 filter
+///- @prev_header[in]: 前一个区块的 filter header，创世区块为全零
+pub fn filter_header(filter: &[u8], prev_header: &[u8; 32]) -> [u8; 32] {
+    let mut buf = double_sha256(filter).to_vec();
+    buf.extend_from_slice(prev_header);
+    double_sha256(&buf)
+}
+
+fn double_sha256(data: &[u8]) -> [u8; 32] {
+    let mut res = [0; 32];
+    res.copy_from_slice(digest(&SHA256, digest(&SHA256, data).as_ref()).as_ref());
+    res
+}
+
+//BIP158：密钥为区块哈希的前 16 字节
+fn basic_key(block_hash: &[u8; 32]) -> [u8; 16] {
+    let mut key = [0; 16];
+    key.copy_from_slice(&block_hash[..16]);
+    key
+}
+
+fn split_key(key: &[u8; 16]) -> (u64, u64) {
+    let mut k0 = [0; 8];
+    let mut k1 = [0; 8];
+    k0.copy_from_slice(&key[..8]);
+    k1.copy_from_slice(&key[8..]);
+    (u64::from_le_bytes(k0), u64::from_le_bytes(k1))
+}
+
+macro_rules! sip_round {
+    ($v0: ident, $v1: ident, $v2: ident, $v3: ident) => {
+        $v0 = $v0.wrapping_add($v1);
+        $v1 = $v1.rotate_left(13) ^ $v0;
+        $v0 = $v0.rotate_left(32);
+        $v2 = $v2.wrapping_add($v3);
+        $v3 = $v3.rotate_left(16) ^ $v2;
+        $v0 = $v0.wrapping_add($v3);
+        $v3 = $v3.rotate_left(21) ^ $v0;
+        $v2 = $v2.wrapping_add($v1);
+        $v1 = $v1.rotate_left(17) ^ $v2;
+        $v2 = $v2.rotate_left(32);
+    };
+}
+
+///#### SipHash-2-4
+///- @k0, k1[in]: 128 bit 密钥的低、高 64 位
+///- @data[in]: 消息
+pub fn siphash24(k0: u64, k1: u64, data: &[u8]) -> u64 {
+    let mut v0 = k0 ^ 0x736f_6d65_7073_6575;
+    let mut v1 = k1 ^ 0x646f_7261_6e64_6f6d;
+    let mut v2 = k0 ^ 0x6c79_6765_6e65_7261;
+    let mut v3 = k1 ^ 0x7465_6462_7974_6573;
+
+    let chunks = data.chunks_exact(8);
+    let tail = chunks.remainder();
+    for c in chunks {
+        let mut buf = [0; 8];
+        buf.copy_from_slice(c);
+        let m = u64::from_le_bytes(buf);
+        v3 ^= m;
+        sip_round!(v0, v1, v2, v3);
+        sip_round!(v0, v1, v2, v3);
+        v0 ^= m;
+    }
+
+    //最后一个分组：剩余字节 + 最高字节存放消息长度
+    let mut buf = [0; 8];
+    buf[..tail.len()].copy_from_slice(tail);
+    let m = u64::from_le_bytes(buf) | ((data.len() as u64) << 56);
+    v3 ^= m;
+    sip_round!(v0, v1, v2, v3);
+    sip_round!(v0, v1, v2, v3);
+    v0 ^= m;
+
+    v2 ^= 0xff;
+    for _ in 0..4 {
+        sip_round!(v0, v1, v2, v3);
+    }
+    v0 ^ v1 ^ v2 ^ v3
+}
+
+//按字节内从高位到低位的顺序写入比特流
+#[derive(Default)]
+struct BitWriter {
+    data: Vec<u8>,
+    bit_cnt: usize,
+}
+
+impl BitWriter {
+    fn write_bit(&mut self, bit: bool) {
+        if self.bit_cnt.is_multiple_of(8) {
+            self.data.push(0);
+        }
+        if bit {
+            *self.data.last_mut().unwrap() |= 0x80 >> (self.bit_cnt % 8);
+        }
+        self.bit_cnt += 1;
+    }
+
+    //写入 v 的低 n 位，高位在前
+    fn write_bits(&mut self, v: u64, n: u8) {
+        (0..n).rev().for_each(|i| self.write_bit(0 < (v >> i) & 1));
+    }
+
+    fn write_golomb(&mut self, x: u64, p: u8) {
+        (0..x >> p).for_each(|_| self.write_bit(true));
+        self.write_bit(false);
+        self.write_bits(x, p);
+    }
+}
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> BitReader<'a> {
+        BitReader { data, pos: 0 }
+    }
+
+    fn read_bit(&mut self) -> Option<bool> {
+        let byte = self.data.get(self.pos / 8)?;
+        let bit = 0 < byte & (0x80 >> (self.pos % 8));
+        self.pos += 1;
+        Some(bit)
+    }
+
+    fn read_bits(&mut self, n: u8) -> Option<u64> {
+        let mut res = 0;
+        for _ in 0..n {
+            res = (res << 1) | u64::from(self.read_bit()?);
+        }
+        Some(res)
+    }
+
+    //商左移 p 位之后超出 u64 时返回 None
+    fn read_golomb(&mut self, p: u8) -> Option<u64> {
+        let mut q = 0u64;
+        while self.read_bit()? {
+            q += 1;
+        }
+        if u64::MAX >> p < q {
+            return None;
+        }
+        Some((q << p) | self.read_bits(p)?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testutil::{hash, hex};
+
+    //test vectors from the SipHash reference implementation: key 00..0f, message 00..(n-1)
+    #[test]
+    fn siphash() {
+        let k0 = 0x0706_0504_0302_0100;
+        let k1 = 0x0f0e_0d0c_0b0a_0908;
+        let msg = (0..64u8).collect::<Vec<u8>>();
+        assert_eq!(0x726f_db47_dd0e_0e31, siphash24(k0, k1, &[]));
+        assert_eq!(0x74f8_39c5_93dc_67fd, siphash24(k0, k1, &msg[..1]));
+        assert_eq!(0x93f5_f579_9a93_2462, siphash24(k0, k1, &msg[..8]));
+        assert_eq!(0xa129_ca61_49be_45e5, siphash24(k0, k1, &msg[..15]));
+    }
+
+    #[test]
+    fn golomb_rice() {
+        let xs = [0u64, 1, 2, 100, 784_931, 1 << 19, (1 << 20) + 3];
+        let mut writer = BitWriter::default();
+        xs.iter().for_each(|x| writer.write_golomb(*x, BASIC_P));
+
+        let mut reader = BitReader::new(&writer.data);
+        for x in xs.iter() {
+            assert_eq!(Some(*x), reader.read_golomb(BASIC_P));
+        }
+        assert_eq!(writer.bit_cnt, reader.pos);
+
+        //x = 5, p = 2: quotient 1 -> "10", remainder "01"
+        let mut writer = BitWriter::default();
+        writer.write_golomb(5, 2);
+        assert_eq!(vec![0b1001_0000], writer.data);
+
+        //a quotient that does not fit once shifted is rejected
+        assert_eq!(
+            None,
+            BitReader::new(&[0b1100_0000, 0, 0, 0, 0, 0, 0, 0, 0]).read_golomb(63)
+        );
+        assert_eq!(
+            Some(1 << 63),
+            BitReader::new(&[0b1000_0000, 0, 0, 0, 0, 0, 0, 0, 0]).read_golomb(63)
+        );
+    }
+
+    //test vectors from BIP158: testnet genesis block
+    #[test]
+    fn bip158() {
+        let block_hash = hash("000000000933ea01ad0ee984209779baaec3ced90fa3f408719526f8d77f4943");
+        let script = hex("4104678afdb0fe5548271967f1a67130b7105cd6a828e03909a67962e0ea1f61deb649f6bc3f4cef38c4f35504e51ec112de5c384df7ba0b8d578a4c702b6bf11d5fac");
+
+        let filter = GcsFilter::basic(&block_hash, &[&script, &[], &[OP_RETURN, 1, 0]]);
+        assert_eq!(1, filter.len());
+        assert_eq!(hex("019dfca8"), filter.to_bytes());
+        assert_eq!(
+            hash("21584579b7eb08997773e5aeff3a7f932700042d0ed2a6129012b7d7ae81b750"),
+            filter_header(&filter.to_bytes(), &[0; 32])
+        );
+
+        let de = GcsFilter::basic_from_bytes(&block_hash, &hex("019dfca8")).unwrap();
+        assert_eq!(filter, de);
+        assert!(de.match_one(&script));
+        assert!(!de.match_one(&script[1..]));
+        assert!(de.match_any(&[&[0], &script]));
+
+        //empty filter
+        let empty = GcsFilter::basic(&block_hash, &[]);
+        assert_eq!(vec![0], empty.to_bytes());
+        assert!(!empty.match_one(&script));
+
+        //blocks 2 and 3 chain onto the previous filter headers
+        let block_hash = hash("000000006c02c8ea6e4ff69651f7fcde348fb9d557a06e6957b65552002a7820");
+        let script = hex("21038a7f6ef1c8ca0c588aa53fa860128077c9e6c11e6830f4d7ee4e763a56b7718fac");
+        let filter = GcsFilter::basic(&block_hash, &[&script]).to_bytes();
+        assert_eq!(hex("0174a170"), filter);
+        let prev = hash("d7bdac13a59d745b1add0d2ce852f1a0442e8945fc1bf3848d3cbffd88c24fe1");
+        let header = filter_header(&filter, &prev);
+        assert_eq!(
+            hash("186afd11ef2b5e7e3504f2e8cbf8df28a1fd251fe53d60dff8b1467d1b386cf0"),
+            header
+        );
+        let block_hash = hash("000000008b896e272758da5297bcd98fdc6d97c9b765ecec401e286dc1fdbe10");
+        let script = hex("2103f6d9ff4c12959445ca5549c811683bf9c88e637b222dd2e0311154c4c85cf423ac");
+        let filter = GcsFilter::basic(&block_hash, &[&script]).to_bytes();
+        assert_eq!(hex("016cf7a0"), filter);
+        assert_eq!(
+            hash("8d63aadf5ab7257cb6d2316a57b16f517bff1c6388f124ec4c04af1212729d2a"),
+            filter_header(&filter, &header)
+        );
+    }
+
+    //multi-element vectors from BIP158: testnet blocks 49291 and 180480
+    #[test]
+    fn bip158_multi() {
+        //outputs and spent scripts of block 49291, including an empty spent script
+        let block_hash = hash("0000000018b07dca1b28b4b5a119f6d6e71698ce1ed96f143f54179ce177a19c");
+        let bytes = hex("0afbc2920af1b027f31f87b592276eb4c32094bb4d3697021b4c6380");
+        let scripts = [
+            "2102971dd6034ed0cf52450b608d196c07d6345184fcb14deb277a6b82d526a6163dac",
+            "76a91445db0b779c0b9fa207f12a8218c94fc77aff504588ac",
+            "",
+            "5221033423007d8f263819a2e42becaaf5b06f34cb09919e06304349d950668209eaed21021d69e2b68c3960903b702af7829fadcd80bd89b158150c85c4a75b2c8cb9c39452ae",
+            "52210279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f8179821021d69e2b68c3960903b702af7829fadcd80bd89b158150c85c4a75b2c8cb9c39452ae",
+            "522102a7ae1e0971fc1689bd66d2a7296da3a1662fd21a53c9e38979e0f090a375c12d21022adb62335f41eb4e27056ac37d462cda5ad783fa8e0e526ed79c752475db285d52ae",
+            "52210279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f8179821022adb62335f41eb4e27056ac37d462cda5ad783fa8e0e526ed79c752475db285d52ae",
+            "512103b9d1d0e2b4355ec3cdef7c11a5c0beff9e8b8d8372ab4b4e0aaf30e80173001951ae",
+            "76a9149144761ebaccd5b4bbdc2a35453585b5637b2f8588ac",
+            "522103f1848b40621c5d48471d9784c8174ca060555891ace6d2b03c58eece946b1a9121020ee5d32b54d429c152fdc7b1db84f2074b0564d35400d89d11870f9273ec140c52ae",
+            "76a914f4fa1cc7de742d135ea82c17adf0bb9cf5f4fb8388ac",
+        ]
+        .iter()
+        .map(|s| hex(s))
+        .collect::<Vec<_>>();
+        let refs = scripts.iter().map(|s| &s[..]).collect::<Vec<&[u8]>>();
+        let filter = GcsFilter::basic(&block_hash, &refs);
+        assert_eq!(10, filter.len());
+        assert_eq!(bytes, filter.to_bytes());
+        assert_eq!(
+            hash("b6d98692cec5145f67585f3434ec3c2b3030182e1cb3ec58b855c5c164dfaaa3"),
+            filter_header(
+                &filter.to_bytes(),
+                &hash("ed47705334f4643892ca46396eb3f4196a5e30880589e4009ef38eae895d4a13")
+            )
+        );
+        let filter = GcsFilter::basic_from_bytes(&block_hash, &bytes).unwrap();
+        assert!(refs
+            .iter()
+            .filter(|s| !s.is_empty())
+            .all(|s| filter.match_one(s)));
+        //the empty script is not part of the filter
+        assert!(!filter.match_one(&[]));
+        assert!(!filter.match_one(&scripts[1][1..]));
+
+        //spent scripts of block 180480, its empty ones are skipped, the 8 output scripts of the
+        //block are not listed so the filter is only decoded
+        let block_hash = hash("00000000fd3ceb2404ff07a785c7fdcc76619edc8ed61bd25134eaa22084366a");
+        let bytes = hex("0db414c859a07e8205876354a210a75042d0463404913d61a8e068e58a3ae2aa080026");
+        let scripts = [
+            "76a9142903b138c24be9e070b3e73ec495d77a204615e788ac",
+            "76a91433a1941fd9a37b9821d376f5a51bd4b52fa50e2888ac",
+            "76a914e4374e8155d0865742ca12b8d4d14d41b57d682f88ac",
+            "76a914001fa7459a6cfc64bdc178ba7e7a21603bb2568f88ac",
+            "76a914f6039952bc2b307aeec5371bfb96b66078ec17f688ac",
+        ]
+        .iter()
+        .map(|s| hex(s))
+        .collect::<Vec<_>>();
+        let filter = GcsFilter::basic_from_bytes(&block_hash, &bytes).unwrap();
+        assert_eq!(13, filter.len());
+        let refs = scripts.iter().map(|s| &s[..]).collect::<Vec<&[u8]>>();
+        assert!(refs.iter().all(|s| filter.match_one(s)));
+        assert!(filter.match_any(&[&[0x6a], refs[4]]));
+        assert_eq!(
+            hash("c582d51c0ca365e3fcf36c51cb646d7f83a67e867cb4743fd2128e3e022b700c"),
+            filter_header(
+                &bytes,
+                &hash("d34ef98386f413769502808d4bac5f20f8dfd5bffc9eedafaa71de0eb1f01489")
+            )
+        );
+    }
+
+    #[test]
+    fn invalid_params() {
+        let key = [7; 16];
+        let items: [&[u8]; 2] = [b"a", b"b"];
+        assert!(GcsFilter::new(&key, 0, BASIC_M, &items).is_err());
+        assert!(GcsFilter::new(&key, MAX_P + 1, BASIC_M, &items).is_err());
+        assert!(GcsFilter::new(&key, 64, BASIC_M, &items).is_err());
+        assert!(GcsFilter::new(&key, BASIC_P, 0, &items).is_err());
+        assert!(GcsFilter::new(&key, BASIC_P, u64::MAX, &items).is_err());
+        assert!(GcsFilter::new(&key, MAX_P, BASIC_M, &items).is_ok());
+
+        let bytes = GcsFilter::new(&key, BASIC_P, BASIC_M, &items)
+            .unwrap()
+            .to_bytes();
+        assert!(GcsFilter::from_bytes(&key, 64, BASIC_M, &bytes).is_none());
+        assert!(GcsFilter::from_bytes(&key, BASIC_P, 0, &bytes).is_none());
+        //N * M overflows
+        assert!(
+            GcsFilter::from_bytes(&key, BASIC_P, BASIC_M, &hex("ffffffffffffffffff00")).is_none()
+        );
+
+        //a run of ones longer than the data ends the scan instead of panicking
+        let filter =
+            GcsFilter::from_bytes(&key, MAX_P, BASIC_M, &hex("02ffffffffffffffffffff")).unwrap();
+        assert!(!filter.match_one(b"a"));
+    }
+
+    #[test]
+    fn match_many() {
+        let key = [7; 16];
+        let items = (0..1000u32).map(|i| i.to_le_bytes()).collect::<Vec<_>>();
+        let refs = items.iter().map(|i| &i[..]).collect::<Vec<&[u8]>>();
+
+        let mut dup = refs.clone();
+        dup.extend_from_slice(&refs[..10]);
+        let filter = GcsFilter::new(&key, BASIC_P, BASIC_M, &dup).unwrap();
+        assert_eq!(1000, filter.len());
+        //about P + 1 + 1 bits per item
+        assert!(filter.to_bytes().len() < 1000 * 22 / 8);
+
+        let filter = GcsFilter::from_bytes(&key, BASIC_P, BASIC_M, &filter.to_bytes()).unwrap();
+        assert!(refs.iter().all(|i| filter.match_one(i)));
+
+        let others = (1000..21000u32)
+            .map(|i| i.to_le_bytes())
+            .collect::<Vec<_>>();
+        let others = others.iter().map(|i| &i[..]).collect::<Vec<&[u8]>>();
+        assert!(others.iter().filter(|i| filter.match_one(i)).count() < 5);
+
+        assert!(filter.match_any(&[others[0], others[1], refs[999]]));
+        assert!(!filter.match_any(&others[..100]));
+        assert!(!filter.match_any(&[]));
+    }
+}
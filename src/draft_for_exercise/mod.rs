@@ -1,5 +1,6 @@
 #![allow(dead_code)]
 
 pub mod bloomfilter;
+pub mod gcs;
 pub mod linkedlist;
 pub mod tree;
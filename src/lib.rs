@@ -2,7 +2,10 @@ pub mod consensus;
 pub mod data_structure;
 pub mod draft_for_exercise;
 pub mod p2p_routing;
+#[cfg(test)]
+mod testutil;
 
+pub use data_structure::graph::dag;
 pub use data_structure::tree::mpt;
 pub use data_structure::tree::msl;
 pub use data_structure::tree::skiplist;
@@ -1,10 +0,0 @@
-/// ## coral P2P routing for CDN
-/// - coral P2P-CDN 路由算法
-
-#[cfg(test)]
-mod tests {
-    #[test]
-    fn test() {
-        assert_eq!(1 + 9, 10);
-    }
-}
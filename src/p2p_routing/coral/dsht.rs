@@ -0,0 +1,388 @@
+//! ## coral DSHT(distributed sloppy hash table)
+//!
+//! #### 算法说明
+//! - 分级 cluster：按 RTT 阈值将节点分为 L0(全局)、L1(区域)、L2(城市) 三级 cluster，下级 cluster 嵌套在上级之中，
+//!   同一 cluster 内任意两个节点的 RTT 都低于该级的阈值；每一级 cluster 都是一个独立的 DSHT，
+//!   节点在其所属的每一级 cluster 中各有一张路由表与一份 sloppy storage；
+//! - 路由：每一跳选择与 key 的距离恰好再多一位公共前缀的节点(距离约减半)，而不是直接跳到已知最近的节点，
+//!   于是来自不同起点的请求在接近 key 之前经过不同的节点，为 put 提前停下留出空间；
+//! - put 与 get 由各节点自身的状态(node)与逐跳推进的查找状态机(lookup)完成，与 kademlia 的 Node/Lookup 相同，
+//!   既可由 sim 模拟器在丢包与流失下驱动，也可由本模块按 RTT 计时的同步网络驱动；
+//! - cluster 的划分：论文中节点以 ping 测得的 RTT 自行加入或合并 cluster，这一过程与 DSHT 的读写无关，
+//!   此处简化为由全部节点间的 RTT 集中计算，并用同一 cluster 的成员填充各级路由表(bucket 满时丢弃多余节点)，
+//!   相当于全部节点都已完成加入；此后的 put/get 只经由 RPC 访问其他节点的状态。
+//!
+//! #### 应用场景
+//! - Coral CDN：key 为 URL 的哈希，value 为缓存了该 URL 的代理地址。
+//!
+//! #### 实现属性
+//! - <font color=Green>√</font> 多线程安全
+//! - <font color=Green>√</font> 无 unsafe 代码
+//!
+
+use super::super::peer::NodeId;
+pub use super::lookup::LEVELS;
+use super::lookup::{Lookup, LookupResult};
+use super::node::CoralNode;
+use super::store::SloppyStore;
+use std::collections::BTreeMap;
+
+///各级 cluster 内节点间 RTT 的上限(毫秒)，L0 为全局 cluster
+pub const THRESHOLDS: [u64; LEVELS] = [u64::MAX, 80, 20];
+
+//- @nodes: 全部节点
+//- @index: 节点 Id 到下标的映射
+//- @rtt: 节点间的 RTT
+//- @clusters: clusters[lv][i] 为第 i 个节点在第 lv 级所属的 cluster 编号
+#[derive(Clone, Debug)]
+pub struct Coral {
+    nodes: Vec<CoralNode>,
+    index: BTreeMap<NodeId, usize>,
+    rtt: Vec<Vec<u64>>,
+    clusters: Vec<Vec<usize>>,
+}
+
+impl Coral {
+    ///#### 按 RTT 划分 cluster，并为每一级 cluster 建立路由表
+    ///- @ids[in]: 全部节点
+    ///- @rtt[in]: 第 i、j 个节点之间的 RTT
+    ///- @k[in]: 每个 bucket 最多保存的节点数
+    pub fn new<F: Fn(usize, usize) -> u64>(ids: &[NodeId], rtt: F, k: usize) -> Coral {
+        let n = ids.len();
+        let rtt = (0..n)
+            .map(|i| (0..n).map(|j| rtt(i, j)).collect())
+            .collect::<Vec<Vec<u64>>>();
+
+        //每个节点依次加入父 cluster 内第一个 RTT 全部低于阈值的子 cluster
+        let mut clusters = vec![vec![0; n]];
+        for threshold in THRESHOLDS.iter().skip(1) {
+            let parent = &clusters[clusters.len() - 1];
+            let mut members: Vec<(usize, Vec<usize>)> = vec![];
+            let mut cur = vec![0; n];
+            for i in 0..n {
+                match members
+                    .iter_mut()
+                    .position(|(p, m)| *p == parent[i] && m.iter().all(|j| rtt[i][*j] < *threshold))
+                {
+                    Some(c) => {
+                        members[c].1.push(i);
+                        cur[i] = c;
+                    }
+                    None => {
+                        cur[i] = members.len();
+                        members.push((parent[i], vec![i]));
+                    }
+                }
+            }
+            clusters.push(cur);
+        }
+
+        let mut nodes = ids
+            .iter()
+            .map(|id| CoralNode::new(*id, k))
+            .collect::<Vec<CoralNode>>();
+        for (lv, cluster) in clusters.iter().enumerate() {
+            for i in 0..n {
+                if let Some(table) = nodes[i].table_mut(lv) {
+                    for j in (0..n).filter(|j| *j != i && cluster[*j] == cluster[i]) {
+                        table.update(&ids[j], 0);
+                    }
+                }
+            }
+        }
+
+        Coral {
+            nodes,
+            index: ids.iter().enumerate().map(|(i, id)| (*id, i)).collect(),
+            rtt,
+            clusters,
+        }
+    }
+
+    #[inline(always)]
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    pub fn node(&self, id: &NodeId) -> Option<&CoralNode> {
+        self.nodes.get(*self.index.get(id)?)
+    }
+
+    ///#### 取出全部节点，例如交给 sim 模拟器
+    pub fn into_nodes(self) -> Vec<CoralNode> {
+        self.nodes
+    }
+
+    ///- #: 第 lv 级 cluster 的个数
+    pub fn cluster_cnt(&self, lv: usize) -> usize {
+        self.clusters
+            .get(lv)
+            .and_then(|c| c.iter().max())
+            .map_or(0, |c| c + 1)
+    }
+
+    ///- #: 节点在第 lv 级 cluster 中的全部成员，含节点自身；节点不存在时返回 None
+    pub fn cluster(&self, lv: usize, id: &NodeId) -> Option<Vec<NodeId>> {
+        let clusters = self.clusters.get(lv)?;
+        let c = clusters[*self.index.get(id)?];
+        Some(
+            (0..self.nodes.len())
+                .filter(|i| c == clusters[*i])
+                .map(|i| *self.nodes[i].id())
+                .collect(),
+        )
+    }
+
+    ///- #: 节点在第 lv 级 cluster 中的 sloppy storage，节点不存在时返回 None
+    pub fn store(&self, lv: usize, id: &NodeId) -> Option<&SloppyStore> {
+        self.node(id)?.store(lv)
+    }
+
+    //由第 o 个节点同步驱动查找，每个请求都在对端立即处理，耗时按 RTT 计
+    //- #: (查找结果, 全部请求的 RTT 之和)
+    fn run(&mut self, o: usize, mut lookup: Lookup, now: u64) -> (LookupResult, u64) {
+        let origin = *self.nodes[o].id();
+        let mut latency = 0;
+        loop {
+            let reqs = lookup.poll();
+            if reqs.is_empty() {
+                break;
+            }
+            for (to, req) in reqs {
+                let resp = match self.index.get(&to) {
+                    Some(i) => {
+                        latency += self.rtt[o][*i];
+                        Some(self.nodes[*i].handle(&origin, req, now))
+                    }
+                    None => None,
+                };
+                lookup.on_response(&to, resp);
+            }
+        }
+        (lookup.result(), latency)
+    }
+
+    ///#### 由 origin 在其所属的每一级 cluster 中存入 value
+    ///- #: origin 不存在时返回 None
+    ///- @origin[in]: 发起 put 的节点
+    ///- @key[in]: key
+    ///- @value[in]: value
+    ///- @ttl[in]: 存活时间
+    ///- @now[in]: 当前时间
+    pub fn put(
+        &mut self,
+        origin: &NodeId,
+        key: &NodeId,
+        value: &[u8],
+        ttl: u64,
+        now: u64,
+    ) -> Option<LookupResult> {
+        let o = *self.index.get(origin)?;
+        let lookup = self.nodes[o].put(*key, value, ttl);
+        Some(self.run(o, lookup, now).0)
+    }
+
+    ///#### 由 origin 查找 key，从最快的 cluster 开始
+    ///- #: (查找结果, 全部请求的 RTT 之和)，origin 不存在时返回 None
+    ///- @origin[in]: 发起 get 的节点
+    ///- @key[in]: key
+    ///- @now[in]: 当前时间
+    pub fn get(&mut self, origin: &NodeId, key: &NodeId, now: u64) -> Option<(LookupResult, u64)> {
+        let o = *self.index.get(origin)?;
+        let lookup = self.nodes[o].get(*key, now);
+        Some(self.run(o, lookup, now))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::super::sim::{Config, Query, Simulator};
+    use super::super::super::testutil::{id, ids};
+    use super::super::store::L;
+    use super::*;
+
+    //3 个区域，每个区域 2 个城市，每个城市 20 个节点
+    //同城 RTT < 10，同区域不同城 RTT 约 50，跨区域 RTT >= 200
+    fn build() -> (Coral, Vec<NodeId>) {
+        let n = 3 * 2 * 20;
+        let pos = |i: usize| {
+            (
+                (i / 40) as i64 * 200,
+                ((i / 20) % 2) as i64 * 50 + (i % 5) as i64,
+            )
+        };
+        let ids = ids(n);
+        let coral = Coral::new(
+            &ids,
+            |i, j| {
+                let (a, b) = (pos(i), pos(j));
+                ((a.0 - b.0).abs() + (a.1 - b.1).abs()) as u64
+            },
+            4,
+        );
+        (coral, ids)
+    }
+
+    //在第 lv 级 cluster 中从 from 出发到达 key 的路径，不含 from
+    fn route(coral: &Coral, lv: usize, from: &NodeId, key: &NodeId) -> Vec<NodeId> {
+        let mut res = vec![];
+        let mut cur = *from;
+        while let Some(next) = coral.node(&cur).unwrap().next_hop(lv, key) {
+            res.push(next);
+            cur = next;
+        }
+        res
+    }
+
+    #[test]
+    fn cluster() {
+        let (coral, ids) = build();
+        assert_eq!(120, coral.len());
+        assert_eq!(1, coral.cluster_cnt(0));
+        assert_eq!(3, coral.cluster_cnt(1));
+        assert_eq!(6, coral.cluster_cnt(2));
+        assert_eq!(ids[20..40].to_vec(), coral.cluster(2, &ids[25]).unwrap());
+        assert_eq!(ids[..40].to_vec(), coral.cluster(1, &ids[25]).unwrap());
+        assert_eq!(ids, coral.cluster(0, &ids[25]).unwrap());
+
+        //routes stay inside the cluster and end at its closest member
+        let key = id("key");
+        for lv in 0..LEVELS {
+            let members = coral.cluster(lv, &ids[25]).unwrap();
+            let path = route(&coral, lv, &ids[25], &key);
+            assert!(path.iter().all(|x| members.contains(x)));
+            let closest = members.iter().min_by_key(|x| x.distance(&key)).unwrap();
+            match path.last() {
+                Some(x) => assert_eq!(closest, x),
+                None => assert_eq!(closest, &ids[25]),
+            }
+        }
+    }
+
+    #[test]
+    fn nearby_first() {
+        let (mut coral, ids) = build();
+        let key = id("http://example.com/");
+        let res = coral.put(&ids[0], &key, b"proxy0", 3_600_000, 0).unwrap();
+        assert_eq!(LEVELS, res.stored.len());
+
+        //the same city finds it in L2, a city nearby in L1, another region only in L0
+        let (near, near_latency) = coral.get(&ids[7], &key, 1).unwrap();
+        assert_eq!(Some(2), near.level);
+        assert_eq!(vec![b"proxy0".to_vec()], near.values);
+        let (region, _) = coral.get(&ids[27], &key, 1).unwrap();
+        assert_eq!(Some(1), region.level);
+        let (far, far_latency) = coral.get(&ids[100], &key, 1).unwrap();
+        assert_eq!(Some(0), far.level);
+        assert!(near_latency < 20 * near.rpc_cnt as u64);
+        assert!(near_latency < far_latency);
+
+        //once the far node caches the url its neighbours stay in their city
+        coral
+            .put(&ids[100], &key, b"proxy100", 3_600_000, 2)
+            .unwrap();
+        let (res, _) = coral.get(&ids[101], &key, 3).unwrap();
+        assert_eq!(Some(2), res.level);
+        assert_eq!(vec![b"proxy100".to_vec()], res.values);
+
+        //values expire
+        let (res, _) = coral.get(&ids[7], &key, 3_600_003).unwrap();
+        assert!(res.values.is_empty() && res.level.is_none());
+
+        //unknown nodes are rejected instead of panicking
+        let stranger = id("stranger");
+        assert!(coral.get(&stranger, &key, 1).is_none());
+        assert!(coral.put(&stranger, &key, b"x", 3_600_000, 1).is_none());
+        assert!(coral.cluster(0, &stranger).is_none());
+        assert!(coral.store(0, &stranger).is_none());
+        assert!(coral.cluster(LEVELS, &ids[0]).is_none());
+        assert_eq!(0, coral.cluster_cnt(LEVELS));
+    }
+
+    #[test]
+    fn hotspot() {
+        let (mut coral, ids) = build();
+        let key = id("http://hot.example.com/");
+        for (i, x) in ids.iter().enumerate() {
+            coral
+                .put(
+                    x,
+                    &key,
+                    format!("proxy{}", i).as_bytes(),
+                    3_600_000,
+                    i as u64,
+                )
+                .unwrap();
+        }
+
+        let holders = ids
+            .iter()
+            .filter(|x| 0 < coral.store(0, x).unwrap().len(&key, 200))
+            .collect::<Vec<_>>();
+        assert!(ids
+            .iter()
+            .all(|x| coral.store(0, x).unwrap().len(&key, 200) <= L));
+        //the load spills over to nodes farther from the key
+        assert!(ids.len() / L / 2 < holders.len(), "{}", holders.len());
+        let closest = ids.iter().min_by_key(|x| x.distance(&key)).unwrap();
+        assert!(holders.contains(&closest));
+
+        for x in ids.iter().step_by(7) {
+            let (res, _) = coral.get(x, &key, 200).unwrap();
+            assert!(!res.values.is_empty() && res.values.len() <= L);
+        }
+    }
+
+    #[test]
+    fn simulated() {
+        let (coral, ids) = build();
+        let mut sim = Simulator::new(Config::default());
+        coral.into_nodes().into_iter().for_each(|x| sim.add_node(x));
+
+        let key = id("http://example.com/");
+        let put = sim.node(&ids[0]).unwrap().put(key, b"proxy0", 3_600_000);
+        sim.start(ids[0], put);
+        sim.run();
+        let put = sim.take_finished().pop().unwrap();
+        assert!(put.query.is_success());
+        let stored = put.query.result().stored;
+        assert_eq!(
+            (0..LEVELS).collect::<Vec<_>>(),
+            stored.iter().map(|(lv, _)| *lv).collect::<Vec<_>>()
+        );
+
+        for x in ids.iter().step_by(3) {
+            let get = sim.node(x).unwrap().get(key, sim.now());
+            sim.start(*x, get);
+        }
+        sim.run();
+        let finished = sim.take_finished();
+        assert_eq!(40, finished.len());
+        for f in finished.iter() {
+            assert!(f.query.is_success());
+            assert_eq!(vec![b"proxy0".to_vec()], f.query.result().values);
+        }
+        assert_eq!(1.0, sim.metrics().success_rate());
+
+        //with the global holder offline the other regions time out, the origin's city still hits
+        let (_, holder) = stored[0];
+        sim.set_online(&holder, false);
+        for i in [3, 11, 83, 111] {
+            let get = sim.node(&ids[i]).unwrap().get(key, sim.now());
+            sim.start(ids[i], get);
+        }
+        sim.run();
+        assert_eq!(0, sim.running());
+        let finished = sim.take_finished();
+        assert!(0 < sim.metrics().timeouts);
+        for f in finished.iter() {
+            let near = ids[..20].contains(&f.origin);
+            assert_eq!(near, f.query.is_success(), "{:?}", f.origin);
+        }
+    }
+}
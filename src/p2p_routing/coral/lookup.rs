@@ -0,0 +1,300 @@
+//! ## coral put/get 状态机
+//!
+//! #### 算法说明
+//! - 每一跳由被询问的节点在自己的路由表中选出下一跳(距离 key 恰好多一位公共前缀的节点)并随响应返回，
+//!   发起方据此逐跳前进，同一时刻只有一个请求在途；
+//! - get：从最快的 L2 cluster 开始，先看本节点已有的 value，再沿路径逐跳询问，遇到保存有 value 的节点即结束；
+//!   本级未找到时，从本级最后一个响应的节点出发在上一级 cluster 中继续；超时的节点视为本级路径的终点；
+//! - put 前向阶段：逐跳发送 PROBE，对端计入一次 put 并回答是否对 key 既 full 又 loaded，
+//!   遇到既 full 又 loaded 的节点或路径终点即停止，途经的其余节点按顺序压栈；
+//! - put 反向阶段：从栈顶开始发送 STORE，失败或超时则出栈重试，栈为空时存入发起节点自身
+//!   (同样以 STORE 请求表示，在模拟器中即发往自身的消息)；put 在 L0、L1、L2 中依次执行一次；
+//! - 查找本身是一个状态机(poll/on_response)，可由 sim 模拟器或 dsht 中按 RTT 计时的同步网络驱动。
+//!
+//! #### 实现属性
+//! - <font color=Green>√</font> 多线程安全
+//! - <font color=Green>√</font> 无 unsafe 代码
+//!
+
+use super::super::peer::NodeId;
+use super::super::sim::Query;
+
+///cluster 的级数
+pub const LEVELS: usize = 3;
+
+///coral RPC 请求，第一个字段为 cluster 的级别
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Request {
+    ///(级别, key, ttl)，put 前向阶段的询问，对端同时计入一次 put
+    Probe(usize, NodeId, u64),
+    ///(级别, key, value, ttl)
+    Store(usize, NodeId, Vec<u8>, u64),
+    ///(级别, key)
+    Get(usize, NodeId),
+}
+
+///coral RPC 响应，下一跳为 None 表示对端已是本级路径的终点
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Response {
+    ///(是否既 full 又 loaded, 下一跳)
+    Probe(bool, Option<NodeId>),
+    ///是否存入
+    Stored(bool),
+    ///(未过期的 value, 下一跳)
+    Values(Vec<Vec<u8>>, Option<NodeId>),
+}
+
+///查找的结果
+///- @values: get 取得的 value，未找到时为空
+///- @level: get 在哪一级 cluster 中找到
+///- @from: get 中返回 value 的节点
+///- @stored: put 存入 value 的 (级别, 节点)
+///- @rpc_cnt: 发出的请求数
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LookupResult {
+    pub values: Vec<Vec<u8>>,
+    pub level: Option<usize>,
+    pub from: Option<NodeId>,
+    pub stored: Vec<(usize, NodeId)>,
+    pub rpc_cnt: usize,
+}
+
+//查找的类型
+//- Get: 发起节点在各级 cluster 中已有的 value
+//- Put: (value, ttl, 前向阶段途经的节点, 是否处于反向阶段)
+#[derive(Clone, Debug)]
+enum Kind {
+    Get(Vec<Vec<Vec<u8>>>),
+    Put(Vec<u8>, u64, Vec<NodeId>, bool),
+}
+
+//- @origin: 发起查找的节点
+//- @key: key
+//- @kind: 查找的类型
+//- @first: 发起节点在各级 cluster 中的第一跳
+//- @lv: 当前所在的级别，查找结束后为 None
+//- @last: get 中本级最后一个成功响应的节点，初始为发起节点
+//- @next: 下一个待发出的请求
+//- @res: 查找的结果
+#[derive(Clone, Debug)]
+pub struct Lookup {
+    origin: NodeId,
+    key: NodeId,
+    kind: Kind,
+    first: Vec<Option<NodeId>>,
+    lv: Option<usize>,
+    last: NodeId,
+    next: Option<(NodeId, Request)>,
+    res: LookupResult,
+}
+
+impl Lookup {
+    fn new(origin: NodeId, key: NodeId, kind: Kind, first: Vec<Option<NodeId>>) -> Lookup {
+        assert_eq!(LEVELS, first.len(), "one first hop per level");
+        Lookup {
+            origin,
+            key,
+            kind,
+            first,
+            lv: None,
+            last: origin,
+            next: None,
+            res: LookupResult::default(),
+        }
+    }
+
+    ///- @origin[in]: 发起节点 Id
+    ///- @key[in]: key
+    ///- @first[in]: 发起节点在各级 cluster 中的第一跳
+    ///- @local[in]: 发起节点在各级 cluster 中已有的 value
+    pub fn get(
+        origin: NodeId,
+        key: NodeId,
+        first: Vec<Option<NodeId>>,
+        local: Vec<Vec<Vec<u8>>>,
+    ) -> Lookup {
+        assert_eq!(LEVELS, local.len(), "one local store per level");
+        let mut res = Lookup::new(origin, key, Kind::Get(local), first);
+        res.enter(LEVELS - 1);
+        res
+    }
+
+    ///- @origin[in]: 发起节点 Id
+    ///- @key[in]: key
+    ///- @value[in]: value
+    ///- @ttl[in]: 存活时间
+    ///- @first[in]: 发起节点在各级 cluster 中的第一跳
+    pub fn put(
+        origin: NodeId,
+        key: NodeId,
+        value: Vec<u8>,
+        ttl: u64,
+        first: Vec<Option<NodeId>>,
+    ) -> Lookup {
+        let mut res = Lookup::new(origin, key, Kind::Put(value, ttl, vec![], false), first);
+        res.enter(0);
+        res
+    }
+
+    #[inline(always)]
+    pub fn key(&self) -> &NodeId {
+        &self.key
+    }
+
+    #[inline(always)]
+    pub fn is_finished(&self) -> bool {
+        self.lv.is_none()
+    }
+
+    //进入第 lv 级 cluster
+    fn enter(&mut self, lv: usize) {
+        self.lv = Some(lv);
+        match &mut self.kind {
+            Kind::Get(local) => {
+                if !local[lv].is_empty() {
+                    self.res.values = std::mem::take(&mut local[lv]);
+                    self.res.level = Some(lv);
+                    self.res.from = Some(self.origin);
+                    self.lv = None;
+                    return;
+                }
+                //上一级路径的终点同样属于本级 cluster，先询问它
+                let to = Some(self.last)
+                    .filter(|x| *x != self.origin)
+                    .or(self.first[lv]);
+                match to {
+                    Some(to) => self.next = Some((to, Request::Get(lv, self.key))),
+                    None => self.descend(),
+                }
+            }
+            Kind::Put(_, ttl, stack, reverse) => {
+                stack.clear();
+                *reverse = false;
+                match self.first[lv] {
+                    Some(to) => self.next = Some((to, Request::Probe(lv, self.key, *ttl))),
+                    None => self.reverse(),
+                }
+            }
+        }
+    }
+
+    //get 在本级未找到，转到上一级 cluster
+    fn descend(&mut self) {
+        match self.lv {
+            Some(lv) if 0 < lv => self.enter(lv - 1),
+            _ => self.lv = None,
+        }
+    }
+
+    //put 在本级结束，转到下一级 cluster
+    fn ascend(&mut self) {
+        match self.lv {
+            Some(lv) if lv + 1 < LEVELS => self.enter(lv + 1),
+            _ => self.lv = None,
+        }
+    }
+
+    //put 反向阶段：向栈顶节点发送 STORE，栈为空时存入发起节点自身
+    fn reverse(&mut self) {
+        let lv = match self.lv {
+            Some(lv) => lv,
+            None => return,
+        };
+        if let Kind::Put(value, ttl, stack, reverse) = &mut self.kind {
+            *reverse = true;
+            let to = stack.pop().unwrap_or(self.origin);
+            self.next = Some((to, Request::Store(lv, self.key, value.clone(), *ttl)));
+        }
+    }
+
+    ///#### 取出下一个请求
+    ///- #: (对端 Id, 请求)，查找已结束或请求在途时为空
+    pub fn poll(&mut self) -> Vec<(NodeId, Request)> {
+        match self.next.take() {
+            Some(req) => {
+                self.res.rpc_cnt += 1;
+                vec![req]
+            }
+            None => vec![],
+        }
+    }
+
+    ///#### 处理一个请求的结果
+    ///- @from[in]: 对端 Id
+    ///- @resp[in]: 响应，None 表示超时
+    pub fn on_response(&mut self, from: &NodeId, resp: Option<Response>) {
+        let lv = match self.lv {
+            Some(lv) => lv,
+            None => return,
+        };
+        match (&mut self.kind, resp) {
+            (Kind::Get(_), Some(Response::Values(values, next))) => {
+                self.last = *from;
+                if !values.is_empty() {
+                    self.res.values = values;
+                    self.res.level = Some(lv);
+                    self.res.from = Some(*from);
+                    self.lv = None;
+                    return;
+                }
+                match next {
+                    Some(to) => self.next = Some((to, Request::Get(lv, self.key))),
+                    None => self.descend(),
+                }
+            }
+            (Kind::Get(_), _) => self.descend(),
+            (Kind::Put(_, ttl, stack, false), Some(Response::Probe(stop, next))) => {
+                match next.filter(|_| !stop) {
+                    Some(to) => {
+                        stack.push(*from);
+                        self.next = Some((to, Request::Probe(lv, self.key, *ttl)));
+                    }
+                    None => {
+                        if !stop {
+                            stack.push(*from);
+                        }
+                        self.reverse();
+                    }
+                }
+            }
+            (Kind::Put(_, _, _, false), _) => self.reverse(),
+            (Kind::Put(_, _, stack, true), resp) => {
+                if Some(Response::Stored(true)) == resp {
+                    self.res.stored.push((lv, *from));
+                    self.ascend();
+                } else if *from == self.origin && stack.is_empty() {
+                    self.ascend();
+                } else {
+                    self.reverse();
+                }
+            }
+        }
+    }
+
+    ///#### 汇总查找结果
+    pub fn result(&self) -> LookupResult {
+        self.res.clone()
+    }
+}
+
+impl Query<NodeId, Request, Response> for Lookup {
+    fn poll(&mut self, _now: u64) -> Vec<(NodeId, Request)> {
+        Lookup::poll(self)
+    }
+
+    fn on_response(&mut self, from: &NodeId, resp: Option<Response>) {
+        Lookup::on_response(self, from, resp)
+    }
+
+    fn is_finished(&self) -> bool {
+        Lookup::is_finished(self)
+    }
+
+    //get 须取得 value，put 须在每一级 cluster 中都存入
+    fn is_success(&self) -> bool {
+        match self.kind {
+            Kind::Get(_) => !self.res.values.is_empty(),
+            Kind::Put(..) => LEVELS == self.res.stored.len(),
+        }
+    }
+}
@@ -0,0 +1,20 @@
+//! ## coral P2P routing for CDN
+//!
+//! #### 算法说明
+//! - coral P2P-CDN 路由算法：在 kademlia 的基础上构建 DSHT(distributed sloppy hash table)；
+//! - 一个 key 对应多个 value，get 只需返回其中任意一部分，因此 value 不必存放在离 key 最近的节点上，
+//!   热点 key 的写入在 full 且 loaded 的节点之前停下，分散到沿途的节点；
+//! - 节点按 RTT 组成 L0/L1/L2 三级 cluster，查找从最快的 cluster 开始，附近有缓存时无需跨越广域网。
+//!
+//! #### 应用场景
+//! - Coral CDN：用户通过附近的代理获取网页，代理之间以 DSHT 互相发现缓存了同一 URL 的代理。
+//!
+//! #### 实现属性
+//! - <font color=Green>√</font> 多线程安全
+//! - <font color=Green>√</font> 无 unsafe 代码
+//!
+
+pub mod dsht;
+pub mod lookup;
+pub mod node;
+pub mod store;
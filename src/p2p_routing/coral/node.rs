@@ -0,0 +1,142 @@
+//! ## coral 节点
+//!
+//! #### 算法说明
+//! - 节点在其所属的每一级 cluster 中各有一张路由表与一份 sloppy storage，处理 PROBE、STORE、GET 三种 RPC；
+//! - 路由表只包含同一 cluster 的成员，因此收到请求时不会用请求方更新路由表，
+//!   cluster 的成员关系由加入 cluster 时的 RTT 测量决定；
+//! - 下一跳：比本节点离 key 更近的已知节点中，公共前缀恰好多出最少 bit 的一个，同样多时取最近的，
+//!   即距离约减半，而不是直接跳到已知最近的节点，于是来自不同起点的请求在接近 key 之前经过不同的节点。
+//!
+//! #### 实现属性
+//! - <font color=Green>√</font> 多线程安全
+//! - <font color=Green>√</font> 无 unsafe 代码
+//!
+
+use super::super::kademlia::routing::RoutingTable;
+use super::super::peer::NodeId;
+use super::super::sim::SimNode;
+use super::lookup::{Lookup, Request, Response, LEVELS};
+use super::store::SloppyStore;
+
+//- @id: 节点 Id
+//- @tables: 每一级 cluster 中的路由表
+//- @stores: 每一级 cluster 中的 sloppy storage
+#[derive(Clone, Debug)]
+pub struct CoralNode {
+    id: NodeId,
+    tables: Vec<RoutingTable>,
+    stores: Vec<SloppyStore>,
+}
+
+impl CoralNode {
+    ///- @id[in]: 本节点 Id
+    ///- @k[in]: 每个 bucket 最多保存的节点数
+    pub fn new(id: NodeId, k: usize) -> CoralNode {
+        CoralNode {
+            id,
+            tables: (0..LEVELS).map(|_| RoutingTable::new(id, k, 0)).collect(),
+            stores: (0..LEVELS).map(|_| SloppyStore::default()).collect(),
+        }
+    }
+
+    #[inline(always)]
+    pub fn id(&self) -> &NodeId {
+        &self.id
+    }
+
+    ///- #: 第 lv 级 cluster 中的路由表
+    pub fn table(&self, lv: usize) -> Option<&RoutingTable> {
+        self.tables.get(lv)
+    }
+
+    ///- #: 第 lv 级 cluster 中的路由表，用于加入 cluster
+    pub fn table_mut(&mut self, lv: usize) -> Option<&mut RoutingTable> {
+        self.tables.get_mut(lv)
+    }
+
+    ///- #: 第 lv 级 cluster 中的 sloppy storage
+    pub fn store(&self, lv: usize) -> Option<&SloppyStore> {
+        self.stores.get(lv)
+    }
+
+    ///- #: 在第 lv 级 cluster 中去往 key 的下一跳，本节点已是终点时返回 None
+    pub fn next_hop(&self, lv: usize, key: &NodeId) -> Option<NodeId> {
+        let table = self.tables.get(lv)?;
+        let d = self.id.distance(key);
+        table
+            .closest(key, table.len())
+            .into_iter()
+            .filter(|x| x.distance(key) < d)
+            .min_by_key(|x| (x.distance(key).leading_zeros(), x.distance(key)))
+    }
+
+    ///#### 处理 RPC 请求，级别无效时按终点且拒绝存入处理
+    ///- @from[in]: 请求方 Id
+    ///- @req[in]: 请求
+    ///- @now[in]: 当前时间
+    pub fn handle(&mut self, _from: &NodeId, req: Request, now: u64) -> Response {
+        match req {
+            Request::Probe(lv, key, ttl) => {
+                let next = self.next_hop(lv, &key);
+                match self.stores.get_mut(lv) {
+                    Some(st) => {
+                        let stop = st.is_full(&key, ttl, now) && st.is_loaded(&key, now);
+                        st.record_put(&key, now);
+                        Response::Probe(stop, next)
+                    }
+                    None => Response::Probe(true, None),
+                }
+            }
+            Request::Store(lv, key, value, ttl) => Response::Stored(
+                self.stores
+                    .get_mut(lv)
+                    .is_some_and(|st| st.insert(&key, &value, ttl, now)),
+            ),
+            Request::Get(lv, key) => Response::Values(
+                self.stores.get(lv).map_or(vec![], |st| st.get(&key, now)),
+                self.next_hop(lv, &key),
+            ),
+        }
+    }
+
+    ///#### 发起 get，从最快的 cluster 开始
+    ///- @key[in]: key
+    ///- @now[in]: 当前时间
+    pub fn get(&self, key: NodeId, now: u64) -> Lookup {
+        Lookup::get(
+            self.id,
+            key,
+            (0..LEVELS).map(|lv| self.next_hop(lv, &key)).collect(),
+            self.stores.iter().map(|st| st.get(&key, now)).collect(),
+        )
+    }
+
+    ///#### 发起 put，在所属的每一级 cluster 中各存入一次
+    ///- @key[in]: key
+    ///- @value[in]: value
+    ///- @ttl[in]: 存活时间
+    pub fn put(&self, key: NodeId, value: &[u8], ttl: u64) -> Lookup {
+        Lookup::put(
+            self.id,
+            key,
+            value.to_vec(),
+            ttl,
+            (0..LEVELS).map(|lv| self.next_hop(lv, &key)).collect(),
+        )
+    }
+}
+
+impl SimNode for CoralNode {
+    type Id = NodeId;
+    type Req = Request;
+    type Resp = Response;
+    type Query = Lookup;
+
+    fn id(&self) -> NodeId {
+        self.id
+    }
+
+    fn handle(&mut self, from: &NodeId, req: Request, now: u64) -> Response {
+        CoralNode::handle(self, from, req, now)
+    }
+}
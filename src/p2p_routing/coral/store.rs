@@ -0,0 +1,216 @@
+//! ## coral sloppy storage
+//!
+//! #### 算法说明
+//! - 同一个 key 可以对应多个 value(例如缓存了同一 URL 的多个代理)，每个 value 带有过期时间；
+//! - full：节点为 key 保存了 l 个 value，且它们的剩余 TTL 都不短于新 value TTL 的一半；
+//!   未 full 的节点总能接受新 value，必要时淘汰剩余 TTL 最短的一个；
+//! - loaded：节点在最近一个时间窗口内收到了至少 β 个针对该 key 的 put 请求；
+//! - 既 full 又 loaded 的节点不再接受 put，请求方转而将 value 存到离 key 稍远的节点上，
+//!   于是热点 key 的写入与读取被分散到 key 附近的多个节点，而不是全部压在最近的那一个节点上。
+//!
+//! #### 实现属性
+//! - <font color=Green>√</font> 多线程安全
+//! - <font color=Green>√</font> 无 unsafe 代码
+//!
+
+use super::super::peer::NodeId;
+use std::collections::{HashMap, VecDeque};
+
+///l 的默认值，即每个 key 最多保存的 value 数
+pub const L: usize = 4;
+
+///β 的默认值，即每个窗口内每个 key 最多接受的 put 请求数
+pub const BETA: usize = 12;
+
+///统计 put 请求的时间窗口，单位毫秒
+pub const WINDOW: u64 = 60_000;
+
+//- @value: 数据
+//- @expire: 过期时间
+#[derive(Clone, Debug)]
+struct Entry {
+    value: Vec<u8>,
+    expire: u64,
+}
+
+//- @l: 每个 key 最多保存的 value 数
+//- @beta: 每个窗口内每个 key 最多接受的 put 请求数
+//- @window: 统计 put 请求的时间窗口
+//- @entries: 每个 key 的 value
+//- @puts: 每个 key 最近收到 put 请求的时间，按先后排列
+#[derive(Clone, Debug)]
+pub struct SloppyStore {
+    l: usize,
+    beta: usize,
+    window: u64,
+    entries: HashMap<NodeId, Vec<Entry>>,
+    puts: HashMap<NodeId, VecDeque<u64>>,
+}
+
+impl Default for SloppyStore {
+    fn default() -> SloppyStore {
+        SloppyStore::new(L, BETA, WINDOW)
+    }
+}
+
+impl SloppyStore {
+    ///- @l[in]: 每个 key 最多保存的 value 数
+    ///- @beta[in]: 每个窗口内每个 key 最多接受的 put 请求数
+    ///- @window[in]: 统计 put 请求的时间窗口
+    pub fn new(l: usize, beta: usize, window: u64) -> SloppyStore {
+        assert!(0 < l && 0 < beta, "l and beta must be positive");
+        SloppyStore {
+            l,
+            beta,
+            window,
+            entries: HashMap::new(),
+            puts: HashMap::new(),
+        }
+    }
+
+    ///- #: key 未过期的 value 数
+    pub fn len(&self, key: &NodeId, now: u64) -> usize {
+        self.entries
+            .get(key)
+            .map_or(0, |v| v.iter().filter(|e| now < e.expire).count())
+    }
+
+    ///- #: 未过期的全部 value
+    pub fn get(&self, key: &NodeId, now: u64) -> Vec<Vec<u8>> {
+        self.entries.get(key).map_or(vec![], |v| {
+            v.iter()
+                .filter(|e| now < e.expire)
+                .map(|e| e.value.clone())
+                .collect()
+        })
+    }
+
+    ///#### 对一个 TTL 为 ttl 的新 value，节点是否 full
+    pub fn is_full(&self, key: &NodeId, ttl: u64, now: u64) -> bool {
+        let long = self.entries.get(key).map_or(0, |v| {
+            v.iter()
+                .filter(|e| now < e.expire && ttl / 2 <= e.expire - now)
+                .count()
+        });
+        self.l <= long
+    }
+
+    ///#### 节点在最近一个窗口内是否已收到至少 β 个 put 请求
+    pub fn is_loaded(&self, key: &NodeId, now: u64) -> bool {
+        self.puts.get(key).is_some_and(|q| {
+            self.beta
+                <= q.iter()
+                    .filter(|t| now < t.saturating_add(self.window))
+                    .count()
+        })
+    }
+
+    ///#### 记录一个 put 请求，用于判断 loaded
+    pub fn record_put(&mut self, key: &NodeId, now: u64) {
+        let window = self.window;
+        let q = self.puts.entry(*key).or_default();
+        while q.front().is_some_and(|t| t.saturating_add(window) <= now) {
+            q.pop_front();
+        }
+        q.push_back(now);
+    }
+
+    ///#### 保存 value，已存在时只延长过期时间
+    ///- #: 节点 full 时拒绝并返回 false
+    ///- @key[in]: key
+    ///- @value[in]: value
+    ///- @ttl[in]: 存活时间
+    ///- @now[in]: 当前时间
+    pub fn insert(&mut self, key: &NodeId, value: &[u8], ttl: u64, now: u64) -> bool {
+        let expire = now.saturating_add(ttl);
+        let l = self.l;
+        let full = self.is_full(key, ttl, now);
+        let v = self.entries.entry(*key).or_default();
+        v.retain(|e| now < e.expire);
+        if let Some(e) = v.iter_mut().find(|e| e.value == value) {
+            e.expire = e.expire.max(expire);
+            return true;
+        }
+        if full {
+            return false;
+        }
+
+        if l <= v.len() {
+            //淘汰剩余 TTL 最短的 value
+            let pos = (0..v.len()).min_by_key(|i| v[*i].expire).unwrap_or(0);
+            v.remove(pos);
+        }
+        v.push(Entry {
+            value: value.to_vec(),
+            expire,
+        });
+        true
+    }
+
+    ///#### 清除过期的 value 与 put 记录
+    pub fn expire(&mut self, now: u64) {
+        self.entries.retain(|_, v| {
+            v.retain(|e| now < e.expire);
+            !v.is_empty()
+        });
+        let window = self.window;
+        self.puts.retain(|_, q| {
+            q.retain(|t| now < t.saturating_add(window));
+            !q.is_empty()
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn sloppy() {
+        let key = NodeId::new([1; 32]);
+        let mut st = SloppyStore::new(2, 3, 100);
+        assert!(st.insert(&key, b"a", 1000, 0));
+        assert!(st.insert(&key, b"a", 1000, 0));
+        assert!(st.insert(&key, b"b", 300, 0));
+        assert_eq!(2, st.len(&key, 0));
+        assert!(!st.is_full(&key, 1000, 0));
+        assert!(st.is_full(&key, 600, 0));
+
+        //"b" has the shortest ttl left and is evicted
+        assert!(!st.insert(&key, b"c", 600, 0));
+        assert!(st.insert(&key, b"c", 1000, 0));
+        assert_eq!(vec![b"a".to_vec(), b"c".to_vec()], st.get(&key, 0));
+        assert!(st.is_full(&key, 1000, 0));
+
+        for t in 0..3 {
+            assert!(!st.is_loaded(&key, t));
+            st.record_put(&key, t);
+        }
+        assert!(st.is_loaded(&key, 3));
+        assert!(!st.is_loaded(&key, 100));
+
+        //values expire
+        assert_eq!(2, st.len(&key, 999));
+        assert_eq!(0, st.len(&key, 1000));
+        st.expire(1000);
+        assert!(st.get(&key, 1000).is_empty());
+        assert!(!st.is_full(&key, 1000, 1000));
+    }
+    #[test]
+    fn no_overflow() {
+        let key = NodeId::new([1; 32]);
+        let mut st = SloppyStore::new(1, 1, u64::MAX);
+        //an infinite ttl never expires
+        assert!(st.insert(&key, b"a", u64::MAX, 1));
+        assert_eq!(1, st.len(&key, u64::MAX - 1));
+        assert!(st.is_full(&key, 2, u64::MAX - 1));
+
+        //an infinite window keeps every put
+        st.record_put(&key, 1);
+        st.record_put(&key, u64::MAX - 1);
+        assert!(st.is_loaded(&key, u64::MAX - 1));
+        st.expire(u64::MAX - 1);
+        assert_eq!(1, st.len(&key, u64::MAX - 1));
+        assert!(st.is_loaded(&key, u64::MAX - 1));
+    }
+}
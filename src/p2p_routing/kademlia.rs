@@ -1,9 +0,0 @@
-/// ## kademlia P2P routing
-
-#[cfg(test)]
-mod tests {
-    #[test]
-    fn test() {
-        assert_eq!(1 + 9, 10);
-    }
-}
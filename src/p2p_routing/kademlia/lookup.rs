@@ -0,0 +1,622 @@
+//! ## kademlia 迭代查找
+//!
+//! #### 算法说明
+//! - shortlist：按与目标的距离升序排列的候选节点，初始为本地路由表中最近的 k 个节点，
+//!   每收到一个 FIND_NODE 响应，就把其中尚未见过的节点并入 shortlist；
+//! - 窗口：shortlist 中未失败的最近 k 个节点；每次从窗口中挑选尚未询问的节点发出请求，
+//!   同时在途的请求不超过 α 个；
+//! - 终止条件：窗口内的节点全部已成功响应(FIND_VALUE 另外在取得 value 时立即终止)，
+//!   这等价于论文中"一轮未发现更近的节点时，向最近的 k 个节点中尚未询问过的全部发出请求"；
+//! - 超时的节点标记为失败，移出窗口，由后面的候选节点补上；
+//! - FIND_VALUE 取得 value 之后，应将其 STORE 到路径上最近的、未返回 value 的节点(缓存)；
+//! - STORE：先以 FIND_NODE 找到距离 key 最近的 k 个节点，再逐一发送 STORE；
+//! - ADD_PROVIDER 与 STORE 相同，只是写入的是"本节点可提供 key 对应的内容"这一记录；
+//!   GET_PROVIDERS 的每个响应同时带有已知的 provider 与更近的节点，查找按 FIND_NODE 的方式进行并汇总全部 provider；
+//! - 查找本身是一个状态机(poll/on_response)，可由任意调度器驱动；run 以同步 Transport 逐批驱动，
+//!   每批最多 α 个请求，等价于 α 路并发的锁步执行。
+//!
+//! #### 实现属性
+//! - <font color=Green>√</font> 多线程安全
+//! - <font color=Green>√</font> 无 unsafe 代码
+//!
+
+use super::super::peer::NodeId;
+use super::super::sim::Query;
+
+///α 的默认值，即同时在途的请求数
+pub const ALPHA: usize = 3;
+
+///kademlia RPC 请求
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Request<const N: usize> {
+    Ping,
+    FindNode(NodeId<N>),
+    FindValue(NodeId<N>),
+    Store(NodeId<N>, Vec<u8>),
+    ///请求方声明自己是 key 的 provider
+    AddProvider(NodeId<N>),
+    GetProviders(NodeId<N>),
+}
+
+///kademlia RPC 响应
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Response<const N: usize> {
+    Pong,
+    Nodes(Vec<NodeId<N>>),
+    Value(Vec<u8>),
+    Stored,
+    ///(已知的 provider, 距离 key 最近的节点)
+    Providers(Vec<NodeId<N>>, Vec<NodeId<N>>),
+}
+
+///抽象的传输层，测试中由进程内的模拟网络实现
+pub trait Transport<const N: usize> {
+    ///#### 发送请求并等待响应
+    ///- #: None 表示超时或对端离线
+    ///- @from[in]: 请求方 Id，对端据此更新其路由表
+    ///- @to[in]: 对端 Id
+    ///- @req[in]: 请求
+    fn call(&mut self, from: &NodeId<N>, to: &NodeId<N>, req: Request<N>) -> Option<Response<N>>;
+}
+
+//查找的类型
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Kind {
+    FindNode,
+    FindValue,
+    GetProviders,
+}
+
+//shortlist 中每个节点的状态
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Peer {
+    Waiting,
+    InFlight,
+    Succeeded,
+    Failed,
+}
+
+///查找结果
+///- @target: 查找的目标
+///- @closest: 已成功响应的节点中距离目标最近的至多 k 个，按距离升序排列
+///- @value: FIND_VALUE 取得的 value
+///- @providers: GET_PROVIDERS 取得的全部 provider
+///- @cache_at: FIND_VALUE 取得 value 时，未返回 value 的节点中距离目标最近的一个，value 应缓存于此
+///- @succeeded: 全部成功响应的节点，调用方应据此更新路由表
+///- @failed: 全部超时的节点，调用方应将其移出路由表
+///- @rpc_cnt: 发出的请求总数
+#[derive(Clone, Debug)]
+pub struct LookupResult<const N: usize> {
+    pub target: NodeId<N>,
+    pub closest: Vec<NodeId<N>>,
+    pub value: Option<Vec<u8>>,
+    pub providers: Vec<NodeId<N>>,
+    pub cache_at: Option<NodeId<N>>,
+    pub succeeded: Vec<NodeId<N>>,
+    pub failed: Vec<NodeId<N>>,
+    pub rpc_cnt: usize,
+}
+
+//- @local: 发起查找的本节点，永不进入 shortlist
+//- @target: 查找的目标
+//- @kind: 查找的类型
+//- @k: 窗口大小
+//- @alpha: 同时在途的请求数上限
+//- @shortlist: 全部候选节点，按与目标的距离升序排列
+//- @value: 已取得的 value
+//- @value_from: 返回 value 的节点
+//- @providers: 已取得的 provider
+//- @rpc_cnt: 发出的请求总数
+#[derive(Clone, Debug)]
+pub struct Lookup<const N: usize> {
+    local: NodeId<N>,
+    target: NodeId<N>,
+    kind: Kind,
+    k: usize,
+    alpha: usize,
+    shortlist: Vec<(NodeId<N>, Peer)>,
+    value: Option<Vec<u8>>,
+    value_from: Option<NodeId<N>>,
+    providers: Vec<NodeId<N>>,
+    rpc_cnt: usize,
+}
+
+impl<const N: usize> Lookup<N> {
+    fn new(
+        local: NodeId<N>,
+        target: NodeId<N>,
+        kind: Kind,
+        seeds: &[NodeId<N>],
+        k: usize,
+        alpha: usize,
+    ) -> Lookup<N> {
+        assert!(0 < k && 0 < alpha, "k and alpha must be positive");
+        let mut res = Lookup {
+            local,
+            target,
+            kind,
+            k,
+            alpha,
+            shortlist: vec![],
+            value: None,
+            value_from: None,
+            providers: vec![],
+            rpc_cnt: 0,
+        };
+        res.merge(seeds);
+        res
+    }
+
+    ///- @local[in]: 本节点 Id
+    ///- @target[in]: 目标 Id
+    ///- @seeds[in]: 初始候选节点，通常为本地路由表中距离目标最近的 k 个
+    ///- @k[in]: 窗口大小
+    ///- @alpha[in]: 同时在途的请求数上限
+    pub fn find_node(
+        local: NodeId<N>,
+        target: NodeId<N>,
+        seeds: &[NodeId<N>],
+        k: usize,
+        alpha: usize,
+    ) -> Lookup<N> {
+        Lookup::new(local, target, Kind::FindNode, seeds, k, alpha)
+    }
+
+    ///- @local[in]: 本节点 Id
+    ///- @key[in]: value 的 key
+    ///- @seeds[in]: 初始候选节点，通常为本地路由表中距离 key 最近的 k 个
+    ///- @k[in]: 窗口大小
+    ///- @alpha[in]: 同时在途的请求数上限
+    pub fn find_value(
+        local: NodeId<N>,
+        key: NodeId<N>,
+        seeds: &[NodeId<N>],
+        k: usize,
+        alpha: usize,
+    ) -> Lookup<N> {
+        Lookup::new(local, key, Kind::FindValue, seeds, k, alpha)
+    }
+
+    ///- @local[in]: 本节点 Id
+    ///- @key[in]: 内容的 key
+    ///- @seeds[in]: 初始候选节点，通常为本地路由表中距离 key 最近的 k 个
+    ///- @k[in]: 窗口大小
+    ///- @alpha[in]: 同时在途的请求数上限
+    pub fn get_providers(
+        local: NodeId<N>,
+        key: NodeId<N>,
+        seeds: &[NodeId<N>],
+        k: usize,
+        alpha: usize,
+    ) -> Lookup<N> {
+        Lookup::new(local, key, Kind::GetProviders, seeds, k, alpha)
+    }
+
+    #[inline(always)]
+    pub fn target(&self) -> &NodeId<N> {
+        &self.target
+    }
+
+    //将尚未见过的节点按距离插入 shortlist
+    fn merge(&mut self, nodes: &[NodeId<N>]) {
+        let (local, target) = (self.local, self.target);
+        for id in nodes.iter().filter(|id| **id != local) {
+            let d = id.distance(&target);
+            if let Err(pos) = self
+                .shortlist
+                .binary_search_by(|(x, _)| x.distance(&target).cmp(&d))
+            {
+                self.shortlist.insert(pos, (*id, Peer::Waiting));
+            }
+        }
+    }
+
+    //- #: 窗口，即未失败的最近 k 个节点
+    fn window(&mut self) -> impl Iterator<Item = &mut (NodeId<N>, Peer)> {
+        let k = self.k;
+        self.shortlist
+            .iter_mut()
+            .filter(|(_, p)| Peer::Failed != *p)
+            .take(k)
+    }
+
+    ///#### 查找是否已结束
+    pub fn is_finished(&self) -> bool {
+        if self.value.is_some() {
+            return true;
+        }
+        self.shortlist
+            .iter()
+            .filter(|(_, p)| Peer::Failed != *p)
+            .take(self.k)
+            .all(|(_, p)| Peer::Succeeded == *p)
+    }
+
+    ///#### 取出下一批请求，并将这些节点标记为在途
+    ///- #: (对端 Id, 请求)，查找已结束或在途请求已达 α 个时为空
+    pub fn poll(&mut self) -> Vec<(NodeId<N>, Request<N>)> {
+        if self.is_finished() {
+            return vec![];
+        }
+
+        let in_flight = self
+            .shortlist
+            .iter()
+            .filter(|(_, p)| Peer::InFlight == *p)
+            .count();
+        let cnt = self.alpha.saturating_sub(in_flight);
+        let req = match self.kind {
+            Kind::FindNode => Request::FindNode(self.target),
+            Kind::FindValue => Request::FindValue(self.target),
+            Kind::GetProviders => Request::GetProviders(self.target),
+        };
+
+        let mut res = vec![];
+        for (id, p) in self.window().filter(|(_, p)| Peer::Waiting == *p).take(cnt) {
+            *p = Peer::InFlight;
+            res.push((*id, req.clone()));
+        }
+        self.rpc_cnt += res.len();
+        res
+    }
+
+    ///#### 处理一个在途请求的结果
+    ///- @from[in]: 对端 Id
+    ///- @resp[in]: 响应，None 表示超时
+    pub fn on_response(&mut self, from: &NodeId<N>, resp: Option<Response<N>>) {
+        let pos = match self.shortlist.iter().position(|(id, _)| id == from) {
+            Some(pos) => pos,
+            None => return,
+        };
+
+        match resp {
+            Some(Response::Nodes(nodes)) => {
+                self.shortlist[pos].1 = Peer::Succeeded;
+                self.merge(&nodes);
+            }
+            Some(Response::Value(v)) if Kind::FindValue == self.kind => {
+                self.shortlist[pos].1 = Peer::Succeeded;
+                self.value_from.get_or_insert(*from);
+                self.value.get_or_insert(v);
+            }
+            Some(Response::Providers(providers, closer)) if Kind::GetProviders == self.kind => {
+                self.shortlist[pos].1 = Peer::Succeeded;
+                self.merge(&closer);
+                for p in providers {
+                    if !self.providers.contains(&p) {
+                        self.providers.push(p);
+                    }
+                }
+            }
+            _ => self.shortlist[pos].1 = Peer::Failed,
+        }
+    }
+
+    ///#### 汇总查找结果
+    pub fn result(&self) -> LookupResult<N> {
+        let succeeded = self
+            .shortlist
+            .iter()
+            .filter(|(_, p)| Peer::Succeeded == *p)
+            .map(|(id, _)| *id)
+            .collect::<Vec<NodeId<N>>>();
+        LookupResult {
+            target: self.target,
+            closest: succeeded.iter().take(self.k).cloned().collect(),
+            cache_at: self
+                .value_from
+                .and_then(|from| succeeded.iter().find(|id| **id != from).cloned()),
+            value: self.value.clone(),
+            providers: self.providers.clone(),
+            failed: self
+                .shortlist
+                .iter()
+                .filter(|(_, p)| Peer::Failed == *p)
+                .map(|(id, _)| *id)
+                .collect(),
+            succeeded,
+            rpc_cnt: self.rpc_cnt,
+        }
+    }
+
+    ///#### 以同步的 Transport 驱动查找直至结束
+    ///- @transport[in]: 传输层
+    pub fn run<T: Transport<N>>(mut self, transport: &mut T) -> LookupResult<N> {
+        loop {
+            let reqs = self.poll();
+            if reqs.is_empty() {
+                break;
+            }
+            for (to, req) in reqs {
+                let resp = transport.call(&self.local, &to, req);
+                self.on_response(&to, resp);
+            }
+        }
+        self.result()
+    }
+}
+
+impl<const N: usize> Query<NodeId<N>, Request<N>, Response<N>> for Lookup<N> {
+    fn poll(&mut self, _now: u64) -> Vec<(NodeId<N>, Request<N>)> {
+        Lookup::poll(self)
+    }
+
+    fn on_response(&mut self, from: &NodeId<N>, resp: Option<Response<N>>) {
+        Lookup::on_response(self, from, resp)
+    }
+
+    fn is_finished(&self) -> bool {
+        Lookup::is_finished(self)
+    }
+
+    //FIND_VALUE 须取得 value，GET_PROVIDERS 须取得 provider，FIND_NODE 须至少有一个节点成功响应
+    fn is_success(&self) -> bool {
+        match self.kind {
+            Kind::FindNode => self.shortlist.iter().any(|(_, p)| Peer::Succeeded == *p),
+            Kind::FindValue => self.value.is_some(),
+            Kind::GetProviders => !self.providers.is_empty(),
+        }
+    }
+}
+
+///#### 迭代 STORE：找到距离 key 最近的 k 个节点，逐一发送 STORE
+///- #: (成功存储的节点数, 其中 FIND_NODE 阶段的查找结果)
+///- @transport[in]: 传输层
+///- @local[in]: 本节点 Id
+///- @seeds[in]: 初始候选节点
+///- @key[in]: value 的 key
+///- @value[in]: 要存储的数据
+///- @k[in]: 窗口大小，即存储的副本数
+///- @alpha[in]: 同时在途的请求数上限
+pub fn store<T: Transport<N>, const N: usize>(
+    transport: &mut T,
+    local: &NodeId<N>,
+    seeds: &[NodeId<N>],
+    key: NodeId<N>,
+    value: &[u8],
+    k: usize,
+    alpha: usize,
+) -> (usize, LookupResult<N>) {
+    let res = Lookup::find_node(*local, key, seeds, k, alpha).run(transport);
+    let cnt = res
+        .closest
+        .iter()
+        .filter(|to| {
+            Some(Response::Stored) == transport.call(local, to, Request::Store(key, value.to_vec()))
+        })
+        .count();
+    (cnt, res)
+}
+
+///#### 迭代 ADD_PROVIDER：找到距离 key 最近的 k 个节点，逐一声明本节点为 provider
+///- #: (接受记录的节点数, 其中 FIND_NODE 阶段的查找结果)
+///- @transport[in]: 传输层
+///- @local[in]: 本节点 Id，即 provider
+///- @seeds[in]: 初始候选节点
+///- @key[in]: 内容的 key
+///- @k[in]: 窗口大小，即记录的副本数
+///- @alpha[in]: 同时在途的请求数上限
+pub fn add_provider<T: Transport<N>, const N: usize>(
+    transport: &mut T,
+    local: &NodeId<N>,
+    seeds: &[NodeId<N>],
+    key: NodeId<N>,
+    k: usize,
+    alpha: usize,
+) -> (usize, LookupResult<N>) {
+    let res = Lookup::find_node(*local, key, seeds, k, alpha).run(transport);
+    let cnt = res
+        .closest
+        .iter()
+        .filter(|to| Some(Response::Stored) == transport.call(local, to, Request::AddProvider(key)))
+        .count();
+    (cnt, res)
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::super::testutil::{id, ids, Net};
+    use super::super::node::Node;
+    use super::super::provider::{MemoryStore, RecordStore, PROVIDER_TTL, REPUBLISH_INTERVAL};
+    use super::*;
+
+    const K: usize = 8;
+
+    #[test]
+    fn state_machine() {
+        let local = id("local");
+        let seeds = ids(10);
+        let mut lookup = Lookup::find_node(local, id("target"), &seeds, 4, 2);
+
+        //at most alpha requests in flight
+        let first = lookup.poll();
+        assert_eq!(2, first.len());
+        assert!(lookup.poll().is_empty());
+
+        //a timeout frees a slot and shrinks the window
+        lookup.on_response(&first[0].0, None);
+        let second = lookup.poll();
+        assert_eq!(1, second.len());
+        lookup.on_response(&first[1].0, Some(Response::Nodes(vec![local])));
+        lookup.on_response(&second[0].0, Some(Response::Nodes(vec![])));
+        assert!(!lookup.is_finished());
+
+        loop {
+            let reqs = lookup.poll();
+            if reqs.is_empty() {
+                break;
+            }
+            for (to, _) in reqs {
+                lookup.on_response(&to, Some(Response::Nodes(vec![])));
+            }
+        }
+        assert!(lookup.is_finished());
+
+        let res = lookup.result();
+        assert_eq!(4, res.closest.len());
+        assert_eq!(vec![first[0].0], res.failed);
+        assert!(!res.succeeded.contains(&local));
+        assert_eq!(5, res.rpc_cnt);
+        let mut want = seeds.clone();
+        want.retain(|x| *x != first[0].0);
+        want.sort_by_key(|x| x.distance(&id("target")));
+        assert_eq!(want[..4].to_vec(), res.closest);
+    }
+
+    #[test]
+    fn find_node() {
+        let (mut net, ids) = Net::build(128, K);
+
+        let mut rpc_cnt = 0;
+        for i in 0..20 {
+            let target = id(&format!("target{}", i));
+            let from = ids[(i * 7) % ids.len()];
+            let res = net.nodes[&from].find_node(target, ALPHA).run(&mut net);
+            assert_eq!(net.brute_force(&from, &target, K), res.closest);
+            assert!(res.failed.is_empty());
+            rpc_cnt += res.rpc_cnt;
+        }
+        assert!(rpc_cnt < 20 * 30);
+
+        //nodes that went offline are skipped and reported
+        let target = id("target");
+        let gone = net.brute_force(&ids[1], &target, K)[..3].to_vec();
+        net.offline.extend(gone.iter().cloned());
+        let res = net.nodes[&ids[1]].find_node(target, ALPHA).run(&mut net);
+        assert_eq!(net.brute_force(&ids[1], &target, K), res.closest);
+        assert!(gone.iter().all(|g| res.failed.contains(g)));
+
+        let now = net.now;
+        let node = net.nodes.get_mut(&ids[1]).unwrap();
+        node.apply(&res, now);
+        assert!(gone.iter().all(|g| !node.table().contains(g)));
+    }
+
+    #[test]
+    fn store_find_value() {
+        let (mut net, ids) = Net::build(64, K);
+        let key = id("key");
+
+        let seeds = net.nodes[&ids[3]].table().closest(&key, K);
+        let (cnt, res) = store(&mut net, &ids[3], &seeds, key, b"value", K, ALPHA);
+        assert_eq!(K, cnt);
+        for holder in res.closest.iter() {
+            assert_eq!(Some(&b"value".to_vec()), net.nodes[holder].value(&key));
+        }
+
+        //half of the replicas are offline, the value is still found
+        net.offline.extend(res.closest[..K / 2].iter().cloned());
+        let from = ids
+            .iter()
+            .find(|x| !res.closest.contains(x) && **x != ids[3])
+            .unwrap();
+        let found = net.nodes[from]
+            .find_value(key, ALPHA)
+            .unwrap()
+            .run(&mut net);
+        assert_eq!(Some(b"value".to_vec()), found.value);
+
+        //cache the value on the closest node that did not have it
+        if let Some(c) = found.cache_at {
+            assert!(net.nodes[&c].value(&key).is_none());
+            assert_eq!(
+                Some(Response::Stored),
+                net.call(from, &c, Request::Store(key, b"value".to_vec()))
+            );
+        }
+
+        assert!(net.nodes[&res.closest[K - 1]]
+            .find_value(key, ALPHA)
+            .is_none());
+        let missing = net.nodes[from]
+            .find_value(id("nothing"), ALPHA)
+            .unwrap()
+            .run(&mut net);
+        assert!(missing.value.is_none());
+        assert!(missing.cache_at.is_none());
+    }
+
+    #[test]
+    fn providers() {
+        let (mut net, ids) = Net::build(64, K);
+        let key = id("content");
+        let provider = ids[5];
+
+        //the record is replicated to the k closest nodes
+        let now = net.now;
+        net.nodes
+            .get_mut(&provider)
+            .unwrap()
+            .start_providing(key, now);
+        let seeds = net.nodes[&provider].table().closest(&key, K);
+        let (cnt, res) = add_provider(&mut net, &provider, &seeds, key, K, ALPHA);
+        assert_eq!(K, cnt);
+        assert_eq!(net.brute_force(&provider, &key, K), res.closest);
+        for holder in res.closest.iter() {
+            assert_eq!(vec![provider], net.nodes[holder].providers(&key, now));
+        }
+
+        let from = ids
+            .iter()
+            .find(|x| !res.closest.contains(x) && **x != provider)
+            .unwrap();
+        let found = net.nodes[from].get_providers(key, ALPHA).run(&mut net);
+        assert_eq!(vec![provider], found.providers);
+        let none = net.nodes[from]
+            .get_providers(id("nothing"), ALPHA)
+            .run(&mut net);
+        assert!(none.providers.is_empty());
+
+        //without republishing the records expire
+        net.now += REPUBLISH_INTERVAL;
+        assert!(net.nodes[&provider]
+            .republish_due(net.now - 1, REPUBLISH_INTERVAL)
+            .is_empty());
+        assert_eq!(
+            vec![key],
+            net.nodes[&provider].republish_due(net.now, REPUBLISH_INTERVAL)
+        );
+        net.now += PROVIDER_TTL;
+        let now = net.now;
+        for node in net.nodes.values_mut() {
+            node.expire(now);
+        }
+        assert!(net.nodes.values().all(|n| n.store().is_empty()));
+        let lost = net.nodes[from].get_providers(key, ALPHA).run(&mut net);
+        assert!(lost.providers.is_empty());
+
+        //republishing restores them
+        for key in net.nodes[&provider].republish_due(now, REPUBLISH_INTERVAL) {
+            let seeds = net.nodes[&provider].table().closest(&key, K);
+            assert_eq!(
+                K,
+                add_provider(&mut net, &provider, &seeds, key, K, ALPHA).0
+            );
+            net.nodes
+                .get_mut(&provider)
+                .unwrap()
+                .mark_published(&key, now);
+        }
+        assert!(net.nodes[&provider]
+            .republish_due(now, REPUBLISH_INTERVAL)
+            .is_empty());
+        let found = net.nodes[from].get_providers(key, ALPHA).run(&mut net);
+        assert_eq!(vec![provider], found.providers);
+        assert!(net.nodes.get_mut(&provider).unwrap().stop_providing(&key));
+        assert!(net.nodes[&provider]
+            .republish_due(now + REPUBLISH_INTERVAL, REPUBLISH_INTERVAL)
+            .is_empty());
+
+        //a key full of stale records accepts a fresh provider before any expire()
+        let mut node = Node::with_store(ids[0], K, 0, MemoryStore::new(2));
+        let add = Request::AddProvider(key);
+        assert_eq!(Response::Stored, node.handle(&ids[1], add.clone(), 0));
+        assert_eq!(Response::Stored, node.handle(&ids[2], add.clone(), 0));
+        assert_ne!(Response::Stored, node.handle(&ids[3], add.clone(), 1));
+        assert_eq!(
+            Response::Stored,
+            node.handle(&ids[3], add, PROVIDER_TTL + 1)
+        );
+        assert_eq!(vec![ids[3]], node.providers(&key, PROVIDER_TTL + 1));
+    }
+}
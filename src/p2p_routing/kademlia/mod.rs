@@ -0,0 +1,22 @@
+//! ## kademlia P2P routing
+//!
+//! #### 算法说明
+//! - 每个节点拥有一个固定长度的 Id，两个 Id 之间的距离定义为按位异或(XOR)之后的无符号整数值；
+//! - XOR 距离满足：d(x, x) = 0，d(x, y) = d(y, x)，d(x, z) <= d(x, y) + d(y, z)，
+//!   且对任意 x 与距离 d，恰有一个 y 满足 d(x, y) = d，因此查找路径最终会收敛到同一组节点；
+//! - 路由表由若干 k-bucket 组成，第 i 个 bucket 保存与本节点 Id 的公共前缀恰为 i bit 的节点，
+//!   距离越远的区间节点越多，但每个 bucket 最多保存 k 个，于是路由表在 Id 空间上呈对数分布；
+//! - 查找时每一跳至少将与目标的公共前缀延长 1 bit，O(log n) 跳即可到达目标附近。
+//!
+//! #### 应用场景
+//! - 以太坊 devp2p 节点发现、IPFS/libp2p DHT、BitTorrent Mainline DHT。
+//!
+//! #### 实现属性
+//! - <font color=Green>√</font> 多线程安全
+//! - <font color=Green>√</font> 无 unsafe 代码
+//!
+
+pub mod lookup;
+pub mod node;
+pub mod provider;
+pub mod routing;
@@ -0,0 +1,231 @@
+//! ## kademlia 节点
+//!
+//! #### 算法说明
+//! - 每个节点由路由表、本地存储与 provider 记录组成，处理 PING、FIND_NODE、FIND_VALUE、STORE、
+//!   ADD_PROVIDER、GET_PROVIDERS 六种 RPC；
+//! - 收到任何请求都会用请求方的 Id 更新路由表，这是路由表获知新节点的主要途径；
+//!   bucket 已满时新节点留在 replacement cache 中，由调用方决定何时 ping LRU 节点；
+//! - 发起查找时以路由表中最近的 k 个节点为起点，查找结束后用结果更新路由表：
+//!   成功响应的节点加入，超时的节点移除，目标所在的 bucket 视为已刷新；
+//! - 加入网络：先将引导节点加入路由表，再查找自身 Id，沿途的节点会填满本节点的路由表，
+//!   同时本节点也被加入它们的路由表；
+//! - 本节点提供的每个 key 记录上次发布的时间，调用方按 republish_due 的结果定期重新发布，
+//!   收到的 provider 记录在过期后由 expire 清除。
+//!
+//! #### 实现属性
+//! - <font color=Green>√</font> 多线程安全
+//! - <font color=Green>√</font> 无 unsafe 代码
+//!
+
+use super::super::peer::NodeId;
+use super::super::sim::SimNode;
+use super::lookup::{Lookup, LookupResult, Request, Response};
+use super::provider::{MemoryStore, ProviderRecord, RecordStore, PROVIDER_TTL};
+use super::routing::RoutingTable;
+use std::collections::{BTreeMap, HashMap};
+
+//- @table: 路由表
+//- @values: 本地存储的 (key, value)
+//- @providers: 收到的 provider 记录
+//- @provided: 本节点提供的 key 及其上次发布的时间
+#[derive(Clone, Debug)]
+pub struct Node<const N: usize = 32, S = MemoryStore<N>> {
+    table: RoutingTable<N>,
+    values: HashMap<NodeId<N>, Vec<u8>>,
+    providers: S,
+    provided: BTreeMap<NodeId<N>, u64>,
+}
+
+impl<const N: usize> Node<N> {
+    ///- @id[in]: 本节点 Id
+    ///- @k[in]: 每个 bucket 最多保存的节点数
+    ///- @now[in]: 当前时间
+    pub fn new(id: NodeId<N>, k: usize, now: u64) -> Node<N> {
+        Node::with_store(id, k, now, MemoryStore::default())
+    }
+}
+
+impl<const N: usize, S: RecordStore<N>> Node<N, S> {
+    ///#### 以指定的 provider 记录存储构造节点
+    ///- @id[in]: 本节点 Id
+    ///- @k[in]: 每个 bucket 最多保存的节点数
+    ///- @now[in]: 当前时间
+    ///- @providers[in]: provider 记录存储
+    pub fn with_store(id: NodeId<N>, k: usize, now: u64, providers: S) -> Node<N, S> {
+        Node {
+            table: RoutingTable::new(id, k, now),
+            values: HashMap::new(),
+            providers,
+            provided: BTreeMap::new(),
+        }
+    }
+
+    #[inline(always)]
+    pub fn id(&self) -> &NodeId<N> {
+        self.table.local()
+    }
+
+    #[inline(always)]
+    pub fn table(&self) -> &RoutingTable<N> {
+        &self.table
+    }
+
+    #[inline(always)]
+    pub fn table_mut(&mut self) -> &mut RoutingTable<N> {
+        &mut self.table
+    }
+
+    ///- #: 本地存储的 value
+    pub fn value(&self, key: &NodeId<N>) -> Option<&Vec<u8>> {
+        self.values.get(key)
+    }
+
+    #[inline(always)]
+    pub fn store(&self) -> &S {
+        &self.providers
+    }
+
+    ///- #: 本地保存的 key 未过期的 provider
+    pub fn providers(&self, key: &NodeId<N>, now: u64) -> Vec<NodeId<N>> {
+        self.providers
+            .providers(key, now)
+            .into_iter()
+            .map(|r| r.provider)
+            .collect()
+    }
+
+    ///#### 处理收到的请求
+    ///- @from[in]: 请求方 Id
+    ///- @req[in]: 请求
+    ///- @now[in]: 当前时间
+    pub fn handle(&mut self, from: &NodeId<N>, req: Request<N>, now: u64) -> Response<N> {
+        self.table.update(from, now);
+        let k = self.table.k();
+        match req {
+            Request::Ping => Response::Pong,
+            Request::FindNode(target) => Response::Nodes(self.table.closest(&target, k)),
+            Request::FindValue(key) => match self.values.get(&key) {
+                Some(v) => Response::Value(v.clone()),
+                None => Response::Nodes(self.table.closest(&key, k)),
+            },
+            Request::Store(key, value) => {
+                self.values.insert(key, value);
+                Response::Stored
+            }
+            Request::AddProvider(key) => {
+                let rec = ProviderRecord {
+                    key,
+                    provider: *from,
+                    expire: now + PROVIDER_TTL,
+                };
+                if self.providers.add_provider(rec, now) {
+                    Response::Stored
+                } else {
+                    Response::Nodes(self.table.closest(&key, k))
+                }
+            }
+            Request::GetProviders(key) => {
+                Response::Providers(self.providers(&key, now), self.table.closest(&key, k))
+            }
+        }
+    }
+
+    ///#### 以路由表中最近的 k 个节点为起点，构造一次 FIND_NODE
+    pub fn find_node(&self, target: NodeId<N>, alpha: usize) -> Lookup<N> {
+        let k = self.table.k();
+        Lookup::find_node(
+            *self.id(),
+            target,
+            &self.table.closest(&target, k),
+            k,
+            alpha,
+        )
+    }
+
+    ///#### 以路由表中最近的 k 个节点为起点，构造一次 FIND_VALUE，本地已有时返回 None
+    pub fn find_value(&self, key: NodeId<N>, alpha: usize) -> Option<Lookup<N>> {
+        if self.values.contains_key(&key) {
+            return None;
+        }
+        let k = self.table.k();
+        Some(Lookup::find_value(
+            *self.id(),
+            key,
+            &self.table.closest(&key, k),
+            k,
+            alpha,
+        ))
+    }
+
+    ///#### 以路由表中最近的 k 个节点为起点，构造一次 GET_PROVIDERS
+    pub fn get_providers(&self, key: NodeId<N>, alpha: usize) -> Lookup<N> {
+        let k = self.table.k();
+        Lookup::get_providers(*self.id(), key, &self.table.closest(&key, k), k, alpha)
+    }
+
+    ///#### 开始提供 key 对应的内容，此后应立即发布一次 provider 记录
+    pub fn start_providing(&mut self, key: NodeId<N>, now: u64) {
+        self.provided.insert(key, now);
+    }
+
+    ///#### 停止提供 key 对应的内容，已发布的记录在过期后自然消失
+    pub fn stop_providing(&mut self, key: &NodeId<N>) -> bool {
+        self.provided.remove(key).is_some()
+    }
+
+    ///- #: 距离上次发布已达 interval、需要重新发布的 key
+    ///- @now[in]: 当前时间
+    ///- @interval[in]: 重新发布的间隔
+    pub fn republish_due(&self, now: u64, interval: u64) -> Vec<NodeId<N>> {
+        self.provided
+            .iter()
+            .filter(|(_, t)| **t + interval <= now)
+            .map(|(key, _)| *key)
+            .collect()
+    }
+
+    ///#### 记录 key 已于 now 重新发布
+    pub fn mark_published(&mut self, key: &NodeId<N>, now: u64) {
+        if let Some(t) = self.provided.get_mut(key) {
+            *t = now;
+        }
+    }
+
+    ///#### 清除过期的 provider 记录
+    ///- #: 清除的记录数
+    pub fn expire(&mut self, now: u64) -> usize {
+        self.providers.expire(now)
+    }
+
+    ///#### 用查找结果更新路由表
+    ///- @res[in]: 查找结果
+    ///- @now[in]: 当前时间
+    pub fn apply(&mut self, res: &LookupResult<N>, now: u64) {
+        res.succeeded.iter().for_each(|id| {
+            self.table.update(id, now);
+        });
+        res.failed.iter().for_each(|id| {
+            self.table.remove(id);
+        });
+        self.table.mark_refreshed(&res.target, now);
+    }
+}
+
+impl<const N: usize, S: RecordStore<N>> SimNode for Node<N, S> {
+    type Id = NodeId<N>;
+    type Req = Request<N>;
+    type Resp = Response<N>;
+    type Query = Lookup<N>;
+
+    fn id(&self) -> NodeId<N> {
+        *Node::id(self)
+    }
+
+    fn handle(&mut self, from: &NodeId<N>, req: Request<N>, now: u64) -> Response<N> {
+        Node::handle(self, from, req, now)
+    }
+
+    fn on_finished(&mut self, query: &Lookup<N>, now: u64) {
+        self.apply(&query.result(), now);
+    }
+}
@@ -0,0 +1,190 @@
+//! ## kademlia provider 记录
+//!
+//! #### 算法说明
+//! - 内容本身不存入 DHT，DHT 中只保存 provider 记录："节点 P 可以提供 key 对应的内容"；
+//! - provider 通过 ADD_PROVIDER 将记录复制到距离 key 最近的 k 个节点上，记录带有过期时间，
+//!   过期之前 provider 须周期性地重新发布(republish)，否则记录自然消失，于是离线的 provider 不会长期残留；
+//! - 只接受请求方为自己发布的记录，节点不能替其他节点声明 provider；
+//! - 记录的存取通过 RecordStore 抽象，默认实现 MemoryStore 保存在内存中，每个 key 的记录数有上限。
+//!
+//! #### 应用场景
+//! - 区块体的分发：持有区块的节点发布 provider 记录，需要区块的节点先查找 provider，再直接向其请求数据。
+//!
+//! #### 实现属性
+//! - <font color=Green>√</font> 多线程安全
+//! - <font color=Green>√</font> 无 unsafe 代码
+//!
+
+use super::super::peer::NodeId;
+use std::collections::HashMap;
+
+///provider 记录的默认存活时间，单位毫秒
+pub const PROVIDER_TTL: u64 = 24 * 3600 * 1000;
+
+///provider 重新发布记录的默认间隔，单位毫秒
+pub const REPUBLISH_INTERVAL: u64 = 12 * 3600 * 1000;
+
+///每个 key 最多保存的 provider 记录数
+pub const MAX_PROVIDERS: usize = 20;
+
+///provider 记录
+///- @key: 内容的 key
+///- @provider: 可提供内容的节点
+///- @expire: 过期时间
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ProviderRecord<const N: usize = 32> {
+    pub key: NodeId<N>,
+    pub provider: NodeId<N>,
+    pub expire: u64,
+}
+
+///provider 记录的存储
+pub trait RecordStore<const N: usize> {
+    ///#### 加入一条记录，已存在时延长其过期时间
+    ///- 该 key 已过期的记录不计入上限，先行清除
+    ///- #: 该 key 未过期的记录数已达上限时拒绝并返回 false
+    ///- @rec[in]: 记录
+    ///- @now[in]: 当前时间
+    fn add_provider(&mut self, rec: ProviderRecord<N>, now: u64) -> bool;
+
+    ///- #: key 未过期的全部记录
+    fn providers(&self, key: &NodeId<N>, now: u64) -> Vec<ProviderRecord<N>>;
+
+    ///- #: 记录存在时返回 true
+    fn remove_provider(&mut self, key: &NodeId<N>, provider: &NodeId<N>) -> bool;
+
+    ///#### 清除过期的记录
+    ///- #: 清除的记录数
+    fn expire(&mut self, now: u64) -> usize;
+
+    ///- #: 记录总数，含尚未清除的过期记录
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        0 == self.len()
+    }
+}
+
+//- @max_per_key: 每个 key 最多保存的记录数
+//- @records: 每个 key 的记录
+#[derive(Clone, Debug)]
+pub struct MemoryStore<const N: usize = 32> {
+    max_per_key: usize,
+    records: HashMap<NodeId<N>, Vec<ProviderRecord<N>>>,
+}
+
+impl<const N: usize> Default for MemoryStore<N> {
+    fn default() -> MemoryStore<N> {
+        MemoryStore::new(MAX_PROVIDERS)
+    }
+}
+
+impl<const N: usize> MemoryStore<N> {
+    ///- @max_per_key[in]: 每个 key 最多保存的记录数
+    pub fn new(max_per_key: usize) -> MemoryStore<N> {
+        MemoryStore {
+            max_per_key,
+            records: HashMap::new(),
+        }
+    }
+}
+
+impl<const N: usize> RecordStore<N> for MemoryStore<N> {
+    fn add_provider(&mut self, rec: ProviderRecord<N>, now: u64) -> bool {
+        let max = self.max_per_key;
+        let v = self.records.entry(rec.key).or_default();
+        v.retain(|r| now < r.expire);
+        if let Some(r) = v.iter_mut().find(|r| r.provider == rec.provider) {
+            r.expire = r.expire.max(rec.expire);
+            true
+        } else if v.len() < max {
+            v.push(rec);
+            true
+        } else {
+            false
+        }
+    }
+
+    fn providers(&self, key: &NodeId<N>, now: u64) -> Vec<ProviderRecord<N>> {
+        self.records.get(key).map_or(vec![], |v| {
+            v.iter().filter(|r| now < r.expire).cloned().collect()
+        })
+    }
+
+    fn remove_provider(&mut self, key: &NodeId<N>, provider: &NodeId<N>) -> bool {
+        let v = match self.records.get_mut(key) {
+            Some(v) => v,
+            None => return false,
+        };
+        let len = v.len();
+        v.retain(|r| &r.provider != provider);
+        let res = len != v.len();
+        if v.is_empty() {
+            self.records.remove(key);
+        }
+        res
+    }
+
+    fn expire(&mut self, now: u64) -> usize {
+        let mut cnt = 0;
+        self.records.retain(|_, v| {
+            let len = v.len();
+            v.retain(|r| now < r.expire);
+            cnt += len - v.len();
+            !v.is_empty()
+        });
+        cnt
+    }
+
+    fn len(&self) -> usize {
+        self.records.values().map(|v| v.len()).sum()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn id(n: u16) -> NodeId<2> {
+        NodeId::new(n.to_be_bytes())
+    }
+
+    #[test]
+    fn memory_store() {
+        let mut st = MemoryStore::new(2);
+        assert!(st.is_empty());
+        let rec = |p, expire| ProviderRecord {
+            key: id(1),
+            provider: id(p),
+            expire,
+        };
+        assert!(st.add_provider(rec(10, 100), 0));
+        assert!(st.add_provider(rec(11, 50), 0));
+        assert!(!st.add_provider(rec(12, 100), 0));
+
+        //re-adding extends the expiry
+        assert!(st.add_provider(rec(11, 200), 0));
+        assert!(st.add_provider(rec(11, 150), 0));
+        assert_eq!(vec![rec(10, 100), rec(11, 200)], st.providers(&id(1), 0));
+        assert_eq!(vec![rec(11, 200)], st.providers(&id(1), 100));
+        assert!(st.providers(&id(2), 0).is_empty());
+
+        assert_eq!(1, st.expire(100));
+        assert_eq!(1, st.len());
+        assert!(st.add_provider(rec(12, 300), 100));
+        assert!(st.remove_provider(&id(1), &id(11)));
+        assert!(!st.remove_provider(&id(1), &id(11)));
+        assert!(!st.remove_provider(&id(2), &id(12)));
+        assert_eq!(1, st.expire(300));
+        assert!(st.is_empty());
+
+        //a key full of stale records still accepts fresh providers without expire()
+        assert!(st.add_provider(rec(10, 400), 300));
+        assert!(st.add_provider(rec(11, 400), 300));
+        assert!(!st.add_provider(rec(12, 500), 399));
+        assert!(st.add_provider(rec(12, 500), 400));
+        assert!(st.add_provider(rec(13, 500), 400));
+        assert_eq!(vec![rec(12, 500), rec(13, 500)], st.providers(&id(1), 400));
+        assert_eq!(2, st.len());
+    }
+}
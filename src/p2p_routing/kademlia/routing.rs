@@ -0,0 +1,390 @@
+//! ## kademlia 路由表
+//!
+//! #### 算法说明
+//! - 初始只有一个覆盖整个 Id 空间的 bucket；bucket 已满时，若其区间包含本节点 Id(即最后一个 bucket)则一分为二，
+//!   否则不再分裂，于是第 i 个 bucket 恰好保存与本节点距离的前导零为 i 的节点，最后一个 bucket 保存其余更近的节点；
+//! - bucket 内按最近一次通信的时间排序，队首为最久未通信的节点(LRU)；
+//! - 已满且不可分裂的 bucket 遇到新节点时，不直接驱逐旧节点(在线越久的节点越可能继续在线)，
+//!   而是将新节点放入 replacement cache，并要求调用方 ping 队首节点：ping 通则将其移到队尾，
+//!   ping 不通则将其驱逐，并从 replacement cache 中提升最近见到的节点；
+//! - 每个 bucket 记录最近一次刷新的时间，长时间未刷新的 bucket 需要对其区间内的随机 Id 执行一次查找；
+//! - 不涉及网络与系统时钟：时间由调用方以参数传入，全部行为都是确定性的。
+//!
+//! #### 实现属性
+//! - <font color=Green>√</font> 多线程安全
+//! - <font color=Green>√</font> 无 unsafe 代码
+//!
+
+use super::super::peer::NodeId;
+
+///k 的默认值，即每个 bucket 最多保存的节点数
+pub const K: usize = 20;
+
+//- @id: 节点 Id
+//- @last_seen: 最近一次通信的时间
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Contact<const N: usize> {
+    pub id: NodeId<N>,
+    pub last_seen: u64,
+}
+
+//- @contacts: 在册节点，队首为最久未通信的节点
+//- @replacements: 候补节点，队尾为最近见到的节点
+//- @last_refresh: 最近一次刷新的时间
+#[derive(Clone, Debug)]
+pub struct KBucket<const N: usize> {
+    contacts: Vec<Contact<N>>,
+    replacements: Vec<Contact<N>>,
+    last_refresh: u64,
+}
+
+impl<const N: usize> KBucket<N> {
+    fn new(last_refresh: u64) -> KBucket<N> {
+        KBucket {
+            contacts: vec![],
+            replacements: vec![],
+            last_refresh,
+        }
+    }
+
+    #[inline(always)]
+    pub fn contacts(&self) -> &[Contact<N>] {
+        &self.contacts
+    }
+
+    #[inline(always)]
+    pub fn replacements(&self) -> &[Contact<N>] {
+        &self.replacements
+    }
+
+    #[inline(always)]
+    pub fn last_refresh(&self) -> u64 {
+        self.last_refresh
+    }
+
+    fn position(&self, id: &NodeId<N>) -> Option<usize> {
+        self.contacts.iter().position(|c| &c.id == id)
+    }
+}
+
+///RoutingTable::update 的结果
+///- @Update::Inserted: 新节点已加入
+///- @Update::Refreshed: 节点已在册，已移到队尾
+///- @Update::Pending: bucket 已满，新节点已放入 replacement cache，调用方须 ping 该 LRU 节点并调用 ping_result
+///- @Update::Ignored: 本节点自身的 Id
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Update<const N: usize> {
+    Inserted,
+    Refreshed,
+    Pending(NodeId<N>),
+    Ignored,
+}
+
+//- @local: 本节点 Id
+//- @k: 每个 bucket 最多保存的节点数，replacement cache 的容量与之相同
+//- @buckets: 按前导零数量升序排列，最后一个 bucket 的区间包含本节点
+#[derive(Clone, Debug)]
+pub struct RoutingTable<const N: usize = 32> {
+    local: NodeId<N>,
+    k: usize,
+    buckets: Vec<KBucket<N>>,
+}
+
+impl<const N: usize> RoutingTable<N> {
+    ///- @local[in]: 本节点 Id
+    ///- @k[in]: 每个 bucket 最多保存的节点数
+    ///- @now[in]: 当前时间，作为各 bucket 的初始刷新时间
+    pub fn new(local: NodeId<N>, k: usize, now: u64) -> RoutingTable<N> {
+        assert!(0 < k, "k must be positive");
+        RoutingTable {
+            local,
+            k,
+            buckets: vec![KBucket::new(now)],
+        }
+    }
+
+    #[inline(always)]
+    pub fn local(&self) -> &NodeId<N> {
+        &self.local
+    }
+
+    #[inline(always)]
+    pub fn k(&self) -> usize {
+        self.k
+    }
+
+    #[inline(always)]
+    pub fn buckets(&self) -> &[KBucket<N>] {
+        &self.buckets
+    }
+
+    ///- #: 在册节点总数，不含 replacement cache
+    pub fn len(&self) -> usize {
+        self.buckets.iter().map(|b| b.contacts.len()).sum()
+    }
+
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        0 == self.len()
+    }
+
+    pub fn contains(&self, id: &NodeId<N>) -> bool {
+        self.buckets[self.bucket_index(id)].position(id).is_some()
+    }
+
+    ///- #: id 所属的 bucket 序号
+    pub fn bucket_index(&self, id: &NodeId<N>) -> usize {
+        self.local
+            .distance(id)
+            .leading_zeros()
+            .min(self.buckets.len() - 1)
+    }
+
+    //将最后一个 bucket 一分为二：前导零恰为 i 的留下，更近的移入新 bucket
+    fn split(&mut self) {
+        let i = self.buckets.len() - 1;
+        let local = self.local;
+        let last = &mut self.buckets[i];
+        let mut new = KBucket::new(last.last_refresh);
+
+        let far = |c: &Contact<N>| i == local.distance(&c.id).leading_zeros();
+        let (stay, go) = last.contacts.drain(..).partition(far);
+        last.contacts = stay;
+        new.contacts = go;
+        let (stay, go) = last.replacements.drain(..).partition(far);
+        last.replacements = stay;
+        new.replacements = go;
+
+        self.buckets.push(new);
+    }
+
+    ///#### 收到某个节点的消息之后更新路由表
+    ///- @id[in]: 对端节点 Id
+    ///- @now[in]: 当前时间
+    pub fn update(&mut self, id: &NodeId<N>, now: u64) -> Update<N> {
+        if id == &self.local {
+            return Update::Ignored;
+        }
+
+        loop {
+            let idx = self.bucket_index(id);
+            let k = self.k;
+            let splittable =
+                idx == self.buckets.len() - 1 && self.buckets.len() < NodeId::<N>::BITS;
+            let bucket = &mut self.buckets[idx];
+
+            if let Some(pos) = bucket.position(id) {
+                let mut c = bucket.contacts.remove(pos);
+                c.last_seen = now;
+                bucket.contacts.push(c);
+                return Update::Refreshed;
+            }
+
+            let c = Contact {
+                id: *id,
+                last_seen: now,
+            };
+            if bucket.contacts.len() < k {
+                bucket.replacements.retain(|r| &r.id != id);
+                bucket.contacts.push(c);
+                return Update::Inserted;
+            }
+
+            if splittable {
+                self.split();
+                continue;
+            }
+
+            bucket.replacements.retain(|r| &r.id != id);
+            bucket.replacements.push(c);
+            if k < bucket.replacements.len() {
+                bucket.replacements.remove(0);
+            }
+            return Update::Pending(bucket.contacts[0].id);
+        }
+    }
+
+    ///#### 处理 Update::Pending 之后的 ping 结果
+    ///- @id[in]: 被 ping 的节点
+    ///- @alive[in]: 是否 ping 通
+    ///- @now[in]: 当前时间
+    pub fn ping_result(&mut self, id: &NodeId<N>, alive: bool, now: u64) {
+        if alive {
+            self.update(id, now);
+        } else {
+            self.remove(id);
+        }
+    }
+
+    ///#### 移除节点(如 RPC 超时)，并从 replacement cache 中提升最近见到的节点
+    ///- #: 节点在册时返回 true
+    pub fn remove(&mut self, id: &NodeId<N>) -> bool {
+        let idx = self.bucket_index(id);
+        let bucket = &mut self.buckets[idx];
+        bucket.replacements.retain(|r| &r.id != id);
+        match bucket.position(id) {
+            Some(pos) => {
+                bucket.contacts.remove(pos);
+                if let Some(r) = bucket.replacements.pop() {
+                    bucket.contacts.push(r);
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    ///#### 查找距离目标最近的若干节点
+    ///- #: 按距离升序排列
+    ///- @target[in]: 目标 Id
+    ///- @count[in]: 最多返回的数量，通常为 k
+    pub fn closest(&self, target: &NodeId<N>, count: usize) -> Vec<NodeId<N>> {
+        let mut res = self
+            .buckets
+            .iter()
+            .flat_map(|b| b.contacts.iter().map(|c| c.id))
+            .collect::<Vec<NodeId<N>>>();
+        res.sort_by_key(|id| id.distance(target));
+        res.truncate(count);
+        res
+    }
+
+    ///#### 记录一次对 target 的查找，target 所属的 bucket 视为已刷新
+    pub fn mark_refreshed(&mut self, target: &NodeId<N>, now: u64) {
+        let idx = self.bucket_index(target);
+        self.buckets[idx].last_refresh = now;
+    }
+
+    ///- #: 超过 interval 未刷新的 bucket 序号
+    pub fn stale_buckets(&self, now: u64, interval: u64) -> Vec<usize> {
+        self.buckets
+            .iter()
+            .enumerate()
+            .filter(|(_, b)| b.last_refresh + interval <= now)
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    ///#### 刷新第 idx 个 bucket 时用于查找的 Id
+    ///- #: 本节点 Id 的第 idx 个 bit 取反，必然落在该 bucket 的区间内；调用方可再随机化更低的 bit
+    pub fn refresh_target(&self, idx: usize) -> NodeId<N> {
+        self.local.flip_bit(idx)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn id(n: u16) -> NodeId<2> {
+        NodeId::new(n.to_be_bytes())
+    }
+
+    #[test]
+    fn split() {
+        let mut rt = RoutingTable::new(id(0), 2, 0);
+        assert_eq!(Update::Ignored, rt.update(&id(0), 1));
+        assert_eq!(Update::Inserted, rt.update(&id(0x8000), 1));
+        assert_eq!(Update::Inserted, rt.update(&id(0x0001), 1));
+        assert_eq!(1, rt.buckets().len());
+
+        //the only bucket contains the local id, so it splits
+        assert_eq!(Update::Inserted, rt.update(&id(0x4000), 1));
+        assert_eq!(2, rt.buckets().len());
+        assert_eq!(1, rt.bucket_index(&id(0x0001)));
+        assert_eq!(Update::Inserted, rt.update(&id(0x2000), 1));
+        assert_eq!(3, rt.buckets().len());
+        assert_eq!(0, rt.bucket_index(&id(0x8000)));
+        assert_eq!(1, rt.bucket_index(&id(0x4000)));
+        assert_eq!(2, rt.bucket_index(&id(0x2000)));
+        assert_eq!(2, rt.bucket_index(&id(0x0001)));
+        assert_eq!(4, rt.len());
+
+        //bucket 0 is full and far away: no more splitting
+        assert_eq!(Update::Inserted, rt.update(&id(0x8001), 2));
+        assert_eq!(Update::Pending(id(0x8000)), rt.update(&id(0x8002), 3));
+        assert_eq!(3, rt.buckets().len());
+        assert!(!rt.contains(&id(0x8002)));
+
+        //the deepest bucket may split down to the last bit
+        for n in 2..8 {
+            rt.update(&id(n), 4);
+        }
+        assert_eq!(16, rt.buckets().len());
+        for b in rt.buckets() {
+            assert!(b.contacts().len() <= rt.k());
+        }
+    }
+
+    #[test]
+    fn lru() {
+        let mut rt = RoutingTable::new(id(0), 2, 0);
+        rt.update(&id(0x8000), 1);
+        rt.update(&id(0x8001), 2);
+        rt.update(&id(0x0001), 3);
+        rt.update(&id(0x0002), 3);
+
+        //refreshing moves a contact to the tail
+        assert_eq!(Update::Refreshed, rt.update(&id(0x8000), 4));
+        assert_eq!(Update::Pending(id(0x8001)), rt.update(&id(0x8002), 5));
+        assert_eq!(Update::Pending(id(0x8001)), rt.update(&id(0x8003), 6));
+        assert_eq!(Update::Pending(id(0x8001)), rt.update(&id(0x8004), 7));
+        let b = &rt.buckets()[0];
+        assert_eq!(
+            vec![id(0x8003), id(0x8004)],
+            b.replacements().iter().map(|c| c.id).collect::<Vec<_>>()
+        );
+
+        //alive: the old contact stays
+        rt.ping_result(&id(0x8001), true, 8);
+        assert_eq!(Update::Pending(id(0x8000)), rt.update(&id(0x8004), 9));
+        assert_eq!(8, rt.buckets()[0].contacts()[1].last_seen);
+
+        //dead: the most recently seen replacement is promoted
+        rt.ping_result(&id(0x8000), false, 10);
+        assert!(!rt.contains(&id(0x8000)));
+        assert!(rt.contains(&id(0x8004)));
+        assert_eq!(1, rt.buckets()[0].replacements().len());
+
+        assert!(rt.remove(&id(0x8001)));
+        assert!(rt.contains(&id(0x8003)));
+        assert!(!rt.remove(&id(0x8001)));
+    }
+
+    #[test]
+    fn closest() {
+        let mut rt = RoutingTable::new(id(0x5a5a), 3, 0);
+        let mut all = vec![];
+        for n in (0..0xffffu16).step_by(97) {
+            if Update::Inserted == rt.update(&id(n), 0) {
+                all.push(id(n));
+            }
+        }
+        assert_eq!(all.len(), rt.len());
+
+        for t in &[0u16, 0x5a5b, 0xffff, 0x1234] {
+            let target = id(*t);
+            all.sort_by_key(|x| x.distance(&target));
+            assert_eq!(all[..3].to_vec(), rt.closest(&target, 3));
+            assert_eq!(all, rt.closest(&target, 1000));
+        }
+    }
+
+    #[test]
+    fn refresh() {
+        let mut rt = RoutingTable::new(id(0), 1, 100);
+        rt.update(&id(0x8000), 100);
+        rt.update(&id(0x4000), 100);
+        rt.update(&id(0x2000), 100);
+        assert_eq!(3, rt.buckets().len());
+
+        assert!(rt.stale_buckets(150, 100).is_empty());
+        for i in 0..3 {
+            assert_eq!(i, rt.bucket_index(&rt.refresh_target(i)));
+        }
+
+        rt.mark_refreshed(&id(0x4321), 180);
+        assert_eq!(vec![0, 2], rt.stale_buckets(200, 100));
+        assert_eq!(180, rt.buckets()[1].last_refresh());
+    }
+}
@@ -1,3 +1,7 @@
 pub mod coral;
 pub mod kademlia;
+pub mod peer;
 pub mod s_kademlia;
+pub mod sim;
+#[cfg(test)]
+mod testutil;
@@ -0,0 +1,324 @@
+//! ## 节点 Id 与节点地址记录
+//!
+//! #### 算法说明
+//! - NodeId：固定长度的节点 Id，两个 Id 之间的距离为按位异或(XOR)之后的无符号整数值，
+//!   kademlia、S/Kademlia 与 coral 共用；
+//! - 由 Ed25519 公钥导出的 Id 为 H(pub)，H 取 SHA-256，于是 Id 与密钥对绑定；S/Kademlia 的难题同样以 H 计算；
+//! - PeerRecord：节点对自己的 Id、地址列表与序号的签名声明，任何节点都可转发，接收方只需验证签名，
+//!   无需信任转发者；同一节点的地址变化后以更大的序号重新签名，接收方只保留序号最大的记录；
+//!   S/Kademlia 节点在签名的 PING/PONG 中交换各自的记录，并以此维护地址簿；
+//! - 编码：公钥 | 序号 | 地址数 | 地址 ... | 签名，Id 由公钥导出因而不编码；
+//!   整数一律小端，地址为 4 或 6 (IP 版本) | IP | 端口，IP 与端口按网络字节序；签名覆盖签名之前的全部字节。
+//!
+//! #### 实现属性
+//! - <font color=Green>√</font> 多线程安全
+//! - <font color=Green>√</font> 无 unsafe 代码
+//!
+
+use ring::digest::{digest, SHA256};
+use ring::signature::{self, Ed25519KeyPair, KeyPair, ED25519};
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+///Ed25519 公钥的字节数
+pub const PUBLIC_KEY_LEN: usize = 32;
+
+///Ed25519 签名的字节数
+pub const SIGNATURE_LEN: usize = 64;
+
+///PeerRecord 最多携带的地址数
+pub const MAX_ADDRS: usize = 16;
+
+///节点 Id，N 为字节数，默认 32 字节(256 bit)
+///- 按大端字节序比较大小，因此两个距离值可以直接比较远近
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct NodeId<const N: usize = 32>(pub [u8; N]);
+
+impl<const N: usize> NodeId<N> {
+    ///Id 的 bit 数
+    pub const BITS: usize = N * 8;
+
+    pub fn new(bytes: [u8; N]) -> NodeId<N> {
+        NodeId(bytes)
+    }
+
+    #[inline(always)]
+    pub fn as_bytes(&self) -> &[u8; N] {
+        &self.0
+    }
+
+    ///- #: XOR 距离
+    pub fn distance(&self, other: &NodeId<N>) -> NodeId<N> {
+        let mut res = [0; N];
+        res.iter_mut()
+            .zip(self.0.iter().zip(other.0.iter()))
+            .for_each(|(r, (a, b))| *r = a ^ b);
+        NodeId(res)
+    }
+
+    ///- #: 从最高位开始连续为 0 的 bit 数，全零时为 BITS
+    pub fn leading_zeros(&self) -> usize {
+        match self.0.iter().position(|b| 0 != *b) {
+            Some(i) => i * 8 + self.0[i].leading_zeros() as usize,
+            None => Self::BITS,
+        }
+    }
+
+    ///- #: 第 i 个 bit 是否为 1，i 从最高位开始计数
+    #[inline(always)]
+    pub fn bit(&self, i: usize) -> bool {
+        0 < self.0[i / 8] & (0x80 >> (i % 8))
+    }
+
+    ///- #: 将第 i 个 bit 取反之后的 Id，i 从最高位开始计数
+    pub fn flip_bit(&self, i: usize) -> NodeId<N> {
+        let mut res = *self;
+        res.0[i / 8] ^= 0x80 >> (i % 8);
+        res
+    }
+}
+
+impl NodeId {
+    ///- #: 任意数据的 SHA-256 哈希
+    pub fn sha256(data: &[u8]) -> NodeId {
+        let mut res = [0; 32];
+        res.copy_from_slice(digest(&SHA256, data).as_ref());
+        NodeId(res)
+    }
+
+    ///- #: 由公钥导出的节点 Id，即 H(pub)
+    #[inline(always)]
+    pub fn from_public_key(public_key: &[u8]) -> NodeId {
+        NodeId::sha256(public_key)
+    }
+}
+
+impl<const N: usize> fmt::Display for NodeId<N> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.iter().try_for_each(|b| write!(f, "{:02x}", b))
+    }
+}
+
+impl<const N: usize> fmt::Debug for NodeId<N> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+///节点地址记录
+///- @id: 节点 Id，由公钥导出
+///- @public_key: Ed25519 公钥
+///- @addrs: 节点的地址列表
+///- @seq: 序号，地址变化后递增
+///- @signature: 节点对记录的签名
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PeerRecord {
+    pub id: NodeId,
+    pub public_key: [u8; PUBLIC_KEY_LEN],
+    pub addrs: Vec<SocketAddr>,
+    pub seq: u64,
+    pub signature: [u8; SIGNATURE_LEN],
+}
+
+impl PeerRecord {
+    ///#### 以节点的密钥对签名一条记录
+    ///- @keypair[in]: 节点的密钥对
+    ///- @addrs[in]: 地址列表，至多 MAX_ADDRS 个
+    ///- @seq[in]: 序号
+    pub fn new(keypair: &Ed25519KeyPair, addrs: Vec<SocketAddr>, seq: u64) -> PeerRecord {
+        assert!(addrs.len() <= MAX_ADDRS, "too many addresses");
+        let mut public_key = [0; PUBLIC_KEY_LEN];
+        public_key.copy_from_slice(keypair.public_key().as_ref());
+        let mut res = PeerRecord {
+            id: NodeId::from_public_key(&public_key),
+            public_key,
+            addrs,
+            seq,
+            signature: [0; SIGNATURE_LEN],
+        };
+        let sig = keypair.sign(&res.signing_bytes());
+        res.signature.copy_from_slice(sig.as_ref());
+        res
+    }
+
+    //- #: 签名覆盖的字节，即编码中签名之前的部分
+    fn signing_bytes(&self) -> Vec<u8> {
+        let mut res = Vec::with_capacity(PUBLIC_KEY_LEN + 9 + self.addrs.len() * 19);
+        res.extend_from_slice(&self.public_key);
+        res.extend_from_slice(&self.seq.to_le_bytes());
+        res.push(self.addrs.len() as u8);
+        for addr in self.addrs.iter() {
+            match addr.ip() {
+                IpAddr::V4(ip) => {
+                    res.push(4);
+                    res.extend_from_slice(&ip.octets());
+                }
+                IpAddr::V6(ip) => {
+                    res.push(6);
+                    res.extend_from_slice(&ip.octets());
+                }
+            }
+            res.extend_from_slice(&addr.port().to_be_bytes());
+        }
+        res
+    }
+
+    ///#### 验证 Id 由公钥导出，且签名有效
+    pub fn verify(&self) -> bool {
+        self.addrs.len() <= MAX_ADDRS
+            && self.id == NodeId::from_public_key(&self.public_key)
+            && signature::verify(
+                &ED25519,
+                untrusted::Input::from(&self.public_key),
+                untrusted::Input::from(&self.signing_bytes()),
+                untrusted::Input::from(&self.signature),
+            )
+            .is_ok()
+    }
+
+    ///#### 是否为同一节点更新的记录，即应以本记录替换 other
+    pub fn supersedes(&self, other: &PeerRecord) -> bool {
+        self.id == other.id && other.seq < self.seq
+    }
+
+    ///#### 序列化
+    ///- #: 公钥 | 序号 | 地址数 | 地址 ... | 签名
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut res = self.signing_bytes();
+        res.extend_from_slice(&self.signature);
+        res
+    }
+
+    ///#### 反序列化，不验证签名
+    ///- #: 格式错误、存在多余字节或地址数超出上限时返回 None
+    pub fn from_bytes(bytes: &[u8]) -> Option<PeerRecord> {
+        if bytes.len() < PUBLIC_KEY_LEN + 9 + SIGNATURE_LEN {
+            return None;
+        }
+        let mut public_key = [0; PUBLIC_KEY_LEN];
+        public_key.copy_from_slice(&bytes[..PUBLIC_KEY_LEN]);
+        let mut pos = PUBLIC_KEY_LEN;
+        let mut buf = [0; 8];
+        buf.copy_from_slice(&bytes[pos..pos + 8]);
+        let seq = u64::from_le_bytes(buf);
+        let cnt = bytes[pos + 8] as usize;
+        pos += 9;
+        if MAX_ADDRS < cnt {
+            return None;
+        }
+
+        let mut addrs = Vec::with_capacity(cnt);
+        for _ in 0..cnt {
+            let ip_len = match bytes.get(pos) {
+                Some(4) => 4,
+                Some(6) => 16,
+                _ => return None,
+            };
+            let end = pos + 1 + ip_len + 2;
+            if bytes.len() < end + SIGNATURE_LEN {
+                return None;
+            }
+            let ip = &bytes[pos + 1..pos + 1 + ip_len];
+            let ip = if 4 == ip_len {
+                let mut octets = [0; 4];
+                octets.copy_from_slice(ip);
+                IpAddr::V4(Ipv4Addr::from(octets))
+            } else {
+                let mut octets = [0; 16];
+                octets.copy_from_slice(ip);
+                IpAddr::V6(Ipv6Addr::from(octets))
+            };
+            let port = u16::from_be_bytes([bytes[end - 2], bytes[end - 1]]);
+            addrs.push(SocketAddr::new(ip, port));
+            pos = end;
+        }
+
+        if bytes.len() != pos + SIGNATURE_LEN {
+            return None;
+        }
+        let mut signature = [0; SIGNATURE_LEN];
+        signature.copy_from_slice(&bytes[pos..]);
+        Some(PeerRecord {
+            id: NodeId::from_public_key(&public_key),
+            public_key,
+            addrs,
+            seq,
+            signature,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn distance() {
+        let a = NodeId::new([0b1010_0000, 0]);
+        let b = NodeId::new([0b1000_0000, 1]);
+        assert_eq!(NodeId::new([0b0010_0000, 1]), a.distance(&b));
+        assert_eq!(a.distance(&b), b.distance(&a));
+        assert_eq!(16, a.distance(&a).leading_zeros());
+        assert_eq!(2, a.distance(&b).leading_zeros());
+        assert_eq!(16, NodeId::<2>::BITS);
+
+        assert!(a.bit(0) && !a.bit(1) && a.bit(2));
+        assert_eq!(b, a.flip_bit(2).flip_bit(15));
+        assert_eq!("a000", format!("{:?}", a));
+        assert_eq!("a000", a.to_string());
+    }
+
+    #[test]
+    fn peer_record() {
+        let keypair =
+            Ed25519KeyPair::from_seed_unchecked(untrusted::Input::from(&[7; 32])).unwrap();
+        let addrs = vec![
+            "10.0.0.1:30303".parse().unwrap(),
+            "[2001:db8::1]:4001".parse().unwrap(),
+        ];
+        let rec = PeerRecord::new(&keypair, addrs, 1);
+        assert!(rec.verify());
+        assert_eq!(
+            NodeId::from_public_key(keypair.public_key().as_ref()),
+            rec.id
+        );
+
+        //round trip: 32 + 9 + (1 + 4 + 2) + (1 + 16 + 2) + 64 bytes
+        let bytes = rec.to_bytes();
+        assert_eq!(131, bytes.len());
+        let decoded = PeerRecord::from_bytes(&bytes).unwrap();
+        assert_eq!(rec, decoded);
+        assert!(decoded.verify());
+
+        //truncated, padded or with an unknown address type
+        assert!(PeerRecord::from_bytes(&bytes[..bytes.len() - 1]).is_none());
+        let mut padded = bytes.clone();
+        padded.push(0);
+        assert!(PeerRecord::from_bytes(&padded).is_none());
+        let mut bad = bytes.clone();
+        bad[41] = 5;
+        assert!(PeerRecord::from_bytes(&bad).is_none());
+
+        //any tampering breaks the signature
+        let mut forged = rec.clone();
+        forged.addrs[0] = "10.0.0.2:30303".parse().unwrap();
+        assert!(!forged.verify());
+        let mut forged = rec.clone();
+        forged.seq = 2;
+        assert!(!forged.verify());
+        let mut bytes = bytes;
+        bytes[39] ^= 1;
+        assert!(!PeerRecord::from_bytes(&bytes).unwrap().verify());
+        let mut forged = rec.clone();
+        forged.id = forged.id.flip_bit(0);
+        assert!(!forged.verify());
+
+        //a newer record from the same node replaces the old one
+        let newer = PeerRecord::new(&keypair, vec!["10.0.0.2:30303".parse().unwrap()], 2);
+        assert!(newer.verify());
+        assert!(newer.supersedes(&rec));
+        assert!(!rec.supersedes(&newer));
+        let other = Ed25519KeyPair::from_seed_unchecked(untrusted::Input::from(&[8; 32])).unwrap();
+        assert!(!PeerRecord::new(&other, vec![], 3).supersedes(&rec));
+    }
+}
@@ -1,10 +0,0 @@
-/// ## kademlia P2P routing
-/// - S/KAD 路由算法
-
-#[cfg(test)]
-mod tests {
-    #[test]
-    fn test() {
-        assert_eq!(1 + 9, 10);
-    }
-}
@@ -0,0 +1,338 @@
+//! ## S/Kademlia 不相交路径查找
+//!
+//! #### 算法说明
+//! - 初始的 k 个候选节点按距离依次轮流分配给 d 条路径，每条路径各自维护 shortlist，
+//!   按 kademlia 的方式迭代查找，同时在途的请求不超过 α 个；
+//! - 不相交：一个节点一旦被某条路径询问，就从其余路径的 shortlist 中移除，
+//!   之后任何路径返回的该节点也不再并入其余路径，于是每个节点至多被询问一次；
+//! - 恶意节点只能影响经过它的路径：只要 d 条路径中有一条全部由诚实节点组成，查找即可成功；
+//!   设恶意节点比例为 f，单条路径不经过恶意节点的概率随跳数递减，d 条路径同时失败的概率约为其 d 次方；
+//! - 全部路径结束(或取得 value)时查找结束，结果取全部路径中成功响应的节点中距离目标最近的 k 个；
+//! - 与 kademlia 的 Lookup 相同，本身是一个状态机(poll/on_response)，可由同步 Transport 或模拟器驱动。
+//!
+//! #### 实现属性
+//! - <font color=Green>√</font> 多线程安全
+//! - <font color=Green>√</font> 无 unsafe 代码
+//!
+
+use super::super::kademlia::lookup::{LookupResult, Request, Response, Transport};
+use super::super::peer::NodeId;
+use super::super::sim::Query;
+use std::collections::BTreeMap;
+
+///d 的默认值，即不相交路径的条数
+pub const D: usize = 4;
+
+//shortlist 中每个节点的状态
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Peer {
+    Waiting,
+    InFlight,
+    Succeeded,
+    Failed,
+}
+
+//- @local: 发起查找的本节点，永不进入 shortlist
+//- @target: 查找的目标
+//- @find_value: FIND_VALUE 还是 FIND_NODE
+//- @k: 每条路径的窗口大小
+//- @alpha: 每条路径同时在途的请求数上限
+//- @paths: 每条路径的 shortlist，按与目标的距离升序排列
+//- @owner: 已被询问的节点及询问它的路径
+//- @value: 已取得的 value
+//- @value_from: 返回 value 的节点
+//- @rpc_cnt: 发出的请求总数
+#[derive(Clone, Debug)]
+pub struct DisjointLookup<const N: usize = 32> {
+    local: NodeId<N>,
+    target: NodeId<N>,
+    find_value: bool,
+    k: usize,
+    alpha: usize,
+    paths: Vec<Vec<(NodeId<N>, Peer)>>,
+    owner: BTreeMap<NodeId<N>, usize>,
+    value: Option<Vec<u8>>,
+    value_from: Option<NodeId<N>>,
+    rpc_cnt: usize,
+}
+
+impl<const N: usize> DisjointLookup<N> {
+    ///- @local[in]: 本节点 Id
+    ///- @target[in]: 目标 Id，FIND_VALUE 时为 key
+    ///- @find_value[in]: FIND_VALUE 还是 FIND_NODE
+    ///- @seeds[in]: 初始候选节点，通常为本地路由表中距离目标最近的 k 个
+    ///- @k[in]: 每条路径的窗口大小
+    ///- @alpha[in]: 每条路径同时在途的请求数上限
+    ///- @d[in]: 路径条数
+    pub fn new(
+        local: NodeId<N>,
+        target: NodeId<N>,
+        find_value: bool,
+        seeds: &[NodeId<N>],
+        k: usize,
+        alpha: usize,
+        d: usize,
+    ) -> DisjointLookup<N> {
+        assert!(
+            0 < k && 0 < alpha && 0 < d,
+            "k, alpha and d must be positive"
+        );
+        let mut res = DisjointLookup {
+            local,
+            target,
+            find_value,
+            k,
+            alpha,
+            paths: vec![vec![]; d],
+            owner: BTreeMap::new(),
+            value: None,
+            value_from: None,
+            rpc_cnt: 0,
+        };
+
+        let mut seeds = seeds.to_vec();
+        seeds.sort_by_key(|id| id.distance(&target));
+        seeds.dedup();
+        for (i, id) in seeds.iter().enumerate() {
+            res.merge(i % d, &[*id]);
+        }
+        res
+    }
+
+    #[inline(always)]
+    pub fn target(&self) -> &NodeId<N> {
+        &self.target
+    }
+
+    ///- #: 路径条数
+    #[inline(always)]
+    pub fn paths(&self) -> usize {
+        self.paths.len()
+    }
+
+    //将尚未被其他路径询问过的节点按距离插入第 p 条路径
+    fn merge(&mut self, p: usize, nodes: &[NodeId<N>]) {
+        let (local, target) = (self.local, self.target);
+        for id in nodes.iter().filter(|id| **id != local) {
+            if self.owner.contains_key(id) {
+                continue;
+            }
+            let d = id.distance(&target);
+            let path = &mut self.paths[p];
+            if let Err(pos) = path.binary_search_by(|(x, _)| x.distance(&target).cmp(&d)) {
+                path.insert(pos, (*id, Peer::Waiting));
+            }
+        }
+    }
+
+    //第 p 条路径的窗口，即未失败的最近 k 个节点
+    fn window(&self, p: usize) -> impl Iterator<Item = &(NodeId<N>, Peer)> {
+        self.paths[p]
+            .iter()
+            .filter(|(_, s)| Peer::Failed != *s)
+            .take(self.k)
+    }
+
+    //第 p 条路径是否已结束
+    fn path_finished(&self, p: usize) -> bool {
+        self.window(p).all(|(_, s)| Peer::Succeeded == *s)
+    }
+
+    ///#### 查找是否已结束
+    pub fn is_finished(&self) -> bool {
+        self.value.is_some() || (0..self.paths.len()).all(|p| self.path_finished(p))
+    }
+
+    ///#### 取出全部路径的下一批请求，并将这些节点标记为在途
+    ///- #: (对端 Id, 请求)，查找已结束时为空
+    pub fn poll(&mut self) -> Vec<(NodeId<N>, Request<N>)> {
+        if self.is_finished() {
+            return vec![];
+        }
+        let req = if self.find_value {
+            Request::FindValue(self.target)
+        } else {
+            Request::FindNode(self.target)
+        };
+
+        let mut res = vec![];
+        for p in 0..self.paths.len() {
+            let in_flight = self.paths[p]
+                .iter()
+                .filter(|(_, s)| Peer::InFlight == *s)
+                .count();
+            let picked = self
+                .window(p)
+                .filter(|(_, s)| Peer::Waiting == *s)
+                .take(self.alpha.saturating_sub(in_flight))
+                .map(|(id, _)| *id)
+                .collect::<Vec<NodeId<N>>>();
+
+            for id in picked {
+                self.owner.insert(id, p);
+                for (q, path) in self.paths.iter_mut().enumerate() {
+                    if q == p {
+                        path.iter_mut()
+                            .filter(|(x, _)| *x == id)
+                            .for_each(|(_, s)| *s = Peer::InFlight);
+                    } else {
+                        path.retain(|(x, _)| *x != id);
+                    }
+                }
+                res.push((id, req.clone()));
+            }
+        }
+        self.rpc_cnt += res.len();
+        res
+    }
+
+    ///#### 处理一个在途请求的结果，新发现的节点只并入询问它的路径
+    ///- @from[in]: 对端 Id
+    ///- @resp[in]: 响应，None 表示超时
+    pub fn on_response(&mut self, from: &NodeId<N>, resp: Option<Response<N>>) {
+        let p = match self.owner.get(from) {
+            Some(p) => *p,
+            None => return,
+        };
+        let pos = match self.paths[p].iter().position(|(id, _)| id == from) {
+            Some(pos) => pos,
+            None => return,
+        };
+
+        match resp {
+            Some(Response::Nodes(nodes)) => {
+                self.paths[p][pos].1 = Peer::Succeeded;
+                self.merge(p, &nodes);
+            }
+            Some(Response::Value(v)) if self.find_value => {
+                self.paths[p][pos].1 = Peer::Succeeded;
+                self.value_from.get_or_insert(*from);
+                self.value.get_or_insert(v);
+            }
+            _ => self.paths[p][pos].1 = Peer::Failed,
+        }
+    }
+
+    ///- #: 第 p 条路径中成功响应的节点，按与目标的距离升序排列
+    pub fn path_succeeded(&self, p: usize) -> Vec<NodeId<N>> {
+        self.paths[p]
+            .iter()
+            .filter(|(_, s)| Peer::Succeeded == *s)
+            .map(|(id, _)| *id)
+            .collect()
+    }
+
+    ///#### 汇总全部路径的查找结果
+    pub fn result(&self) -> LookupResult<N> {
+        let collect = |state: Peer| {
+            let mut res = self
+                .paths
+                .iter()
+                .flat_map(|path| path.iter().filter(|(_, s)| state == *s).map(|(id, _)| *id))
+                .collect::<Vec<NodeId<N>>>();
+            res.sort_by_key(|id| id.distance(&self.target));
+            res
+        };
+
+        let succeeded = collect(Peer::Succeeded);
+        LookupResult {
+            target: self.target,
+            closest: succeeded.iter().take(self.k).cloned().collect(),
+            cache_at: self
+                .value_from
+                .and_then(|from| succeeded.iter().find(|id| **id != from).cloned()),
+            value: self.value.clone(),
+            providers: vec![],
+            failed: collect(Peer::Failed),
+            succeeded,
+            rpc_cnt: self.rpc_cnt,
+        }
+    }
+
+    ///#### 以同步的 Transport 驱动查找直至结束
+    ///- @transport[in]: 传输层
+    pub fn run<T: Transport<N>>(mut self, transport: &mut T) -> LookupResult<N> {
+        loop {
+            let reqs = self.poll();
+            if reqs.is_empty() {
+                break;
+            }
+            for (to, req) in reqs {
+                let resp = transport.call(&self.local, &to, req);
+                self.on_response(&to, resp);
+            }
+        }
+        self.result()
+    }
+}
+
+impl<const N: usize> Query<NodeId<N>, Request<N>, Response<N>> for DisjointLookup<N> {
+    fn poll(&mut self, _now: u64) -> Vec<(NodeId<N>, Request<N>)> {
+        DisjointLookup::poll(self)
+    }
+
+    fn on_response(&mut self, from: &NodeId<N>, resp: Option<Response<N>>) {
+        DisjointLookup::on_response(self, from, resp)
+    }
+
+    fn is_finished(&self) -> bool {
+        DisjointLookup::is_finished(self)
+    }
+
+    //FIND_VALUE 须取得 value，FIND_NODE 须至少有一个节点成功响应
+    fn is_success(&self) -> bool {
+        if self.find_value {
+            self.value.is_some()
+        } else {
+            self.paths
+                .iter()
+                .any(|path| path.iter().any(|(_, s)| Peer::Succeeded == *s))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::super::kademlia::lookup::{Lookup, ALPHA};
+    use super::super::super::testutil::{id, Net};
+    use super::*;
+    use std::collections::BTreeSet;
+
+    const K: usize = 8;
+
+    #[test]
+    fn disjoint() {
+        let (mut net, ids) = Net::build(128, K);
+        let target = id("target");
+        let seeds = net.nodes[&ids[1]].table().closest(&target, K);
+        let mut lookup = DisjointLookup::new(ids[1], target, false, &seeds, K, ALPHA, D);
+        assert_eq!(D, lookup.paths());
+
+        let mut asked = BTreeSet::new();
+        loop {
+            let reqs = lookup.poll();
+            if reqs.is_empty() {
+                break;
+            }
+            for (to, req) in reqs {
+                //no node is ever queried twice, not even by different paths
+                assert!(asked.insert(to));
+                let resp = net.call(&ids[1], &to, req);
+                lookup.on_response(&to, resp);
+            }
+        }
+        for p in 0..D {
+            for q in 0..p {
+                let a = lookup.path_succeeded(p);
+                assert!(lookup.path_succeeded(q).iter().all(|x| !a.contains(x)));
+            }
+        }
+
+        //without adversaries the result equals a plain lookup
+        let res = lookup.result();
+        let plain = Lookup::find_node(ids[1], target, &seeds, K, ALPHA).run(&mut net);
+        assert_eq!(plain.closest, res.closest);
+        assert_eq!(asked.len(), res.rpc_cnt);
+        assert!(plain.rpc_cnt < res.rpc_cnt);
+    }
+}
@@ -0,0 +1,22 @@
+//! ## S/Kademlia P2P routing
+//!
+//! #### 算法说明
+//! - 在 kademlia 的基础上抵御 Sybil 与 Eclipse 攻击：
+//!   节点 Id 由公钥导出，并须求解静态与动态两个密码学难题，路由表只接纳通过验证的节点；
+//! - 全部 RPC 消息都由发送方签名，并以 nonce 绑定请求与响应；
+//! - 每个节点另外维护距离自身最近的 s·k 个节点(sibling list)，数据在 s 个 siblings 上保存副本；
+//! - 查找沿 d 条互不相交的路径并行进行，只要其中一条路径不经过恶意节点即可成功。
+//!
+//! #### 应用场景
+//! - 开放网络中的 DHT，例如 IPFS 早期设计、OverSim 中的安全 DHT 实验。
+//!
+//! #### 实现属性
+//! - <font color=Green>√</font> 多线程安全
+//! - <font color=Green>√</font> 无 unsafe 代码
+//!
+
+pub mod lookup;
+pub mod node;
+pub mod puzzle;
+pub mod rpc;
+pub mod sibling;
@@ -0,0 +1,809 @@
+//! ## S/Kademlia 节点
+//!
+//! #### 算法说明
+//! - 在 kademlia 节点的基础上：收到的每个请求、每个响应都先验证签名与难题，
+//!   未通过验证的消息直接丢弃，其发送方既不会被处理也不会进入路由表与 sibling list；
+//! - 请求的 nonce 取自系统随机数，时间戳过期或在窗口内重复的请求视为重放，同样直接丢弃；
+//! - FIND_NODE、FIND_VALUE 与 GET_PROVIDERS 的响应取路由表与 sibling list 中距离目标最近的 k 个节点；
+//! - 查找使用 d 条不相交路径，初始候选节点同样取自路由表与 sibling list；
+//! - 负责某个 key 的节点写入数据之后，将 STORE 广播给 sibling list 中其余的副本节点；
+//! - 地址簿：节点以 HELLO(PING/PONG) 交换各自签名的 PeerRecord，直接收到的记录须与消息的发送方一致，
+//!   转发得来的记录须通过签名与静态难题的验证，同一节点只保留序号最大的记录；
+//!   本节点的地址变化后以更大的序号重新签名自己的记录；
+//! - SecureLookup 以本节点的身份签名查找发出的每个请求，响应通过验证之后才交给不相交路径查找，
+//!   于是查找既可由 sim 模拟器驱动(SecureNode 实现了 SimNode)，也可逐个请求手动驱动。
+//!
+//! #### 实现属性
+//! - <font color=Green>√</font> 多线程安全
+//! - <font color=Green>√</font> 无 unsafe 代码
+//!
+
+use super::super::kademlia::lookup::{LookupResult, Request, Response};
+use super::super::kademlia::node::Node;
+use super::super::peer::{NodeId, PeerRecord, MAX_ADDRS};
+use super::super::sim::{Query, SimNode};
+use super::lookup::DisjointLookup;
+use super::puzzle::{verify_static, Difficulty, Identity};
+use super::rpc::{Hello, ReplayGuard, Signed};
+use super::sibling::SiblingList;
+use ring::error::Unspecified;
+use ring::rand::{SecureRandom, SystemRandom};
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+//- @identity: 本节点身份，与本节点发起的查找共享
+//- @node: kademlia 节点，即路由表与本地存储
+//- @siblings: sibling list
+//- @difficulty: 要求对端满足的难度
+//- @replay: 收到的请求的重放检测
+//- @record: 本节点签名的地址记录
+//- @peers: 地址簿，每个节点序号最大的记录
+#[derive(Debug)]
+pub struct SecureNode {
+    identity: Arc<Identity>,
+    node: Node,
+    siblings: SiblingList,
+    difficulty: Difficulty,
+    replay: ReplayGuard,
+    record: PeerRecord,
+    peers: BTreeMap<NodeId, PeerRecord>,
+}
+
+impl SecureNode {
+    ///- @identity[in]: 本节点身份
+    ///- @k[in]: 每个 bucket 最多保存的节点数
+    ///- @s[in]: 每个 key 的副本数
+    ///- @difficulty[in]: 要求对端满足的难度
+    ///- @now[in]: 当前时间
+    pub fn new(
+        identity: Identity,
+        k: usize,
+        s: usize,
+        difficulty: Difficulty,
+        now: u64,
+    ) -> SecureNode {
+        let id = identity.id();
+        let record = PeerRecord::new(identity.keypair(), vec![], 0);
+        SecureNode {
+            identity: Arc::new(identity),
+            node: Node::new(id, k, now),
+            siblings: SiblingList::new(id, s, k),
+            difficulty,
+            replay: ReplayGuard::default(),
+            record,
+            peers: BTreeMap::new(),
+        }
+    }
+
+    #[inline(always)]
+    pub fn id(&self) -> NodeId {
+        self.identity.id()
+    }
+
+    #[inline(always)]
+    pub fn identity(&self) -> &Identity {
+        &self.identity
+    }
+
+    #[inline(always)]
+    pub fn node(&self) -> &Node {
+        &self.node
+    }
+
+    #[inline(always)]
+    pub fn siblings(&self) -> &SiblingList {
+        &self.siblings
+    }
+
+    ///- #: 本节点签名的地址记录
+    #[inline(always)]
+    pub fn record(&self) -> &PeerRecord {
+        &self.record
+    }
+
+    ///- #: 地址簿中节点的记录
+    pub fn peer(&self, id: &NodeId) -> Option<&PeerRecord> {
+        self.peers.get(id)
+    }
+
+    ///#### 地址变化后，以更大的序号重新签名本节点的记录
+    ///- #: 地址数超出 MAX_ADDRS 时返回 false，记录不变
+    ///- @addrs[in]: 新的地址列表
+    pub fn set_addrs(&mut self, addrs: Vec<SocketAddr>) -> bool {
+        if MAX_ADDRS < addrs.len() {
+            return false;
+        }
+        self.record = PeerRecord::new(self.identity.keypair(), addrs, self.record.seq + 1);
+        true
+    }
+
+    ///#### 将一条记录并入地址簿，记录可以来自第三方的转发
+    ///- #: 签名无效或 Id 未通过静态难题时返回 false；有效但不比已有记录新时保留已有记录
+    ///- @rec[in]: 地址记录
+    pub fn learn(&mut self, rec: &PeerRecord) -> bool {
+        if !rec.verify() || !verify_static(&rec.id, self.difficulty.c1) {
+            return false;
+        }
+        if self
+            .peers
+            .get(&rec.id)
+            .is_none_or(|old| rec.supersedes(old))
+        {
+            self.peers.insert(rec.id, rec.clone());
+        }
+        true
+    }
+
+    //记录一个已通过验证的节点
+    fn see(&mut self, id: &NodeId, now: u64) {
+        self.node.table_mut().update(id, now);
+        self.siblings.insert(id);
+    }
+
+    //- #: 路由表与 sibling list 中距离目标最近的 k 个节点
+    fn closest(&self, target: &NodeId) -> Vec<NodeId> {
+        let k = self.node.table().k();
+        let mut res = self.node.table().closest(target, k);
+        res.extend(self.siblings.closest(target, k));
+        res.sort_by_key(|id| id.distance(target));
+        res.dedup();
+        res.truncate(k);
+        res
+    }
+
+    fn nonce() -> Result<u64, Unspecified> {
+        let mut nonce = [0; 8];
+        SystemRandom::new().fill(&mut nonce)?;
+        Ok(u64::from_le_bytes(nonce))
+    }
+
+    ///#### 以随机的 nonce 签名一个请求
+    ///- @body[in]: 请求内容
+    ///- @now[in]: 当前时间
+    pub fn request(&self, body: Request<32>, now: u64) -> Result<Signed<Request<32>>, Unspecified> {
+        Ok(Signed::new(&self.identity, SecureNode::nonce()?, now, body))
+    }
+
+    ///#### 以随机的 nonce 签名一个携带本节点记录的 PING
+    ///- @now[in]: 当前时间
+    pub fn hello(&self, now: u64) -> Result<Signed<Hello>, Unspecified> {
+        Ok(Signed::new(
+            &self.identity,
+            SecureNode::nonce()?,
+            now,
+            Hello::Ping(self.record.clone()),
+        ))
+    }
+
+    ///#### 处理收到的 PING，记下对端的记录并以本节点的记录回应
+    ///- #: 签名后的 PONG，消息未通过验证、被判定为重放或记录不属于发送方时为 None
+    ///- @msg[in]: PING
+    ///- @now[in]: 当前时间
+    pub fn on_hello(&mut self, msg: &Signed<Hello>, now: u64) -> Option<Signed<Hello>> {
+        let from = msg.sender_id();
+        match &msg.body {
+            Hello::Ping(rec) if rec.id == from => {}
+            _ => return None,
+        }
+        if !msg.verify(self.difficulty)
+            || !self.replay.check(&from, msg.nonce, msg.time, now)
+            || !self.learn(msg.body.record())
+        {
+            return None;
+        }
+        self.see(&from, now);
+        Some(Signed::new(
+            &self.identity,
+            msg.nonce,
+            now,
+            Hello::Pong(self.record.clone()),
+        ))
+    }
+
+    ///#### 验证对 PING 的回应，通过后记下对端的记录，并将对端记入路由表与 sibling list
+    ///- #: 是否通过验证
+    ///- @to[in]: 被 PING 的节点
+    ///- @nonce[in]: PING 的 nonce
+    ///- @resp[in]: PONG
+    ///- @now[in]: 当前时间
+    pub fn accept_hello(&mut self, to: &NodeId, nonce: u64, resp: Signed<Hello>, now: u64) -> bool {
+        match resp.open(to, nonce, self.difficulty) {
+            Some(Hello::Pong(rec)) if rec.id == *to && self.learn(&rec) => {
+                self.see(to, now);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    ///#### 处理收到的请求
+    ///- #: 签名后的响应，请求未通过验证或被判定为重放时为 None
+    ///- @req[in]: 请求
+    ///- @now[in]: 当前时间
+    pub fn handle(&mut self, req: &Signed<Request<32>>, now: u64) -> Option<Signed<Response<32>>> {
+        if !req.verify(self.difficulty) {
+            return None;
+        }
+        let from = req.sender_id();
+        if !self.replay.check(&from, req.nonce, req.time, now) {
+            return None;
+        }
+        self.siblings.insert(&from);
+        let resp = match (self.node.handle(&from, req.body.clone(), now), &req.body) {
+            (Response::Nodes(_), Request::FindNode(target))
+            | (Response::Nodes(_), Request::FindValue(target)) => {
+                Response::Nodes(self.closest(target))
+            }
+            (Response::Providers(providers, _), Request::GetProviders(key)) => {
+                Response::Providers(providers, self.closest(key))
+            }
+            (resp, _) => resp,
+        };
+        Some(Signed::new(&self.identity, req.nonce, now, resp))
+    }
+
+    ///#### 验证对某个请求的响应，通过后将对端记入路由表与 sibling list
+    ///- #: 通过验证的响应内容
+    ///- @to[in]: 被请求的节点
+    ///- @nonce[in]: 请求的 nonce
+    ///- @resp[in]: 响应
+    ///- @now[in]: 当前时间
+    pub fn accept(
+        &mut self,
+        to: &NodeId,
+        nonce: u64,
+        resp: Signed<Response<32>>,
+        now: u64,
+    ) -> Option<Response<32>> {
+        let body = resp.open(to, nonce, self.difficulty)?;
+        self.see(to, now);
+        Some(body)
+    }
+
+    //- #: 以本节点的身份签名请求的查找
+    fn lookup(
+        &self,
+        target: NodeId,
+        find_value: bool,
+        alpha: usize,
+        d: usize,
+    ) -> Result<SecureLookup, Unspecified> {
+        let k = self.node.table().k();
+        Ok(SecureLookup {
+            identity: self.identity.clone(),
+            difficulty: self.difficulty,
+            nonce: SecureNode::nonce()?,
+            sent: BTreeMap::new(),
+            lookup: DisjointLookup::new(
+                self.id(),
+                target,
+                find_value,
+                &self.closest(&target),
+                k,
+                alpha,
+                d,
+            ),
+        })
+    }
+
+    ///#### 构造一次 d 条路径的 FIND_NODE
+    pub fn find_node(
+        &self,
+        target: NodeId,
+        alpha: usize,
+        d: usize,
+    ) -> Result<SecureLookup, Unspecified> {
+        self.lookup(target, false, alpha, d)
+    }
+
+    ///#### 构造一次 d 条路径的 FIND_VALUE
+    ///- #: 本地已有时为 Ok(None)
+    pub fn find_value(
+        &self,
+        key: NodeId,
+        alpha: usize,
+        d: usize,
+    ) -> Result<Option<SecureLookup>, Unspecified> {
+        if self.node.value(&key).is_some() {
+            return Ok(None);
+        }
+        self.lookup(key, true, alpha, d).map(Some)
+    }
+
+    ///#### 用查找结果更新路由表与 sibling list
+    pub fn apply(&mut self, res: &LookupResult<32>, now: u64) {
+        self.node.apply(res, now);
+        res.succeeded.iter().for_each(|id| {
+            self.siblings.insert(id);
+        });
+        res.failed.iter().for_each(|id| {
+            self.siblings.remove(id);
+        });
+    }
+
+    ///#### 本节点负责 key 时，写入数据之后应将 STORE 广播给的其余副本节点
+    ///- #: 本节点不负责 key 时为空
+    pub fn broadcast(&self, key: &NodeId) -> Vec<NodeId> {
+        if self.node.value(key).is_some() && self.siblings.is_responsible(key) {
+            self.siblings.broadcast(key)
+        } else {
+            vec![]
+        }
+    }
+}
+
+impl SimNode for SecureNode {
+    type Id = NodeId;
+    type Req = Signed<Request<32>>;
+    type Resp = Option<Signed<Response<32>>>;
+    type Query = SecureLookup;
+
+    fn id(&self) -> NodeId {
+        SecureNode::id(self)
+    }
+
+    //请求方取自请求的签名，而不是网络层给出的地址；未通过验证的请求得到空响应
+    fn handle(
+        &mut self,
+        _from: &NodeId,
+        req: Signed<Request<32>>,
+        now: u64,
+    ) -> Option<Signed<Response<32>>> {
+        SecureNode::handle(self, &req, now)
+    }
+
+    fn on_finished(&mut self, query: &SecureLookup, now: u64) {
+        self.apply(&query.result(), now);
+    }
+}
+
+///以签名的 RPC 驱动的不相交路径查找
+//- @identity: 发起节点的身份，用于签名请求
+//- @difficulty: 要求对端满足的难度
+//- @nonce: 上一个请求的 nonce，自随机值开始递增
+//- @sent: 在途请求的 nonce
+//- @lookup: 不相交路径查找
+#[derive(Clone, Debug)]
+pub struct SecureLookup {
+    identity: Arc<Identity>,
+    difficulty: Difficulty,
+    nonce: u64,
+    sent: BTreeMap<NodeId, u64>,
+    lookup: DisjointLookup,
+}
+
+impl SecureLookup {
+    #[inline(always)]
+    pub fn lookup(&self) -> &DisjointLookup {
+        &self.lookup
+    }
+
+    #[inline(always)]
+    pub fn is_finished(&self) -> bool {
+        self.lookup.is_finished()
+    }
+
+    ///#### 取出下一批请求，以发出时的时间签名，于是耗时超过 REPLAY_WINDOW 的查找也不会被对端视为重放
+    ///- #: (对端 Id, 签名后的请求)
+    ///- @now[in]: 当前时间
+    pub fn poll(&mut self, now: u64) -> Vec<(NodeId, Signed<Request<32>>)> {
+        self.lookup
+            .poll()
+            .into_iter()
+            .map(|(to, body)| {
+                self.nonce = self.nonce.wrapping_add(1);
+                self.sent.insert(to, self.nonce);
+                (to, Signed::new(&self.identity, self.nonce, now, body))
+            })
+            .collect()
+    }
+
+    ///#### 验证响应，未通过验证的响应与超时同样视为失败
+    ///- @from[in]: 对端 Id
+    ///- @resp[in]: 响应，外层的 None 表示超时，内层的 None 表示对端丢弃了请求
+    pub fn on_response(&mut self, from: &NodeId, resp: Option<Option<Signed<Response<32>>>>) {
+        let body = match (self.sent.remove(from), resp) {
+            (Some(nonce), Some(Some(resp))) => resp.open(from, nonce, self.difficulty),
+            _ => None,
+        };
+        self.lookup.on_response(from, body);
+    }
+
+    ///#### 汇总查找结果
+    pub fn result(&self) -> LookupResult<32> {
+        self.lookup.result()
+    }
+}
+
+impl Query<NodeId, Signed<Request<32>>, Option<Signed<Response<32>>>> for SecureLookup {
+    fn poll(&mut self, now: u64) -> Vec<(NodeId, Signed<Request<32>>)> {
+        SecureLookup::poll(self, now)
+    }
+
+    fn on_response(&mut self, from: &NodeId, resp: Option<Option<Signed<Response<32>>>>) {
+        SecureLookup::on_response(self, from, resp)
+    }
+
+    fn is_finished(&self) -> bool {
+        SecureLookup::is_finished(self)
+    }
+
+    fn is_success(&self) -> bool {
+        Query::is_success(&self.lookup)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::super::kademlia::lookup::ALPHA;
+    use super::super::super::sim::{Config, Simulator};
+    use super::super::super::testutil::id;
+    use super::super::lookup::D;
+    use super::*;
+
+    const K: usize = 4;
+    const S: usize = 3;
+    const DIFFICULTY: Difficulty = Difficulty { c1: 3, c2: 3 };
+
+    //恶意节点相互勾结：请求照常验证、响应照常签名，但只返回距离目标最近的恶意节点，从不返回 value
+    //- @node: 节点
+    //- @colluding: 全部恶意节点，诚实节点为空
+    struct Peer {
+        node: SecureNode,
+        colluding: Vec<NodeId>,
+    }
+
+    impl SimNode for Peer {
+        type Id = NodeId;
+        type Req = Signed<Request<32>>;
+        type Resp = Option<Signed<Response<32>>>;
+        type Query = SecureLookup;
+
+        fn id(&self) -> NodeId {
+            self.node.id()
+        }
+
+        fn handle(
+            &mut self,
+            from: &NodeId,
+            req: Signed<Request<32>>,
+            now: u64,
+        ) -> Option<Signed<Response<32>>> {
+            let (nonce, body) = (req.nonce, req.body.clone());
+            let resp = SimNode::handle(&mut self.node, from, req, now)?;
+            if self.colluding.is_empty() {
+                return Some(resp);
+            }
+            Some(match body {
+                Request::FindNode(target)
+                | Request::FindValue(target)
+                | Request::GetProviders(target) => {
+                    let mut res = self.colluding.clone();
+                    res.sort_by_key(|x| x.distance(&target));
+                    res.truncate(self.node.node().table().k());
+                    Signed::new(self.node.identity(), nonce, now, Response::Nodes(res))
+                }
+                _ => resp,
+            })
+        }
+
+        fn on_finished(&mut self, query: &SecureLookup, now: u64) {
+            self.node.on_finished(query, now);
+        }
+    }
+
+    fn identity(i: u8, difficulty: Difficulty) -> Identity {
+        Identity::from_seed([i; 32], difficulty).unwrap()
+    }
+
+    fn peer(i: u8, k: usize, difficulty: Difficulty) -> Peer {
+        Peer {
+            node: SecureNode::new(identity(i, difficulty), k, S, DIFFICULTY, 0),
+            colluding: vec![],
+        }
+    }
+
+    //经由 boot 加入网络：以 HELLO 取得第一个联系人，再查找自身 Id
+    fn join(sim: &mut Simulator<Peer>, peer: Peer, boot: &NodeId) -> NodeId {
+        let (id, now) = (peer.id(), sim.now());
+        sim.add_node(peer);
+        let ping = sim.node(&id).unwrap().node.hello(now).unwrap();
+        if let Some(pong) = sim.node_mut(boot).unwrap().node.on_hello(&ping, now) {
+            let node = &mut sim.node_mut(&id).unwrap().node;
+            assert!(node.accept_hello(boot, ping.nonce, pong, now));
+        }
+        let lookup = sim.node(&id).unwrap().node.find_node(id, ALPHA, D);
+        sim.start(id, lookup.unwrap());
+        sim.run();
+        id
+    }
+
+    //在模拟器之外直接发送一个请求
+    fn call(
+        sim: &mut Simulator<Peer>,
+        from: &NodeId,
+        to: &NodeId,
+        body: Request<32>,
+    ) -> Option<Response<32>> {
+        let now = sim.now();
+        let req = sim.node(from)?.node.request(body, now).ok()?;
+        let nonce = req.nonce;
+        let resp = sim.node_mut(to)?.handle(from, req, now)?;
+        sim.node_mut(from)?.node.accept(to, nonce, resp, now)
+    }
+
+    //- #: 在模拟器中执行一次查找的结果
+    fn run(sim: &mut Simulator<Peer>, from: &NodeId, lookup: SecureLookup) -> LookupResult<32> {
+        sim.start(*from, lookup);
+        sim.run();
+        sim.take_finished().pop().unwrap().query.result()
+    }
+
+    #[test]
+    fn secure_node() {
+        let mut sim = Simulator::new(Config::default());
+        let first = peer(0, K, DIFFICULTY);
+        let boot = first.id();
+        sim.add_node(first);
+        let ids = (1..40)
+            .map(|i| join(&mut sim, peer(i, K, DIFFICULTY), &boot))
+            .collect::<Vec<_>>();
+        for p in sim.nodes() {
+            assert!(K <= p.node.node().table().len());
+            assert!(!p.node.siblings().is_empty());
+        }
+        let m = sim.metrics();
+        assert_eq!(1.0, m.success_rate());
+        assert_eq!(0, m.timeouts);
+
+        //a sybil with an unsolved puzzle is never admitted
+        let sybil = peer(99, K, Difficulty { c1: 0, c2: 0 });
+        assert!(!sybil.node.identity().proof().verify(DIFFICULTY));
+        let sybil = join(&mut sim, sybil, &boot);
+        assert_eq!(0, sim.node(&sybil).unwrap().node.node().table().len());
+        assert!(sim.nodes().all(|p| {
+            !p.node.node().table().contains(&sybil) && !p.node.siblings().contains(&sybil)
+        }));
+
+        //store on the closest node, which broadcasts to its siblings
+        let key = NodeId::new([0x5a; 32]);
+        let lookup = sim.node(&ids[0]).unwrap().node.find_node(key, ALPHA, D);
+        let res = run(&mut sim, &ids[0], lookup.unwrap());
+        let primary = res.closest[0];
+        assert_eq!(
+            Some(Response::Stored),
+            call(
+                &mut sim,
+                &ids[0],
+                &primary,
+                Request::Store(key, b"value".to_vec())
+            )
+        );
+        let replicas = sim.node(&primary).unwrap().node.broadcast(&key);
+        assert_eq!(S - 1, replicas.len());
+        for r in replicas.iter() {
+            call(
+                &mut sim,
+                &primary,
+                r,
+                Request::Store(key, b"value".to_vec()),
+            );
+        }
+        let mut holders = sim
+            .nodes()
+            .filter(|p| p.node.node().value(&key).is_some())
+            .map(|p| p.id())
+            .collect::<Vec<_>>();
+        holders.sort_by_key(|id| id.distance(&key));
+        let mut want = sim
+            .nodes()
+            .map(|p| p.id())
+            .filter(|id| *id != sybil)
+            .collect::<Vec<_>>();
+        want.sort_by_key(|id| id.distance(&key));
+        assert_eq!(want[..S].to_vec(), holders);
+
+        let from = ids.iter().find(|id| !holders.contains(id)).unwrap();
+        let lookup = sim.node(from).unwrap().node.find_value(key, ALPHA, D);
+        let found = run(&mut sim, from, lookup.unwrap().unwrap());
+        assert_eq!(Some(b"value".to_vec()), found.value);
+        let holder = &sim.node(&holders[0]).unwrap().node;
+        assert!(holder.find_value(key, ALPHA, D).unwrap().is_none());
+        assert!(sim
+            .node(&holders[1])
+            .unwrap()
+            .node
+            .broadcast(&NodeId::new([0xa5; 32]))
+            .is_empty());
+    }
+
+    #[test]
+    fn slow_lookup() {
+        use super::super::rpc::REPLAY_WINDOW;
+
+        let mut sim = Simulator::new(Config::default());
+        let first = peer(0, K, DIFFICULTY);
+        let boot = first.id();
+        sim.add_node(first);
+        for i in 1..20 {
+            join(&mut sim, peer(i, K, DIFFICULTY), &boot);
+        }
+
+        //every round trip takes a whole replay window
+        let mut lookup = sim
+            .node(&boot)
+            .unwrap()
+            .node
+            .find_node(id("far"), 1, 1)
+            .unwrap();
+        let mut now = sim.now();
+        let mut rounds = 0;
+        loop {
+            let reqs = lookup.poll(now);
+            if reqs.is_empty() {
+                break;
+            }
+            now += REPLAY_WINDOW;
+            for (to, req) in reqs {
+                let resp = sim.node_mut(&to).unwrap().handle(&boot, req, now);
+                lookup.on_response(&to, Some(resp));
+            }
+            rounds += 1;
+        }
+        assert!(2 < rounds, "{}", rounds);
+        let res = lookup.result();
+        assert!(res.failed.is_empty());
+        assert_eq!(K, res.closest.len());
+    }
+
+    //- #: 距离 target 最近的诚实节点，不含 local
+    fn closest_honest(sim: &Simulator<Peer>, local: &NodeId, target: &NodeId) -> NodeId {
+        sim.nodes()
+            .filter(|p| p.colluding.is_empty() && p.id() != *local)
+            .map(|p| p.id())
+            .min_by_key(|x| x.distance(target))
+            .unwrap()
+    }
+
+    //以 d 条路径并发查找 cnt 次
+    //- #: 成功的比例，成功即找到了距离目标最近的诚实节点
+    fn success_rate(sim: &mut Simulator<Peer>, honest: &[NodeId], d: usize, cnt: usize) -> f64 {
+        for i in 0..cnt {
+            let local = honest[(i * 7) % honest.len()];
+            let target = id(&format!("target{}", i));
+            let lookup = sim.node(&local).unwrap().node.find_node(target, ALPHA, d);
+            sim.start(local, lookup.unwrap());
+        }
+        sim.run();
+        let ok = sim
+            .take_finished()
+            .iter()
+            .filter(|f| {
+                let target = f.query.lookup().target();
+                let want = closest_honest(sim, &f.origin, target);
+                f.query.result().closest.contains(&want)
+            })
+            .count();
+        ok as f64 / cnt as f64
+    }
+
+    #[test]
+    fn adversarial() {
+        const K: usize = 8;
+        let mut sim = Simulator::new(Config::default());
+        let first = peer(0, K, DIFFICULTY);
+        let boot = first.id();
+        sim.add_node(first);
+        let mut ids = vec![boot];
+        ids.extend((1..=255).map(|i| join(&mut sim, peer(i, K, DIFFICULTY), &boot)));
+        sim.take_finished();
+
+        //20% of the nodes collude
+        let adversaries = ids.iter().skip(1).step_by(5).cloned().collect::<Vec<_>>();
+        for a in adversaries.iter() {
+            sim.node_mut(a).unwrap().colluding = adversaries.clone();
+        }
+        let mut honest = ids.clone();
+        honest.retain(|x| !adversaries.contains(x));
+
+        let single = success_rate(&mut sim, &honest, 1, 100);
+        let disjoint = success_rate(&mut sim, &honest, D, 100);
+        assert!(single < disjoint, "{} {}", single, disjoint);
+        assert!(0.9 <= disjoint, "{}", disjoint);
+
+        //the value is never returned by an adversary, but an honest path still finds it
+        let key = id("key");
+        let holder = closest_honest(&sim, &ids[0], &key);
+        let store = Request::Store(key, b"value".to_vec());
+        assert_eq!(
+            Some(Response::Stored),
+            call(&mut sim, &ids[0], &holder, store)
+        );
+        let local = honest[honest.len() - 1];
+        let lookup = sim.node(&local).unwrap().node.find_value(key, ALPHA, D);
+        let res = run(&mut sim, &local, lookup.unwrap().unwrap());
+        assert_eq!(Some(b"value".to_vec()), res.value);
+    }
+
+    #[test]
+    fn replay() {
+        use super::super::rpc::REPLAY_WINDOW;
+
+        let alice = SecureNode::new(identity(1, DIFFICULTY), K, S, DIFFICULTY, 0);
+        let mut bob = SecureNode::new(identity(2, DIFFICULTY), K, S, DIFFICULTY, 0);
+        let key = NodeId::new([0x5a; 32]);
+
+        //a captured store is served once
+        let store = alice
+            .request(Request::Store(key, b"value".to_vec()), 1000)
+            .unwrap();
+        assert!(bob.handle(&store, 1000).is_some());
+        assert!(bob.handle(&store, 1001).is_none());
+
+        //a captured provider announcement cannot outlive the provider
+        let add = alice.request(Request::AddProvider(key), 1000).unwrap();
+        assert!(bob.handle(&add, 1000).is_some());
+        let later = 1000 + REPLAY_WINDOW + 1;
+        assert!(bob.handle(&add, later).is_none());
+        assert!(bob.handle(&store, later).is_none());
+
+        //a restarted node does not reuse nonces
+        let restarted = SecureNode::new(identity(1, DIFFICULTY), K, S, DIFFICULTY, 0);
+        let (a, b) = (
+            alice.request(Request::Ping, later).unwrap(),
+            restarted.request(Request::Ping, later).unwrap(),
+        );
+        assert_eq!(a.sender_id(), b.sender_id());
+        assert_ne!(a.nonce, b.nonce);
+        assert!(bob.handle(&a, later).is_some());
+        assert!(bob.handle(&b, later).is_some());
+    }
+
+    #[test]
+    fn hello() {
+        let mut alice = SecureNode::new(identity(1, DIFFICULTY), K, S, DIFFICULTY, 0);
+        let mut bob = SecureNode::new(identity(2, DIFFICULTY), K, S, DIFFICULTY, 0);
+        let mut carol = SecureNode::new(identity(3, DIFFICULTY), K, S, DIFFICULTY, 0);
+        assert!(alice.set_addrs(vec!["10.0.0.1:4001".parse().unwrap()]));
+        assert_eq!(1, alice.record().seq);
+
+        //both sides learn each other's record and contact
+        let ping = alice.hello(10).unwrap();
+        let pong = bob.on_hello(&ping, 10).unwrap();
+        assert!(alice.accept_hello(&bob.id(), ping.nonce, pong.clone(), 10));
+        assert_eq!(Some(alice.record()), bob.peer(&alice.id()));
+        assert_eq!(Some(bob.record()), alice.peer(&bob.id()));
+        assert!(bob.node().table().contains(&alice.id()));
+        assert!(alice.node().table().contains(&bob.id()));
+
+        //a replayed ping, a pong for another nonce or from someone else is rejected
+        assert!(bob.on_hello(&ping, 11).is_none());
+        assert!(!alice.accept_hello(&bob.id(), ping.nonce + 1, pong.clone(), 11));
+        assert!(!alice.accept_hello(&carol.id(), ping.nonce, pong, 11));
+
+        //a record can be forwarded, only newer ones replace the old
+        let old = alice.record().clone();
+        assert!(alice.set_addrs(vec!["10.0.0.2:4001".parse().unwrap()]));
+        assert!(carol.learn(alice.record()));
+        assert!(carol.learn(&old));
+        assert_eq!(Some(alice.record()), carol.peer(&alice.id()));
+        let ping = alice.hello(20).unwrap();
+        bob.on_hello(&ping, 20).unwrap();
+        assert_eq!(2, bob.peer(&alice.id()).unwrap().seq);
+
+        //a forged record, or one whose id fails the static puzzle
+        let mut forged = alice.record().clone();
+        forged.seq = 9;
+        assert!(!carol.learn(&forged));
+        let sybil = identity(99, Difficulty { c1: 0, c2: 0 });
+        assert!(!carol.learn(&PeerRecord::new(sybil.keypair(), vec![], 1)));
+        assert!(!alice.set_addrs(vec!["10.0.0.1:4001".parse().unwrap(); MAX_ADDRS + 1]));
+        assert_eq!(2, alice.record().seq);
+
+        //a ping carrying someone else's record is dropped
+        let relay = Signed::new(bob.identity(), 7, 30, Hello::Ping(alice.record().clone()));
+        assert!(carol.on_hello(&relay, 30).is_none());
+        assert!(carol.peer(&bob.id()).is_none());
+    }
+}
@@ -0,0 +1,248 @@
+//! ## S/Kademlia 节点 Id 的密码学难题
+//!
+//! #### 算法说明
+//! - 节点 Id 不能由节点任意选择，而是其公钥的哈希：NodeId = H(pub)，于是节点只能通过生成新的密钥对来更换 Id；
+//! - 静态难题(static puzzle)：H(NodeId) 须有 c1 个前导零 bit，生成一个合法 Id 平均需要 2^c1 次密钥生成，
+//!   由于 Id 由公钥唯一决定，攻击者既不能批量廉价地生成 Id(Sybil 攻击)，也不能挑选特定区间的 Id(Eclipse 攻击)；
+//! - 动态难题(dynamic puzzle)：找到 X 使 H(NodeId ⊕ X) 有 c2 个前导零 bit，平均需要 2^c2 次哈希；
+//!   难题与 Id 绑定，而 c2 可以随网络规模上调，已有节点只需重新求解 X 而无需更换 Id；
+//! - 验证两个难题各只需一次哈希，代价远低于求解；
+//! - 哈希函数 H 取 SHA-256，密钥对为 Ed25519，私钥种子按 seed' = H(seed) 依次派生，便于复现。
+//!
+//! #### 应用场景
+//! - 路由表只接纳通过验证的节点，提高生成大量 Id 或伪造靠近目标的 Id 的代价。
+//!
+//! #### 实现属性
+//! - <font color=Green>√</font> 多线程安全
+//! - <font color=Green>√</font> 无 unsafe 代码
+//!
+
+use super::super::kademlia::routing::{RoutingTable, Update};
+use super::super::peer::NodeId;
+pub use super::super::peer::PUBLIC_KEY_LEN;
+use ring::error::Unspecified;
+use ring::rand::{SecureRandom, SystemRandom};
+use ring::signature::{Ed25519KeyPair, KeyPair};
+
+///难题的难度，即要求的前导零 bit 数
+///- @c1: 静态难题的难度
+///- @c2: 动态难题的难度
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Difficulty {
+    pub c1: usize,
+    pub c2: usize,
+}
+
+impl Default for Difficulty {
+    fn default() -> Difficulty {
+        Difficulty { c1: 16, c2: 16 }
+    }
+}
+
+///节点 Id 的凭证：公钥与动态难题的解，任何节点都可据此验证 Id
+///- @public_key: Ed25519 公钥
+///- @x: 动态难题的解
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Proof {
+    pub public_key: [u8; PUBLIC_KEY_LEN],
+    pub x: NodeId,
+}
+
+impl Proof {
+    ///- #: 由公钥导出的节点 Id
+    pub fn id(&self) -> NodeId {
+        node_id(&self.public_key)
+    }
+
+    ///#### 验证静态难题与动态难题
+    ///- @difficulty[in]: 要求的难度
+    pub fn verify(&self, difficulty: Difficulty) -> bool {
+        let id = self.id();
+        verify_static(&id, difficulty.c1) && verify_dynamic(&id, &self.x, difficulty.c2)
+    }
+}
+
+///本节点的身份：密钥对、节点 Id 与动态难题的解
+//- @keypair: Ed25519 密钥对
+//- @proof: Id 的凭证
+#[derive(Debug)]
+pub struct Identity {
+    keypair: Ed25519KeyPair,
+    proof: Proof,
+}
+
+impl Identity {
+    ///#### 以系统随机数为起点，生成满足难度要求的身份
+    ///- @difficulty[in]: 难度
+    pub fn generate(difficulty: Difficulty) -> Result<Identity, Unspecified> {
+        let mut seed = [0; 32];
+        SystemRandom::new().fill(&mut seed)?;
+        Identity::from_seed(seed, difficulty)
+    }
+
+    ///#### 从指定的种子开始派生私钥，直至满足难度要求
+    ///- @seed[in]: 第一个私钥种子
+    ///- @difficulty[in]: 难度
+    pub fn from_seed(seed: [u8; 32], difficulty: Difficulty) -> Result<Identity, Unspecified> {
+        let mut seed = seed;
+        let keypair = loop {
+            let keypair = Ed25519KeyPair::from_seed_unchecked(untrusted::Input::from(&seed))
+                .map_err(|_| Unspecified)?;
+            if verify_static(&node_id(keypair.public_key().as_ref()), difficulty.c1) {
+                break keypair;
+            }
+            seed = NodeId::sha256(&seed).0;
+        };
+
+        let mut public_key = [0; PUBLIC_KEY_LEN];
+        public_key.copy_from_slice(keypair.public_key().as_ref());
+        let id = node_id(&public_key);
+        Ok(Identity {
+            keypair,
+            proof: Proof {
+                public_key,
+                x: solve_dynamic(&id, difficulty.c2),
+            },
+        })
+    }
+
+    #[inline(always)]
+    pub fn id(&self) -> NodeId {
+        self.proof.id()
+    }
+
+    #[inline(always)]
+    pub fn proof(&self) -> &Proof {
+        &self.proof
+    }
+
+    #[inline(always)]
+    pub fn keypair(&self) -> &Ed25519KeyPair {
+        &self.keypair
+    }
+
+    ///#### 以本节点的私钥签名
+    ///- #: Ed25519 签名
+    pub fn sign(&self, msg: &[u8]) -> [u8; 64] {
+        let mut res = [0; 64];
+        res.copy_from_slice(self.keypair.sign(msg).as_ref());
+        res
+    }
+
+    ///#### 动态难题的难度上调之后，重新求解，Id 不变
+    ///- @c2[in]: 新的难度
+    pub fn resolve(&mut self, c2: usize) {
+        self.proof.x = solve_dynamic(&self.id(), c2);
+    }
+}
+
+///- #: 由公钥导出的节点 Id，即 H(pub)
+pub fn node_id(public_key: &[u8]) -> NodeId {
+    NodeId::from_public_key(public_key)
+}
+
+///#### 验证静态难题：H(NodeId) 至少有 c1 个前导零 bit
+pub fn verify_static(id: &NodeId, c1: usize) -> bool {
+    c1 <= NodeId::sha256(id.as_bytes()).leading_zeros()
+}
+
+///#### 验证动态难题：H(NodeId ⊕ X) 至少有 c2 个前导零 bit
+pub fn verify_dynamic(id: &NodeId, x: &NodeId, c2: usize) -> bool {
+    c2 <= NodeId::sha256(id.distance(x).as_bytes()).leading_zeros()
+}
+
+///#### 求解动态难题，X 从 0 开始逐一尝试
+///- #: X
+///- @id[in]: 节点 Id
+///- @c2[in]: 难度
+pub fn solve_dynamic(id: &NodeId, c2: usize) -> NodeId {
+    let mut x = [0; 32];
+    for i in 0u64.. {
+        x[24..].copy_from_slice(&i.to_be_bytes());
+        if verify_dynamic(id, &NodeId::new(x), c2) {
+            break;
+        }
+    }
+    NodeId::new(x)
+}
+
+///#### 验证凭证之后再更新路由表，拒绝未求解难题的 Id
+///- #: 验证失败时返回 None
+///- @table[in]: 路由表
+///- @proof[in]: 对端的凭证
+///- @difficulty[in]: 要求的难度
+///- @now[in]: 当前时间
+pub fn admit(
+    table: &mut RoutingTable,
+    proof: &Proof,
+    difficulty: Difficulty,
+    now: u64,
+) -> Option<Update<32>> {
+    if proof.verify(difficulty) {
+        Some(table.update(&proof.id(), now))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn puzzle() {
+        let d = Difficulty { c1: 8, c2: 10 };
+        let ident = Identity::from_seed([7; 32], d).unwrap();
+        let proof = *ident.proof();
+        assert_eq!(ident.id(), node_id(ident.keypair().public_key().as_ref()));
+        assert!(8 <= NodeId::sha256(ident.id().as_bytes()).leading_zeros());
+        assert!(proof.verify(d));
+        assert!(proof.verify(Difficulty { c1: 0, c2: 0 }));
+
+        //same seed, same identity
+        assert_eq!(proof, *Identity::from_seed([7; 32], d).unwrap().proof());
+
+        //a random key pair almost never solves the static puzzle
+        let cheap = Identity::from_seed([7; 32], Difficulty { c1: 0, c2: 10 }).unwrap();
+        assert!(cheap.proof().verify(Difficulty { c1: 0, c2: 10 }));
+        assert!(!cheap.proof().verify(d));
+
+        //tampering with the key or the solution is detected
+        let mut forged = proof;
+        forged.public_key[0] ^= 1;
+        assert!(!forged.verify(d));
+        let mut forged = proof;
+        forged.x = forged.x.flip_bit(255);
+        assert!(!forged.verify(d));
+
+        //raising c2 keeps the id
+        let mut ident = ident;
+        let id = ident.id();
+        ident.resolve(14);
+        assert_eq!(id, ident.id());
+        assert!(ident.proof().verify(Difficulty { c1: 8, c2: 14 }));
+
+        let ident = Identity::generate(Difficulty { c1: 4, c2: 4 }).unwrap();
+        assert!(ident.proof().verify(Difficulty { c1: 4, c2: 4 }));
+    }
+
+    #[test]
+    fn admission() {
+        let d = Difficulty { c1: 6, c2: 6 };
+        let local = Identity::from_seed([1; 32], d).unwrap();
+        let mut table = RoutingTable::new(local.id(), 20, 0);
+
+        let honest = Identity::from_seed([2; 32], d).unwrap();
+        assert_eq!(
+            Some(Update::Inserted),
+            admit(&mut table, honest.proof(), d, 1)
+        );
+        assert!(table.contains(&honest.id()));
+
+        let sybil = Identity::from_seed([2; 32], Difficulty { c1: 0, c2: 0 }).unwrap();
+        assert!(sybil.id() != honest.id());
+        assert_eq!(None, admit(&mut table, sybil.proof(), d, 2));
+        assert!(!table.contains(&sybil.id()));
+        assert_eq!(1, table.len());
+    }
+}
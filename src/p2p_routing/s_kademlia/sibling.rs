@@ -0,0 +1,175 @@
+//! ## S/Kademlia sibling list
+//!
+//! #### 算法说明
+//! - 除 k-bucket 之外，每个节点另外维护距离自身最近的 η = s·k 个节点，称为 sibling list；
+//! - 一个 key 的数据由距离它最近的 s 个节点(siblings)共同保存，s·k 的容量保证：
+//!   只要 key 落在 sibling list 覆盖的区间内，本节点就能准确判断自己是否属于这 s 个节点，
+//!   以及其余的副本应当位于哪些节点上；
+//! - 写入数据时，负责的节点将 STORE 广播给其余 s - 1 个 siblings，
+//!   于是即使部分副本落在恶意节点上，其余诚实副本仍可响应查找。
+//!
+//! #### 实现属性
+//! - <font color=Green>√</font> 多线程安全
+//! - <font color=Green>√</font> 无 unsafe 代码
+//!
+
+use super::super::peer::NodeId;
+
+//- @local: 本节点 Id
+//- @s: 每个 key 的副本数
+//- @cap: 容量，即 s·k
+//- @nodes: 按与本节点的距离升序排列
+#[derive(Clone, Debug)]
+pub struct SiblingList<const N: usize = 32> {
+    local: NodeId<N>,
+    s: usize,
+    cap: usize,
+    nodes: Vec<NodeId<N>>,
+}
+
+impl<const N: usize> SiblingList<N> {
+    ///- @local[in]: 本节点 Id
+    ///- @s[in]: 每个 key 的副本数
+    ///- @k[in]: kademlia 的 k
+    pub fn new(local: NodeId<N>, s: usize, k: usize) -> SiblingList<N> {
+        assert!(0 < s && 0 < k, "s and k must be positive");
+        SiblingList {
+            local,
+            s,
+            cap: s * k,
+            nodes: vec![],
+        }
+    }
+
+    #[inline(always)]
+    pub fn s(&self) -> usize {
+        self.s
+    }
+
+    #[inline(always)]
+    pub fn capacity(&self) -> usize {
+        self.cap
+    }
+
+    #[inline(always)]
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    ///- #: 按与本节点的距离升序排列
+    #[inline(always)]
+    pub fn nodes(&self) -> &[NodeId<N>] {
+        &self.nodes
+    }
+
+    pub fn contains(&self, id: &NodeId<N>) -> bool {
+        self.position(id).is_ok()
+    }
+
+    fn position(&self, id: &NodeId<N>) -> Result<usize, usize> {
+        let d = self.local.distance(id);
+        self.nodes
+            .binary_search_by(|x| self.local.distance(x).cmp(&d))
+    }
+
+    ///#### 加入节点，已满时挤出最远的节点
+    ///- #: 节点加入时返回 true；节点已存在、为本节点或比全部 siblings 都远时返回 false
+    pub fn insert(&mut self, id: &NodeId<N>) -> bool {
+        if id == &self.local {
+            return false;
+        }
+        match self.position(id) {
+            Ok(_) => false,
+            Err(pos) if pos < self.cap => {
+                self.nodes.insert(pos, *id);
+                self.nodes.truncate(self.cap);
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
+    ///- #: 节点在列表中时返回 true
+    pub fn remove(&mut self, id: &NodeId<N>) -> bool {
+        match self.position(id) {
+            Ok(pos) => {
+                self.nodes.remove(pos);
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
+    ///#### siblings 中距离目标最近的若干节点
+    ///- #: 按与目标的距离升序排列
+    pub fn closest(&self, target: &NodeId<N>, count: usize) -> Vec<NodeId<N>> {
+        let mut res = self.nodes.clone();
+        res.sort_by_key(|id| id.distance(target));
+        res.truncate(count);
+        res
+    }
+
+    ///#### 保存 key 的 s 个副本的节点
+    ///- #: 本节点与 siblings 中距离 key 最近的 s 个，按距离升序排列
+    pub fn replicas(&self, key: &NodeId<N>) -> Vec<NodeId<N>> {
+        let mut res = self.closest(key, self.s);
+        res.push(self.local);
+        res.sort_by_key(|id| id.distance(key));
+        res.truncate(self.s);
+        res
+    }
+
+    ///- #: 本节点是否属于 key 的 s 个副本节点
+    pub fn is_responsible(&self, key: &NodeId<N>) -> bool {
+        self.replicas(key).contains(&self.local)
+    }
+
+    ///#### 负责 key 的节点写入数据之后，应将 STORE 广播给的其余副本节点
+    pub fn broadcast(&self, key: &NodeId<N>) -> Vec<NodeId<N>> {
+        let mut res = self.replicas(key);
+        res.retain(|id| id != &self.local);
+        res
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn id(n: u16) -> NodeId<2> {
+        NodeId::new(n.to_be_bytes())
+    }
+
+    #[test]
+    fn sibling() {
+        let mut sl = SiblingList::new(id(0x1000), 2, 2);
+        assert_eq!(4, sl.capacity());
+        assert!(!sl.insert(&id(0x1000)));
+        for n in [0x1001, 0x1002, 0x8000, 0x1004, 0x1008] {
+            assert!(sl.insert(&id(n)));
+        }
+        assert!(!sl.insert(&id(0x1001)));
+
+        //the farthest one is pushed out, a farther one is rejected
+        assert_eq!(
+            vec![id(0x1001), id(0x1002), id(0x1004), id(0x1008)],
+            sl.nodes().to_vec()
+        );
+        assert!(!sl.insert(&id(0x9000)));
+
+        assert_eq!(vec![id(0x1000), id(0x1001)], sl.replicas(&id(0x1000)));
+        assert!(sl.is_responsible(&id(0x1001)));
+        assert_eq!(vec![id(0x1004)], sl.broadcast(&id(0x1004)));
+        assert_eq!(vec![id(0x1008), id(0x1001)], sl.replicas(&id(0x1009)));
+        assert!(!sl.is_responsible(&id(0x1006)));
+
+        assert!(sl.remove(&id(0x1002)));
+        assert!(!sl.remove(&id(0x1002)));
+        assert_eq!(3, sl.len());
+    }
+}
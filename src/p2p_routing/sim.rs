@@ -0,0 +1,650 @@
+//! ## P2P 路由算法的进程内网络模拟器
+//!
+//! #### 算法说明
+//! - 离散事件模拟：全部消息投递、超时与节点上下线都是带时间戳的事件，由同一个调度器按 (时间, 序号) 顺序处理，
+//!   不涉及 socket 与系统时钟，相同的配置与种子必然得到相同的结果；
+//! - 节点只需实现 SimNode(处理请求)，查找只需实现 Query(poll/on_response 状态机)，
+//!   于是 kademlia、S/Kademlia、coral 等算法可以共用同一套模拟环境；
+//! - 网络模型：每条消息的延迟在 [min, max] 内均匀分布，以概率 loss 丢失；
+//!   发往离线节点的请求同样丢失，请求方只能通过超时得知；
+//! - 节点流失(churn)：每隔 interval，在线节点以概率 leave 下线，离线节点以概率 rejoin 重新上线(保留原有状态)；
+//! - 统计指标：查找次数、成功率、每次查找的跳数(请求链的最大深度，初始请求为第 1 跳)、消息数、丢包与超时次数。
+//!
+//! #### 应用场景
+//! - 在不同延迟、丢包率与流失率下比较各路由算法的查找成功率与开销。
+//!
+//! #### 实现属性
+//! - <font color=Green>√</font> 多线程安全
+//! - <font color=Green>√</font> 无 unsafe 代码
+//!
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Debug;
+
+///由模拟器驱动的查找状态机
+pub trait Query<Id, Req, Resp> {
+    ///#### 取出下一批请求
+    ///- #: (对端 Id, 请求)，无可发送的请求时为空
+    ///- @now[in]: 当前时间，即请求发出的时间
+    fn poll(&mut self, now: u64) -> Vec<(Id, Req)>;
+
+    ///#### 处理一个请求的结果
+    ///- @from[in]: 对端 Id
+    ///- @resp[in]: 响应，None 表示超时
+    fn on_response(&mut self, from: &Id, resp: Option<Resp>);
+
+    ///#### 查找是否已结束
+    fn is_finished(&self) -> bool;
+
+    ///#### 查找结束后，是否达成了目标
+    fn is_success(&self) -> bool;
+}
+
+///模拟网络中的节点
+pub trait SimNode {
+    type Id: Copy + Ord + Debug;
+    type Req: Clone + Debug;
+    type Resp: Clone + Debug;
+    type Query: Query<Self::Id, Self::Req, Self::Resp>;
+
+    fn id(&self) -> Self::Id;
+
+    ///#### 处理收到的请求
+    ///- @from[in]: 请求方 Id
+    ///- @req[in]: 请求
+    ///- @now[in]: 当前时间
+    fn handle(&mut self, from: &Self::Id, req: Self::Req, now: u64) -> Self::Resp;
+
+    ///#### 本节点发起的查找结束时调用，默认不做任何事
+    ///- @query[in]: 已结束的查找
+    ///- @now[in]: 当前时间
+    fn on_finished(&mut self, _query: &Self::Query, _now: u64) {}
+}
+
+///节点流失参数
+///- @interval: 检查间隔
+///- @leave: 每次检查时，在线节点下线的概率
+///- @rejoin: 每次检查时，离线节点重新上线的概率
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Churn {
+    pub interval: u64,
+    pub leave: f64,
+    pub rejoin: f64,
+}
+
+///网络参数，时间单位由调用方自行约定(通常为毫秒)
+///- @latency: 单条消息延迟的 [min, max]
+///- @loss: 单条消息的丢失概率
+///- @timeout: 请求的超时时间
+///- @churn: 节点流失参数，None 表示节点始终在线
+///- @seed: 随机数种子
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Config {
+    pub latency: (u64, u64),
+    pub loss: f64,
+    pub timeout: u64,
+    pub churn: Option<Churn>,
+    pub seed: u64,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            latency: (10, 100),
+            loss: 0.0,
+            timeout: 500,
+            churn: None,
+            seed: 1,
+        }
+    }
+}
+
+///统计指标
+///- @started: 发起的查找数
+///- @finished: 结束的查找数
+///- @succeeded: 达成目标的查找数
+///- @requests: 发出的请求数
+///- @responses: 发出的响应数
+///- @lost: 丢失的消息数，含发往离线节点的请求
+///- @timeouts: 超时的请求数
+///- @hops: 每次查找的跳数，按结束顺序排列
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Metrics {
+    pub started: usize,
+    pub finished: usize,
+    pub succeeded: usize,
+    pub requests: usize,
+    pub responses: usize,
+    pub lost: usize,
+    pub timeouts: usize,
+    pub hops: Vec<usize>,
+}
+
+impl Metrics {
+    ///- #: 消息总数
+    pub fn messages(&self) -> usize {
+        self.requests + self.responses
+    }
+
+    ///- #: 已结束的查找中达成目标的比例
+    pub fn success_rate(&self) -> f64 {
+        if 0 == self.finished {
+            return 0.0;
+        }
+        self.succeeded as f64 / self.finished as f64
+    }
+
+    ///- #: 平均跳数
+    pub fn mean_hops(&self) -> f64 {
+        if self.hops.is_empty() {
+            return 0.0;
+        }
+        self.hops.iter().sum::<usize>() as f64 / self.hops.len() as f64
+    }
+
+    ///- #: 最大跳数
+    pub fn max_hops(&self) -> usize {
+        self.hops.iter().max().cloned().unwrap_or(0)
+    }
+}
+
+///已结束的查找
+///- @origin: 发起查找的节点
+///- @query: 查找状态机，可从中取出结果
+///- @hops: 跳数
+///- @requests: 发出的请求数
+///- @elapsed: 耗时
+#[derive(Clone, Debug)]
+pub struct Finished<Id, Q> {
+    pub origin: Id,
+    pub query: Q,
+    pub hops: usize,
+    pub requests: usize,
+    pub elapsed: u64,
+}
+
+//- @origin: 发起查找的节点
+//- @query: 查找状态机
+//- @pending: 尚未得到结果的请求，(对端 Id, 所在跳数)
+//- @hops: 已得到响应的请求的最大跳数
+//- @requests: 发出的请求数
+//- @started: 发起时间
+struct Running<Id, Q> {
+    origin: Id,
+    query: Q,
+    pending: BTreeMap<Id, usize>,
+    hops: usize,
+    requests: usize,
+    started: u64,
+}
+
+enum Event<T: SimNode> {
+    Request {
+        qid: usize,
+        from: T::Id,
+        to: T::Id,
+        req: T::Req,
+    },
+    Response {
+        qid: usize,
+        from: T::Id,
+        resp: T::Resp,
+    },
+    Timeout {
+        qid: usize,
+        peer: T::Id,
+    },
+    Churn,
+}
+
+//- @cfg: 网络参数
+//- @now: 当前时间
+//- @seq: 事件序号，时间相同的事件按序号先后处理
+//- @events: 待处理的事件
+//- @nodes: 全部节点
+//- @offline: 离线节点
+//- @queries: 查找，下标即查找编号，结束后置为 None
+//- @running: 进行中的查找数
+//- @finished: 已结束、尚未被取走的查找
+//- @metrics: 统计指标
+//- @rng: xorshift 随机数状态
+pub struct Simulator<T: SimNode> {
+    cfg: Config,
+    now: u64,
+    seq: u64,
+    events: BTreeMap<(u64, u64), Event<T>>,
+    nodes: BTreeMap<T::Id, T>,
+    offline: BTreeSet<T::Id>,
+    queries: Vec<Option<Running<T::Id, T::Query>>>,
+    running: usize,
+    finished: Vec<Finished<T::Id, T::Query>>,
+    metrics: Metrics,
+    rng: u64,
+}
+
+impl<T: SimNode> Simulator<T> {
+    ///- @cfg[in]: 网络参数
+    pub fn new(cfg: Config) -> Simulator<T> {
+        let (min, max) = cfg.latency;
+        assert!(min <= max, "invalid latency range");
+        let mut res = Simulator {
+            cfg,
+            now: 0,
+            seq: 0,
+            events: BTreeMap::new(),
+            nodes: BTreeMap::new(),
+            offline: BTreeSet::new(),
+            queries: vec![],
+            running: 0,
+            finished: vec![],
+            metrics: Metrics::default(),
+            //xorshift 的状态不能为 0
+            rng: cfg.seed | 1,
+        };
+        if let Some(churn) = cfg.churn {
+            res.schedule(churn.interval, Event::Churn);
+        }
+        res
+    }
+
+    #[inline(always)]
+    pub fn now(&self) -> u64 {
+        self.now
+    }
+
+    #[inline(always)]
+    pub fn metrics(&self) -> &Metrics {
+        &self.metrics
+    }
+
+    pub fn node(&self, id: &T::Id) -> Option<&T> {
+        self.nodes.get(id)
+    }
+
+    pub fn node_mut(&mut self, id: &T::Id) -> Option<&mut T> {
+        self.nodes.get_mut(id)
+    }
+
+    ///- #: 全部节点，按 Id 升序排列
+    pub fn nodes(&self) -> impl Iterator<Item = &T> {
+        self.nodes.values()
+    }
+
+    ///- #: 在线节点的 Id，按升序排列
+    pub fn online(&self) -> Vec<T::Id> {
+        self.nodes
+            .keys()
+            .filter(|id| !self.offline.contains(id))
+            .cloned()
+            .collect()
+    }
+
+    pub fn is_online(&self, id: &T::Id) -> bool {
+        self.nodes.contains_key(id) && !self.offline.contains(id)
+    }
+
+    ///#### 加入一个在线节点，Id 已存在时替换原节点
+    pub fn add_node(&mut self, node: T) {
+        let id = node.id();
+        self.offline.remove(&id);
+        self.nodes.insert(id, node);
+    }
+
+    ///#### 设置节点的在线状态
+    pub fn set_online(&mut self, id: &T::Id, online: bool) {
+        if online {
+            self.offline.remove(id);
+        } else if self.nodes.contains_key(id) {
+            self.offline.insert(*id);
+        }
+    }
+
+    ///#### 由 origin 发起一次查找，首批请求立即发出
+    ///- #: 查找编号
+    ///- @origin[in]: 发起查找的节点
+    ///- @query[in]: 查找状态机
+    pub fn start(&mut self, origin: T::Id, query: T::Query) -> usize {
+        let qid = self.queries.len();
+        self.queries.push(Some(Running {
+            origin,
+            query,
+            pending: BTreeMap::new(),
+            hops: 0,
+            requests: 0,
+            started: self.now,
+        }));
+        self.running += 1;
+        self.metrics.started += 1;
+        self.dispatch(qid, 1);
+        qid
+    }
+
+    ///- #: 进行中的查找数
+    #[inline(always)]
+    pub fn running(&self) -> usize {
+        self.running
+    }
+
+    ///#### 取走已结束的查找，按结束顺序排列
+    pub fn take_finished(&mut self) -> Vec<Finished<T::Id, T::Query>> {
+        std::mem::take(&mut self.finished)
+    }
+
+    ///#### 处理事件，直至全部查找结束
+    pub fn run(&mut self) {
+        while 0 < self.running && self.step() {}
+    }
+
+    ///#### 处理时间不晚于 until 的全部事件，之后将当前时间推进到 until
+    pub fn run_until(&mut self, until: u64) {
+        while self
+            .events
+            .keys()
+            .next()
+            .is_some_and(|(at, _)| *at <= until)
+        {
+            self.step();
+        }
+        self.now = self.now.max(until);
+    }
+
+    //处理下一个事件
+    //- #: 无事件时返回 false
+    fn step(&mut self) -> bool {
+        let ((at, _), event) = match self.events.pop_first() {
+            Some(e) => e,
+            None => return false,
+        };
+        self.now = at;
+        match event {
+            Event::Request { qid, from, to, req } => self.on_request(qid, from, to, req),
+            Event::Response { qid, from, resp } => self.on_response(qid, from, Some(resp)),
+            Event::Timeout { qid, peer } => self.on_response(qid, peer, None),
+            Event::Churn => self.on_churn(),
+        }
+        true
+    }
+
+    fn schedule(&mut self, delay: u64, event: Event<T>) {
+        self.seq += 1;
+        self.events.insert((self.now + delay, self.seq), event);
+    }
+
+    fn next_rand(&mut self) -> u64 {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 7;
+        self.rng ^= self.rng << 17;
+        self.rng
+    }
+
+    //- #: 以概率 p 返回 true
+    fn chance(&mut self, p: f64) -> bool {
+        0.0 < p && ((self.next_rand() >> 11) as f64 / (1u64 << 53) as f64) < p
+    }
+
+    fn latency(&mut self) -> u64 {
+        let (min, max) = self.cfg.latency;
+        min + self.next_rand() % (max - min + 1)
+    }
+
+    //发出查找的下一批请求，查找已结束时将其移入 finished
+    //- @hop[in]: 这批请求所在的跳数
+    fn dispatch(&mut self, qid: usize, hop: usize) {
+        let (origin, reqs) = match self.queries[qid].as_mut() {
+            Some(q) => (q.origin, q.query.poll(self.now)),
+            None => return,
+        };
+
+        for (to, req) in reqs {
+            if let Some(q) = self.queries[qid].as_mut() {
+                q.pending.insert(to, hop);
+                q.requests += 1;
+            }
+            self.metrics.requests += 1;
+            if self.chance(self.cfg.loss) {
+                self.metrics.lost += 1;
+            } else {
+                let delay = self.latency();
+                self.schedule(
+                    delay,
+                    Event::Request {
+                        qid,
+                        from: origin,
+                        to,
+                        req,
+                    },
+                );
+            }
+            self.schedule(self.cfg.timeout, Event::Timeout { qid, peer: to });
+        }
+
+        let done = match self.queries[qid].as_ref() {
+            Some(q) => q.query.is_finished() || q.pending.is_empty(),
+            None => false,
+        };
+        if done {
+            self.finish(qid);
+        }
+    }
+
+    fn finish(&mut self, qid: usize) {
+        let q = match self.queries[qid].take() {
+            Some(q) => q,
+            None => return,
+        };
+        self.running -= 1;
+        self.metrics.finished += 1;
+        if q.query.is_success() {
+            self.metrics.succeeded += 1;
+        }
+        self.metrics.hops.push(q.hops);
+
+        let now = self.now;
+        if let Some(node) = self.nodes.get_mut(&q.origin) {
+            node.on_finished(&q.query, now);
+        }
+        self.finished.push(Finished {
+            origin: q.origin,
+            query: q.query,
+            hops: q.hops,
+            requests: q.requests,
+            elapsed: now - q.started,
+        });
+    }
+
+    fn on_request(&mut self, qid: usize, from: T::Id, to: T::Id, req: T::Req) {
+        let now = self.now;
+        let resp = match self.nodes.get_mut(&to) {
+            Some(node) if !self.offline.contains(&to) => node.handle(&from, req, now),
+            _ => {
+                self.metrics.lost += 1;
+                return;
+            }
+        };
+
+        self.metrics.responses += 1;
+        if self.chance(self.cfg.loss) {
+            self.metrics.lost += 1;
+            return;
+        }
+        let delay = self.latency();
+        self.schedule(
+            delay,
+            Event::Response {
+                qid,
+                from: to,
+                resp,
+            },
+        );
+    }
+
+    fn on_response(&mut self, qid: usize, from: T::Id, resp: Option<T::Resp>) {
+        let q = match self.queries[qid].as_mut() {
+            Some(q) => q,
+            None => return,
+        };
+        //重复的响应，或已得到响应之后的超时
+        let hop = match q.pending.remove(&from) {
+            Some(hop) => hop,
+            None => return,
+        };
+
+        //超时的请求由同一跳的其他节点替补
+        let next = if resp.is_some() {
+            q.hops = q.hops.max(hop);
+            hop + 1
+        } else {
+            self.metrics.timeouts += 1;
+            hop
+        };
+        q.query.on_response(&from, resp);
+        self.dispatch(qid, next);
+    }
+
+    fn on_churn(&mut self) {
+        let churn = match self.cfg.churn {
+            Some(churn) => churn,
+            None => return,
+        };
+        let ids = self.nodes.keys().cloned().collect::<Vec<_>>();
+        for id in ids {
+            if self.offline.contains(&id) {
+                if self.chance(churn.rejoin) {
+                    self.offline.remove(&id);
+                }
+            } else if self.chance(churn.leave) {
+                self.offline.insert(id);
+            }
+        }
+        self.schedule(churn.interval, Event::Churn);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::kademlia::lookup::{Request, ALPHA};
+    use super::super::kademlia::node::Node;
+    use super::super::peer::NodeId;
+    use super::super::testutil::{bootstrap, id};
+    use super::*;
+
+    const K: usize = 8;
+
+    fn build(n: usize, cfg: Config) -> (Simulator<Node>, Vec<NodeId>) {
+        let mut sim = Simulator::new(cfg);
+        let ids = bootstrap(n, K, |node, lookup| {
+            let id = *node.id();
+            sim.add_node(node);
+            if let Some(lookup) = lookup {
+                sim.start(id, lookup);
+                sim.run();
+            }
+        });
+        sim.take_finished();
+        (sim, ids)
+    }
+
+    #[test]
+    fn kademlia_lookup() {
+        let (mut sim, ids) = build(256, Config::default());
+        let joined = sim.metrics().clone();
+        assert_eq!(255, joined.finished);
+        assert_eq!(1.0, joined.success_rate());
+        assert_eq!(0, joined.lost + joined.timeouts);
+        assert_eq!(joined.requests, joined.responses);
+
+        for i in 0..50 {
+            let from = ids[(i * 5) % ids.len()];
+            let lookup = sim
+                .node(&from)
+                .unwrap()
+                .find_node(id(&format!("t{}", i)), ALPHA);
+            sim.start(from, lookup);
+        }
+        //lookups run concurrently
+        assert_eq!(50, sim.running());
+        sim.run();
+        assert_eq!(0, sim.running());
+
+        let finished = sim.take_finished();
+        assert_eq!(50, finished.len());
+        for f in finished.iter() {
+            let target = f.query.target();
+            let mut want = ids.clone();
+            want.retain(|x| *x != f.origin);
+            want.sort_by_key(|x| x.distance(target));
+            assert_eq!(want[..K].to_vec(), f.query.result().closest);
+            assert!(0 < f.hops && f.hops <= f.requests);
+            assert!(20 <= f.elapsed);
+        }
+
+        let m = sim.metrics();
+        assert_eq!(1.0, m.success_rate());
+        assert!(m.mean_hops() < 5.0, "{}", m.mean_hops());
+        assert!(m.max_hops() <= 8);
+        assert_eq!(
+            finished.iter().map(|f| f.requests).sum::<usize>(),
+            m.requests - joined.requests
+        );
+    }
+
+    //每隔 100 存入一个 value，之后从随机节点查找
+    //- #: (全部统计指标, 查找 value 的成功率)
+    fn workload(cfg: Config) -> (Metrics, f64) {
+        let (mut sim, ids) = build(128, cfg);
+        let keys = (0..20).map(|i| id(&format!("k{}", i))).collect::<Vec<_>>();
+        for key in keys.iter() {
+            let lookup = sim.node(&ids[0]).unwrap().find_node(*key, ALPHA);
+            sim.start(ids[0], lookup);
+            sim.run();
+            let res = sim.take_finished().pop().unwrap().query.result();
+            let now = sim.now();
+            for c in res.closest.iter() {
+                let node = sim.node_mut(c).unwrap();
+                node.handle(&ids[0], Request::Store(*key, b"v".to_vec()), now);
+            }
+            sim.run_until(now + 100);
+        }
+
+        for (i, key) in keys.iter().enumerate() {
+            let online = sim.online();
+            let from = online[(i * 11) % online.len()];
+            if let Some(lookup) = sim.node(&from).unwrap().find_value(*key, ALPHA) {
+                sim.start(from, lookup);
+            }
+        }
+        sim.run();
+        let finished = sim.take_finished();
+        assert!(!finished.is_empty());
+        let found = finished.iter().filter(|f| f.query.is_success()).count();
+        finished
+            .iter()
+            .filter(|f| f.query.is_success())
+            .for_each(|f| assert_eq!(Some(b"v".to_vec()), f.query.result().value));
+        (sim.metrics().clone(), found as f64 / finished.len() as f64)
+    }
+
+    #[test]
+    fn loss_churn() {
+        let cfg = Config {
+            loss: 0.05,
+            churn: Some(Churn {
+                interval: 1000,
+                leave: 0.02,
+                rejoin: 0.2,
+            }),
+            seed: 7,
+            ..Config::default()
+        };
+        let (m, found) = workload(cfg);
+        assert!(0 < m.lost && 0 < m.timeouts);
+        assert!(m.requests > m.responses);
+        //some joins fail when the only bootstrap request is lost
+        assert!((0.75..1.0).contains(&found), "{}", found);
+        assert_eq!(1.0, workload(Config::default()).1);
+
+        //same seed, same run
+        assert_eq!((m.clone(), found), workload(cfg));
+        assert_ne!(m, workload(Config { seed: 8, ..cfg }).0);
+    }
+}
@@ -0,0 +1,97 @@
+//! ## p2p routing 测试共用的工具
+//!
+//! #### 算法说明
+//! - id：以种子字符串的 SHA-256 作为节点 Id，各测试用同样的种子得到同样的网络；
+//! - bootstrap：每个节点经由第一个节点加入网络，并查找自身 Id，由调用方决定如何执行查找；
+//! - Net：同步的 kademlia 网络，可令节点离线，供 Transport 驱动的查找使用。
+//!
+
+use super::kademlia::lookup::{Lookup, Request, Response, Transport, ALPHA};
+use super::kademlia::node::Node;
+use super::peer::NodeId;
+use std::collections::{BTreeMap, HashSet};
+
+///- #: 种子字符串的 SHA-256
+pub fn id(seed: &str) -> NodeId {
+    NodeId::sha256(seed.as_bytes())
+}
+
+///- #: 以 0..n 为种子的 n 个 Id
+pub fn ids(n: usize) -> Vec<NodeId> {
+    (0..n).map(|i| id(&i.to_string())).collect()
+}
+
+///#### 每个节点经由第一个节点加入网络，并查找自身 Id
+///- #: 全部节点的 Id，第一个为引导节点
+///- @n[in]: 节点数
+///- @k[in]: 每个 bucket 最多保存的节点数
+///- @join[in]: 将新节点放入网络并执行其查找自身 Id 的 Lookup，引导节点没有 Lookup
+pub fn bootstrap(
+    n: usize,
+    k: usize,
+    mut join: impl FnMut(Node, Option<Lookup<32>>),
+) -> Vec<NodeId> {
+    let ids = ids(n);
+    join(Node::new(ids[0], k, 0), None);
+    for x in ids.iter().skip(1) {
+        let mut node = Node::new(*x, k, 0);
+        node.table_mut().update(&ids[0], 0);
+        let lookup = node.find_node(*x, ALPHA);
+        join(node, Some(lookup));
+    }
+    ids
+}
+
+///同步的 kademlia 网络
+///- @nodes: 全部节点
+///- @offline: 离线的节点，发往它们的请求超时
+///- @now: 当前时间
+#[derive(Default)]
+pub struct Net {
+    pub nodes: BTreeMap<NodeId, Node>,
+    pub offline: HashSet<NodeId>,
+    pub now: u64,
+}
+
+impl Transport<32> for Net {
+    fn call(&mut self, from: &NodeId, to: &NodeId, req: Request<32>) -> Option<Response<32>> {
+        let now = self.now;
+        if self.offline.contains(to) {
+            return None;
+        }
+        self.nodes.get_mut(to).map(|n| n.handle(from, req, now))
+    }
+}
+
+impl Net {
+    ///#### 构造 n 个节点的网络，节点依次加入，每加入一个时间前进 1
+    ///- @n[in]: 节点数
+    ///- @k[in]: 每个 bucket 最多保存的节点数
+    pub fn build(n: usize, k: usize) -> (Net, Vec<NodeId>) {
+        let mut net = Net::default();
+        let ids = bootstrap(n, k, |node, lookup| {
+            net.now += 1;
+            let id = *node.id();
+            net.nodes.insert(id, node);
+            if let Some(lookup) = lookup {
+                let res = lookup.run(&mut net);
+                let now = net.now;
+                net.nodes.get_mut(&id).unwrap().apply(&res, now);
+            }
+        });
+        (net, ids)
+    }
+
+    ///- #: 除 local 之外，距离 target 最近的 k 个在线节点
+    pub fn brute_force(&self, local: &NodeId, target: &NodeId, k: usize) -> Vec<NodeId> {
+        let mut res = self
+            .nodes
+            .keys()
+            .filter(|id| *id != local && !self.offline.contains(id))
+            .cloned()
+            .collect::<Vec<_>>();
+        res.sort_by_key(|id| id.distance(target));
+        res.truncate(k);
+        res
+    }
+}
@@ -0,0 +1,29 @@
+//! ## 测试共用的工具
+//!
+//! #### 算法说明
+//! - hex：十六进制字符串转为字节；
+//! - hash：比特币的显示形式(大端)转为内部字节序，即逆序；
+//! - h256：按原样解析 32 字节的哈希，用于以太坊等不逆序显示的哈希。
+//!
+
+///- #: 十六进制字符串对应的字节
+pub fn hex(s: &str) -> Vec<u8> {
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+        .collect()
+}
+
+///- #: 显示形式(大端)转为内部字节序
+pub fn hash(s: &str) -> [u8; 32] {
+    let mut res = h256(s);
+    res.reverse();
+    res
+}
+
+///- #: 按原样解析的 32 字节哈希
+pub fn h256(s: &str) -> [u8; 32] {
+    let mut res = [0; 32];
+    res.copy_from_slice(&hex(s));
+    res
+}